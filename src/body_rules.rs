@@ -0,0 +1,553 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::model_routing::match_wildcard;
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum BodyRuleOp {
+    /// Write `value`, replacing whatever is there.
+    Set,
+    /// Delete the field / matching array elements.
+    Remove,
+    /// Move the field to `to`.
+    Rename,
+    /// Write `value` only when the field is absent.
+    Default,
+    /// Lower a numeric field to at most `value`.
+    Cap,
+}
+
+/// A declarative rewrite of the request JSON, applied after an upstream is selected.
+///
+/// ```toml
+/// [[codex.configs.relay.body_rules]]
+/// op = "remove"
+/// path = "/tools/*"
+/// match = { type = "web_search_preview" }
+/// ```
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct BodyRule {
+    pub op: BodyRuleOp,
+    /// JSON pointer (RFC 6901); a `*` segment stands for every array element / object member.
+    pub path: String,
+    /// Value for `set` / `default`, upper bound for `cap`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub value: Option<Value>,
+    /// Destination pointer for `rename`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub to: Option<String>,
+    /// Only touch objects containing all of these key/value pairs.
+    #[serde(default, rename = "match", skip_serializing_if = "Option::is_none")]
+    pub matches: Option<Map<String, Value>>,
+    /// Only apply to these models (exact or `gpt-*` wildcard, after model mapping); empty = all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub models: Vec<String>,
+    /// Only apply when the request path ends with one of these, e.g. `/responses`; empty = all.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub paths: Vec<String>,
+}
+
+impl BodyRule {
+    /// Why this rule can never apply, for startup warnings.
+    pub fn problem(&self) -> Option<String> {
+        let segments = match parse_pointer(&self.path) {
+            Some(s) if !s.is_empty() => s,
+            _ => {
+                return Some(format!(
+                    "path '{}' is not a JSON pointer like /field",
+                    self.path
+                ));
+            }
+        };
+        match self.op {
+            BodyRuleOp::Set | BodyRuleOp::Default | BodyRuleOp::Cap if self.value.is_none() => {
+                Some("missing `value`".to_string())
+            }
+            BodyRuleOp::Cap if !self.value.as_ref().is_some_and(Value::is_number) => {
+                Some("`cap` needs a numeric `value`".to_string())
+            }
+            BodyRuleOp::Rename => match self.to.as_deref().and_then(parse_pointer) {
+                None => Some("`rename` needs a `to` pointer".to_string()),
+                Some(to) if segments.iter().chain(to.iter()).any(|s| s == "*") => {
+                    Some("`rename` does not support `*` segments".to_string())
+                }
+                Some(_) => None,
+            },
+            _ => None,
+        }
+    }
+
+    fn applies_to(&self, model: Option<&str>, request_path: &str) -> bool {
+        let model_ok = self.models.is_empty()
+            || model.is_some_and(|m| self.models.iter().any(|p| match_wildcard(p, m)));
+        let path_ok = self.paths.is_empty() || self.paths.iter().any(|p| request_path.ends_with(p));
+        model_ok && path_ok
+    }
+
+    fn matches(&self, v: &Value) -> bool {
+        self.matches.as_ref().is_none_or(|want| {
+            v.as_object()
+                .is_some_and(|o| want.iter().all(|(k, wv)| o.get(k) == Some(wv)))
+        })
+    }
+}
+
+/// Applies the rules that match `model` / `request_path`.
+///
+/// Returns the rewritten body plus one line per change, or `None` when nothing changed
+/// (including bodies that are not JSON).
+pub fn apply_body_rules<'a>(
+    body: &[u8],
+    rules: impl IntoIterator<Item = &'a BodyRule>,
+    model: Option<&str>,
+    request_path: &str,
+) -> Option<(Vec<u8>, Vec<String>)> {
+    let mut rules = rules
+        .into_iter()
+        .filter(|r| r.applies_to(model, request_path))
+        .peekable();
+    rules.peek()?;
+    let mut root: Value = serde_json::from_slice(body).ok()?;
+    let mut changes = Vec::new();
+    for rule in rules {
+        apply_rule(&mut root, rule, &mut changes);
+    }
+    if changes.is_empty() {
+        return None;
+    }
+    Some((serde_json::to_vec(&root).ok()?, changes))
+}
+
+fn parse_pointer(p: &str) -> Option<Vec<String>> {
+    let rest = p.strip_prefix('/')?;
+    Some(
+        rest.split('/')
+            .map(|s| s.replace("~1", "/").replace("~0", "~"))
+            .collect(),
+    )
+}
+
+fn escape_segment(s: &str) -> String {
+    s.replace('~', "~0").replace('/', "~1")
+}
+
+fn short(v: &Value) -> String {
+    let s = v.to_string();
+    if s.chars().count() <= 80 {
+        return s;
+    }
+    let mut out: String = s.chars().take(77).collect();
+    out.push_str("...");
+    out
+}
+
+fn apply_rule(root: &mut Value, rule: &BodyRule, changes: &mut Vec<String>) {
+    let Some(segments) = parse_pointer(&rule.path) else {
+        return;
+    };
+    let Some((last, parents)) = segments.split_last() else {
+        return;
+    };
+    if rule.op == BodyRuleOp::Rename {
+        rename(root, rule, parents, last, changes);
+        return;
+    }
+    // Missing intermediates are only worth creating when the leaf is then written: a `set` with
+    // `match` never matches a value that isn't there.
+    let create = rule.value.is_some()
+        && match rule.op {
+            BodyRuleOp::Default => true,
+            BodyRuleOp::Set => rule.matches.is_none(),
+            _ => false,
+        };
+    visit_parents(root, parents, create, "", &mut |parent, at| {
+        apply_leaf(parent, last, &at, rule, changes)
+    });
+}
+
+/// Calls `f` on every container reached by `parents`, expanding `*` segments.
+/// With `create`, missing intermediate objects are added below the last `*` (a missing object
+/// in front of a `*` would only be created to be iterated as empty).
+fn visit_parents(
+    node: &mut Value,
+    parents: &[String],
+    create: bool,
+    at: &str,
+    f: &mut dyn FnMut(&mut Value, String),
+) {
+    let Some((seg, rest)) = parents.split_first() else {
+        f(node, at.to_string());
+        return;
+    };
+    if seg == "*" {
+        match node {
+            Value::Array(items) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    visit_parents(item, rest, create, &format!("{at}/{i}"), f);
+                }
+            }
+            Value::Object(map) => {
+                for (k, v) in map.iter_mut() {
+                    let at = format!("{at}/{}", escape_segment(k));
+                    visit_parents(v, rest, create, &at, f);
+                }
+            }
+            _ => {}
+        }
+        return;
+    }
+    let child = match node {
+        Value::Object(map) if create && !rest.iter().any(|s| s == "*") => Some(
+            map.entry(seg.clone())
+                .or_insert_with(|| Value::Object(Map::new())),
+        ),
+        Value::Object(map) => map.get_mut(seg),
+        Value::Array(items) => seg.parse::<usize>().ok().and_then(|i| items.get_mut(i)),
+        _ => None,
+    };
+    if let Some(child) = child {
+        let at = format!("{at}/{}", escape_segment(seg));
+        visit_parents(child, rest, create, &at, f);
+    }
+}
+
+fn apply_leaf(parent: &mut Value, key: &str, at: &str, rule: &BodyRule, changes: &mut Vec<String>) {
+    if key == "*" {
+        let at = format!("{at}/*");
+        let removed = match parent {
+            Value::Array(items) if rule.op == BodyRuleOp::Remove => {
+                let before = items.len();
+                items.retain(|v| !rule.matches(v));
+                before - items.len()
+            }
+            Value::Object(map) if rule.op == BodyRuleOp::Remove => {
+                let before = map.len();
+                map.retain(|_, v| !rule.matches(v));
+                before - map.len()
+            }
+            Value::Array(items) => {
+                for (i, item) in items.iter_mut().enumerate() {
+                    rewrite_value(item, &format!("{at}/{i}"), rule, changes);
+                }
+                0
+            }
+            Value::Object(map) => {
+                for (k, v) in map.iter_mut() {
+                    rewrite_value(v, &format!("{at}/{}", escape_segment(k)), rule, changes);
+                }
+                0
+            }
+            _ => 0,
+        };
+        if removed > 0 {
+            changes.push(format!("remove {at} ({removed} item(s))"));
+        }
+        return;
+    }
+
+    let at = format!("{at}/{}", escape_segment(key));
+    match parent {
+        Value::Object(map) => match rule.op {
+            BodyRuleOp::Remove => {
+                if map.get(key).is_some_and(|v| rule.matches(v))
+                    && let Some(old) = map.remove(key)
+                {
+                    changes.push(format!("remove {at} (was {})", short(&old)));
+                }
+            }
+            BodyRuleOp::Default => {
+                if !map.contains_key(key)
+                    && let Some(value) = rule.value.clone()
+                {
+                    changes.push(format!("default {at} = {}", short(&value)));
+                    map.insert(key.to_string(), value);
+                }
+            }
+            _ => match map.get_mut(key) {
+                Some(v) => rewrite_value(v, &at, rule, changes),
+                None if rule.op == BodyRuleOp::Set && rule.matches.is_none() => {
+                    if let Some(value) = rule.value.clone() {
+                        changes.push(format!("set {at} = {}", short(&value)));
+                        map.insert(key.to_string(), value);
+                    }
+                }
+                None => {}
+            },
+        },
+        Value::Array(items) => {
+            let Some(idx) = key.parse::<usize>().ok().filter(|&i| i < items.len()) else {
+                return;
+            };
+            if rule.op == BodyRuleOp::Remove {
+                if rule.matches(&items[idx]) {
+                    let old = items.remove(idx);
+                    changes.push(format!("remove {at} (was {})", short(&old)));
+                }
+            } else {
+                rewrite_value(&mut items[idx], &at, rule, changes);
+            }
+        }
+        _ => {}
+    }
+}
+
+/// `set` / `cap` on an existing value.
+fn rewrite_value(v: &mut Value, at: &str, rule: &BodyRule, changes: &mut Vec<String>) {
+    let Some(value) = rule.value.as_ref() else {
+        return;
+    };
+    if !rule.matches(v) {
+        return;
+    }
+    match rule.op {
+        BodyRuleOp::Set if v != value => {
+            changes.push(format!("set {at}: {} -> {}", short(v), short(value)));
+            *v = value.clone();
+        }
+        BodyRuleOp::Cap => {
+            if let (Some(cur), Some(max)) = (v.as_f64(), value.as_f64())
+                && cur > max
+            {
+                changes.push(format!("cap {at}: {} -> {}", short(v), short(value)));
+                *v = value.clone();
+            }
+        }
+        _ => {}
+    }
+}
+
+fn rename(
+    root: &mut Value,
+    rule: &BodyRule,
+    parents: &[String],
+    last: &str,
+    changes: &mut Vec<String>,
+) {
+    let Some(to) = rule.to.as_deref().and_then(parse_pointer) else {
+        return;
+    };
+    let Some((to_last, to_parents)) = to.split_last() else {
+        return;
+    };
+    // Wildcards have no single destination, so renames only take concrete paths.
+    if last == "*" || parents.iter().any(|s| s == "*") || to.iter().any(|s| s == "*") {
+        return;
+    }
+    // Resolve the destination before taking the value, so it is never dropped on the floor.
+    if !can_insert(root, to_parents) {
+        return;
+    }
+    let mut taken = None;
+    visit_parents(root, parents, false, "", &mut |parent, _| {
+        if let Value::Object(map) = parent
+            && map.get(last).is_some_and(|v| rule.matches(v))
+        {
+            taken = map.remove(last);
+        }
+    });
+    let Some(value) = taken else {
+        return;
+    };
+    let mut value = Some(value);
+    visit_parents(root, to_parents, true, "", &mut |parent, _| {
+        if let Value::Object(map) = parent
+            && let Some(v) = value.take()
+        {
+            map.insert(to_last.clone(), v);
+        }
+    });
+    if value.is_none() {
+        changes.push(format!(
+            "rename {} -> {}",
+            rule.path,
+            rule.to.as_deref().unwrap_or_default()
+        ));
+    }
+}
+
+/// Whether `parents` (concrete segments) leads to an object, or to a missing key below which
+/// `visit_parents` can create one.
+fn can_insert(node: &Value, parents: &[String]) -> bool {
+    let Some((seg, rest)) = parents.split_first() else {
+        return node.is_object();
+    };
+    match node {
+        Value::Object(map) => map.get(seg).is_none_or(|child| can_insert(child, rest)),
+        Value::Array(items) => seg
+            .parse::<usize>()
+            .ok()
+            .and_then(|i| items.get(i))
+            .is_some_and(|child| can_insert(child, rest)),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rules(toml_src: &str) -> Vec<BodyRule> {
+        #[derive(Deserialize)]
+        struct Wrapper {
+            body_rules: Vec<BodyRule>,
+        }
+        toml::from_str::<Wrapper>(toml_src)
+            .expect("rules")
+            .body_rules
+    }
+
+    fn apply(body: Value, rules: &[BodyRule], model: &str) -> (Value, Vec<String>) {
+        let bytes = serde_json::to_vec(&body).unwrap();
+        match apply_body_rules(&bytes, rules, Some(model), "/v1/responses") {
+            Some((out, changes)) => (serde_json::from_slice(&out).unwrap(), changes),
+            None => (body, Vec::new()),
+        }
+    }
+
+    #[test]
+    fn applies_set_remove_rename_default_and_cap() {
+        let rules = rules(
+            r#"
+            [[body_rules]]
+            op = "remove"
+            path = "/parallel_tool_calls"
+
+            [[body_rules]]
+            op = "set"
+            path = "/store"
+            value = false
+
+            [[body_rules]]
+            op = "cap"
+            path = "/max_output_tokens"
+            value = 4096
+
+            [[body_rules]]
+            op = "default"
+            path = "/service_tier"
+            value = "flex"
+
+            [[body_rules]]
+            op = "rename"
+            path = "/reasoning/summary"
+            to = "/reasoning/summary_mode"
+
+            [[body_rules]]
+            op = "remove"
+            path = "/tools/*"
+            match = { type = "web_search_preview" }
+            "#,
+        );
+        let body = serde_json::json!({
+            "model": "gpt-5",
+            "parallel_tool_calls": true,
+            "store": true,
+            "max_output_tokens": 32000,
+            "reasoning": { "effort": "high", "summary": "auto" },
+            "tools": [
+                { "type": "function", "name": "shell" },
+                { "type": "web_search_preview" }
+            ]
+        });
+        let (out, changes) = apply(body, &rules, "gpt-5");
+        assert_eq!(
+            out,
+            serde_json::json!({
+                "model": "gpt-5",
+                "store": false,
+                "max_output_tokens": 4096,
+                "service_tier": "flex",
+                "reasoning": { "effort": "high", "summary_mode": "auto" },
+                "tools": [{ "type": "function", "name": "shell" }]
+            })
+        );
+        assert_eq!(changes.len(), 6, "{changes:?}");
+        assert!(changes.contains(&"set /store: true -> false".to_string()));
+        assert!(changes.contains(&"remove /tools/* (1 item(s))".to_string()));
+    }
+
+    #[test]
+    fn respects_model_and_path_conditions() {
+        let rules = rules(
+            r#"
+            [[body_rules]]
+            op = "set"
+            path = "/store"
+            value = false
+            models = ["gpt-4*"]
+
+            [[body_rules]]
+            op = "remove"
+            path = "/store"
+            paths = ["/chat/completions"]
+            "#,
+        );
+        let body = serde_json::json!({ "model": "gpt-5", "store": true });
+        let (out, changes) = apply(body.clone(), &rules, "gpt-5");
+        assert_eq!(out, body);
+        assert!(changes.is_empty());
+
+        let (out, _) = apply(body, &rules, "gpt-4o");
+        assert_eq!(out, serde_json::json!({ "model": "gpt-5", "store": false }));
+    }
+
+    #[test]
+    fn rename_keeps_value_when_destination_is_not_an_object() {
+        let rules = rules(
+            r#"
+            [[body_rules]]
+            op = "rename"
+            path = "/reasoning/summary"
+            to = "/model/summary"
+
+            [[body_rules]]
+            op = "rename"
+            path = "/user"
+            to = "/metadata/0/user"
+            "#,
+        );
+        let body = serde_json::json!({
+            "model": "gpt-5",
+            "reasoning": { "summary": "auto" },
+            "user": "u1",
+            "metadata": []
+        });
+        let (out, changes) = apply(body.clone(), &rules, "gpt-5");
+        assert_eq!(out, body);
+        assert!(changes.is_empty(), "{changes:?}");
+    }
+
+    #[test]
+    fn intermediate_objects_are_created_only_on_write() {
+        let rules = rules(
+            r#"
+            [[body_rules]]
+            op = "set"
+            path = "/response_format/json_schema"
+            value = { type = "text" }
+            match = { type = "json_object" }
+
+            [[body_rules]]
+            op = "default"
+            path = "/items/*/meta/source"
+            value = "proxy"
+
+            [[body_rules]]
+            op = "default"
+            path = "/text/verbosity"
+            value = "low"
+            "#,
+        );
+        let body = serde_json::json!({ "model": "gpt-5" });
+        let (out, changes) = apply(body, &rules, "gpt-5");
+        assert_eq!(
+            out,
+            serde_json::json!({ "model": "gpt-5", "text": { "verbosity": "low" } })
+        );
+        assert_eq!(
+            changes,
+            vec!["default /text/verbosity = \"low\"".to_string()]
+        );
+    }
+}
@@ -0,0 +1,157 @@
+//! 配置级预算：按 UTC 自然日统计 token、按 UTC 自然月统计估算费用；超出后该配置退出自动路由，
+//! 直到周期滚动。
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::BudgetConfig;
+
+/// Days since the Unix epoch (UTC) for a millisecond timestamp.
+pub fn day_index(ms: u64) -> i32 {
+    (ms / 86_400_000) as i32
+}
+
+/// `(year, month, day)` of a day index (days since the epoch, UTC).
+fn civil_from_days(day: i32) -> (i64, u32, u32) {
+    // Civil-from-days (Howard Hinnant).
+    let z = day as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let d = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let m = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let y = yoe + era * 400 + if m <= 2 { 1 } else { 0 };
+    (y, m, d)
+}
+
+/// First day (days since the epoch) of the UTC calendar month containing `day`.
+pub fn month_start_day(day: i32) -> i32 {
+    let (_, _, day_of_month) = civil_from_days(day);
+    day - (day_of_month as i32 - 1)
+}
+
+/// `YYYY-MM-DD` for a day index.
+pub fn format_day(day: i32) -> String {
+    let (y, m, d) = civil_from_days(day);
+    format!("{y:04}-{m:02}-{d:02}")
+}
+
+/// Budget usage of one config in the current periods.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct BudgetStatus {
+    pub config_name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_tokens: Option<u64>,
+    #[serde(default)]
+    pub tokens_today: i64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_cost_usd: Option<f64>,
+    /// `None` until a USD-priced request is seen this month, so the cost limit cannot be checked.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cost_this_month_usd: Option<f64>,
+    /// The limit that was reached (`daily_tokens` / `monthly_cost_usd`), if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub exceeded: Option<String>,
+}
+
+impl BudgetStatus {
+    pub fn evaluate(
+        config_name: &str,
+        budget: &BudgetConfig,
+        tokens_today: i64,
+        cost_this_month_usd: Option<f64>,
+    ) -> Self {
+        let tokens_over = budget
+            .daily_tokens
+            .is_some_and(|limit| tokens_today >= limit as i64);
+        let cost_over = budget
+            .monthly_cost_usd
+            .zip(cost_this_month_usd)
+            .is_some_and(|(limit, spent)| spent >= limit);
+        let exceeded = if tokens_over {
+            Some("daily_tokens")
+        } else if cost_over {
+            Some("monthly_cost_usd")
+        } else {
+            None
+        };
+        Self {
+            config_name: config_name.to_string(),
+            daily_tokens: budget.daily_tokens,
+            tokens_today,
+            monthly_cost_usd: budget.monthly_cost_usd,
+            cost_this_month_usd,
+            exceeded: exceeded.map(str::to_string),
+        }
+    }
+
+    pub fn tokens_left(&self) -> Option<i64> {
+        self.daily_tokens
+            .map(|limit| (limit as i64).saturating_sub(self.tokens_today).max(0))
+    }
+
+    pub fn cost_left_usd(&self) -> Option<f64> {
+        let spent = self.cost_this_month_usd?;
+        self.monthly_cost_usd.map(|limit| (limit - spent).max(0.0))
+    }
+
+    /// One-line summary such as `tokens 12000/50000 today (38000 left), $3.20/$20.00 this month
+    /// ($16.80 left)`.
+    pub fn summary(&self) -> String {
+        let mut parts = Vec::new();
+        if let (Some(limit), Some(left)) = (self.daily_tokens, self.tokens_left()) {
+            parts.push(format!(
+                "tokens {}/{} today ({} left)",
+                self.tokens_today, limit, left
+            ));
+        }
+        if let Some(limit) = self.monthly_cost_usd {
+            match (self.cost_this_month_usd, self.cost_left_usd()) {
+                (Some(spent), Some(left)) => parts.push(format!(
+                    "${spent:.2}/${limit:.2} this month (${left:.2} left)"
+                )),
+                _ => parts.push(format!("$?/${limit:.2} this month (no priced usage)")),
+            }
+        }
+        parts.join(", ")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn month_start_and_budget_evaluation() {
+        // 2024-03-15 -> 2024-03-01; 2024-01-01 is its own month start; 2024-02-29 (leap day).
+        assert_eq!(month_start_day(19_797), 19_783);
+        assert_eq!(month_start_day(19_723), 19_723);
+        assert_eq!(month_start_day(19_782), 19_754);
+        assert_eq!(day_index(1_710_460_800_000), 19_797);
+        assert_eq!(format_day(19_797), "2024-03-15");
+        assert_eq!(format_day(19_782), "2024-02-29");
+        assert_eq!(format_day(0), "1970-01-01");
+
+        let budget = BudgetConfig {
+            daily_tokens: Some(1_000),
+            monthly_cost_usd: Some(5.0),
+        };
+        let ok = BudgetStatus::evaluate("relay", &budget, 400, Some(1.25));
+        assert_eq!(ok.exceeded, None);
+        assert_eq!(ok.tokens_left(), Some(600));
+        assert_eq!(ok.cost_left_usd(), Some(3.75));
+        assert_eq!(
+            ok.summary(),
+            "tokens 400/1000 today (600 left), $1.25/$5.00 this month ($3.75 left)"
+        );
+
+        let tokens = BudgetStatus::evaluate("relay", &budget, 1_000, None);
+        assert_eq!(tokens.exceeded.as_deref(), Some("daily_tokens"));
+        assert_eq!(tokens.tokens_left(), Some(0));
+        assert_eq!(tokens.cost_left_usd(), None);
+
+        let cost = BudgetStatus::evaluate("relay", &budget, 10, Some(5.5));
+        assert_eq!(cost.exceeded.as_deref(), Some("monthly_cost_usd"));
+    }
+}
@@ -0,0 +1,203 @@
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, bail};
+use ring::digest::{SHA256, digest};
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::proxy_home_dir;
+
+const REGISTRY_FILE: &str = "client_keys.json";
+const KEY_PREFIX: &str = "chk_";
+
+/// A proxy-issued key for one team member or tool. Only the SHA-256 of the key is stored.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ClientKey {
+    pub id: String,
+    pub label: String,
+    pub key_sha256: String,
+    /// Configs this key may route to; empty means all configs.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_configs: Vec<String>,
+    /// Total tokens per UTC day; requests are rejected once it is spent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_token_budget: Option<u64>,
+    pub created_at_ms: u64,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub revoked_at_ms: Option<u64>,
+}
+
+impl ClientKey {
+    pub fn is_active(&self) -> bool {
+        self.revoked_at_ms.is_none()
+    }
+}
+
+/// Who sent a request, attached to the request by the access layer once its key is verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientIdentity {
+    pub label: String,
+    pub allowed_configs: Vec<String>,
+    pub daily_token_budget: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ClientKeyRegistry {
+    #[serde(default)]
+    pub keys: Vec<ClientKey>,
+}
+
+pub fn registry_path() -> PathBuf {
+    proxy_home_dir().join(REGISTRY_FILE)
+}
+
+fn hash_key(key: &str) -> String {
+    digest(&SHA256, key.as_bytes())
+        .as_ref()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+fn random_hex(rng: &SystemRandom, bytes: usize) -> Result<String> {
+    let mut buf = vec![0u8; bytes];
+    rng.fill(&mut buf)
+        .map_err(|_| anyhow::anyhow!("system random generator failed"))?;
+    Ok(buf.iter().map(|b| format!("{b:02x}")).collect())
+}
+
+impl ClientKeyRegistry {
+    pub fn load_from(path: &std::path::Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let bytes = std::fs::read(path).with_context(|| format!("read {:?}", path))?;
+        serde_json::from_slice(&bytes).with_context(|| format!("parse {:?}", path))
+    }
+
+    pub fn load() -> Result<Self> {
+        Self::load_from(&registry_path())
+    }
+
+    pub fn save(&self) -> Result<()> {
+        crate::secrets::write_private(&registry_path(), &serde_json::to_vec_pretty(self)?)
+    }
+
+    pub fn has_active_keys(&self) -> bool {
+        self.keys.iter().any(ClientKey::is_active)
+    }
+
+    /// Creates a key and returns it with its plaintext value, which is not stored anywhere.
+    pub fn create(
+        &mut self,
+        label: &str,
+        allowed_configs: Vec<String>,
+        daily_token_budget: Option<u64>,
+        now_ms: u64,
+    ) -> Result<(ClientKey, String)> {
+        let label = label.trim();
+        if label.is_empty() {
+            bail!("label must not be empty");
+        }
+        if self.keys.iter().any(|k| k.is_active() && k.label == label) {
+            bail!("an active key labelled '{label}' already exists");
+        }
+        let rng = SystemRandom::new();
+        let plaintext = format!("{KEY_PREFIX}{}", random_hex(&rng, 24)?);
+        let key = ClientKey {
+            id: random_hex(&rng, 4)?,
+            label: label.to_string(),
+            key_sha256: hash_key(&plaintext),
+            allowed_configs,
+            daily_token_budget,
+            created_at_ms: now_ms,
+            revoked_at_ms: None,
+        };
+        self.keys.push(key.clone());
+        Ok((key, plaintext))
+    }
+
+    /// Revokes by id or label; returns the revoked key.
+    pub fn revoke(&mut self, id_or_label: &str, now_ms: u64) -> Option<&ClientKey> {
+        let key = self
+            .keys
+            .iter_mut()
+            .find(|k| k.is_active() && (k.id == id_or_label || k.label == id_or_label))?;
+        key.revoked_at_ms = Some(now_ms);
+        Some(key)
+    }
+
+    pub fn find_by_token(&self, token: &str) -> Option<&ClientKey> {
+        let hash = hash_key(token.trim());
+        self.keys
+            .iter()
+            .find(|k| k.is_active() && k.key_sha256 == hash)
+    }
+}
+
+struct CachedRegistry {
+    modified: Option<SystemTime>,
+    registry: Arc<ClientKeyRegistry>,
+}
+
+/// The registry as seen by the running proxy, re-read whenever the file's mtime changes
+/// so `ch keys create/revoke` apply without a restart.
+pub fn current_registry() -> Arc<ClientKeyRegistry> {
+    static CACHE: OnceLock<Mutex<Option<CachedRegistry>>> = OnceLock::new();
+    let path = registry_path();
+    let modified = std::fs::metadata(&path).and_then(|m| m.modified()).ok();
+    let mut guard = match CACHE.get_or_init(|| Mutex::new(None)).lock() {
+        Ok(g) => g,
+        Err(e) => e.into_inner(),
+    };
+    if let Some(cached) = guard.as_ref()
+        && cached.modified == modified
+    {
+        return cached.registry.clone();
+    }
+    let registry = match ClientKeyRegistry::load_from(&path) {
+        Ok(r) => r,
+        Err(e) => {
+            // Keep serving with the last good registry rather than locking everyone out.
+            warn!("failed to load client key registry {:?}: {:#}", path, e);
+            guard
+                .as_ref()
+                .map(|c| (*c.registry).clone())
+                .unwrap_or_default()
+        }
+    };
+    let registry = Arc::new(registry);
+    *guard = Some(CachedRegistry {
+        modified,
+        registry: registry.clone(),
+    });
+    registry
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn created_keys_are_found_by_token_until_revoked() {
+        let mut reg = ClientKeyRegistry::default();
+        let (key, plaintext) = reg
+            .create("alice", vec!["relay".to_string()], Some(1000), 1)
+            .expect("create");
+        assert!(plaintext.starts_with(KEY_PREFIX));
+        assert_ne!(key.key_sha256, plaintext);
+        assert!(reg.create("alice", Vec::new(), None, 2).is_err());
+
+        assert_eq!(reg.find_by_token(&plaintext).map(|k| &k.id), Some(&key.id));
+        assert!(reg.find_by_token("chk_wrong").is_none());
+
+        assert!(reg.revoke("alice", 3).is_some());
+        assert!(reg.find_by_token(&plaintext).is_none());
+        assert!(!reg.has_active_keys());
+        // The label can be reused once the old key is revoked.
+        assert!(reg.create("alice", Vec::new(), None, 4).is_ok());
+    }
+}
@@ -7,6 +7,9 @@ use anyhow::{Context, Result, anyhow};
 use dirs::home_dir;
 use toml::Value;
 
+use crate::config::ServerConfig;
+use crate::proxy::CLIENT_TOKEN_HEADER;
+
 const ABSENT_BACKUP_SENTINEL: &str = "# codex-helper-backup:absent";
 
 fn codex_home() -> PathBuf {
@@ -52,8 +55,45 @@ fn atomic_write(path: &PathBuf, data: &str) -> Result<()> {
     Ok(())
 }
 
+/// Points the `codex_proxy` provider's headers at the proxy client token (if one is configured).
+///
+/// An env-based token is written as `env_http_headers` so the secret stays out of Codex's config;
+/// stale entries from a previous switch-on are always removed first.
+fn apply_client_token_headers(proxy_table: &mut toml::Table, server: &ServerConfig) {
+    for key in ["http_headers", "env_http_headers"] {
+        if let Some(headers) = proxy_table.get_mut(key).and_then(|v| v.as_table_mut()) {
+            headers.remove(CLIENT_TOKEN_HEADER);
+            if headers.is_empty() {
+                proxy_table.remove(key);
+            }
+        }
+    }
+
+    let (key, value) = match (
+        server
+            .client_token
+            .as_deref()
+            .filter(|t| !t.trim().is_empty()),
+        server
+            .client_token_env
+            .as_deref()
+            .filter(|e| !e.trim().is_empty()),
+    ) {
+        (Some(token), _) => ("http_headers", token),
+        (None, Some(env_name)) => ("env_http_headers", env_name),
+        (None, None) => return,
+    };
+    if let Some(headers) = proxy_table
+        .entry(key)
+        .or_insert_with(|| Value::Table(toml::Table::new()))
+        .as_table_mut()
+    {
+        headers.insert(CLIENT_TOKEN_HEADER.into(), Value::String(value.to_string()));
+    }
+}
+
 /// Switch Codex to use the local codex-helper model provider.
-pub fn switch_on(port: u16) -> Result<()> {
+pub fn switch_on(port: u16, server: &ServerConfig) -> Result<()> {
     let cfg_path = codex_config_path();
     let backup_path = codex_config_backup_path();
 
@@ -86,7 +126,7 @@ pub fn switch_on(port: u16) -> Result<()> {
         .as_table_mut()
         .ok_or_else(|| anyhow!("model_providers must be a table"))?;
 
-    let base_url = format!("http://127.0.0.1:{}", port);
+    let base_url = server.local_base_url(port);
     let mut proxy_table = providers_table
         .get("codex_proxy")
         .and_then(|v| v.as_table())
@@ -99,6 +139,7 @@ pub fn switch_on(port: u16) -> Result<()> {
     proxy_table
         .entry("request_max_retries")
         .or_insert(Value::Integer(0));
+    apply_client_token_headers(&mut proxy_table, server);
 
     providers_table.insert("codex_proxy".into(), Value::Table(proxy_table));
     table.insert("model_provider".into(), Value::String("codex_proxy".into()));
@@ -288,7 +329,7 @@ fn read_settings_text(path: &Path) -> Result<String> {
 }
 
 /// 将 Claude Code 的 settings.json 指向本地 codex-helper 代理（实验性）。
-pub fn claude_switch_on(port: u16) -> Result<()> {
+pub fn claude_switch_on(port: u16, server: &ServerConfig) -> Result<()> {
     let settings_path = claude_settings_path();
     let backup_path = claude_settings_backup_path(&settings_path);
 
@@ -327,12 +368,37 @@ pub fn claude_switch_on(port: u16) -> Result<()> {
         .as_object_mut()
         .ok_or_else(|| anyhow!("Claude settings env must be an object"))?;
 
-    let base_url = format!("http://127.0.0.1:{}", port);
+    let base_url = server.local_base_url(port);
     env_obj.insert(
         "ANTHROPIC_BASE_URL".to_string(),
         serde_json::Value::String(base_url),
     );
 
+    // Claude Code reads extra request headers from ANTHROPIC_CUSTOM_HEADERS ("Name: Value" per line).
+    let mut custom_headers: Vec<String> = env_obj
+        .get("ANTHROPIC_CUSTOM_HEADERS")
+        .and_then(|v| v.as_str())
+        .unwrap_or_default()
+        .lines()
+        .filter(|line| {
+            !line
+                .split_once(':')
+                .is_some_and(|(name, _)| name.trim().eq_ignore_ascii_case(CLIENT_TOKEN_HEADER))
+        })
+        .map(str::to_string)
+        .collect();
+    if let Some(token) = server.resolve_client_token() {
+        custom_headers.push(format!("{CLIENT_TOKEN_HEADER}: {token}"));
+    }
+    if custom_headers.is_empty() {
+        env_obj.remove("ANTHROPIC_CUSTOM_HEADERS");
+    } else {
+        env_obj.insert(
+            "ANTHROPIC_CUSTOM_HEADERS".to_string(),
+            serde_json::Value::String(custom_headers.join("\n")),
+        );
+    }
+
     let new_text = serde_json::to_string_pretty(&value)?;
     if let Some(parent) = settings_path.parent() {
         fs::create_dir_all(parent).with_context(|| format!("create_dir_all {:?}", parent))?;
@@ -122,17 +122,33 @@ pub async fn handle_config_cmd(cmd: ConfigCommand) -> CliResult<()> {
                     auth_token_env,
                     api_key,
                     api_key_env,
+                    auth_token_file: None,
+                    auth_token_cmd: Default::default(),
+                    api_key_file: None,
+                    api_key_cmd: Default::default(),
+                    cmd_cache_ttl_secs: None,
+                    auth_token_secret: None,
+                    api_key_secret: None,
+                    auth_tokens: Default::default(),
+                    auth_token_envs: Default::default(),
+                    auth_token_secrets: Default::default(),
+                    key_rotation: None,
                 },
                 tags: Default::default(),
                 supported_models: Default::default(),
                 model_mapping: Default::default(),
+                resolve: Default::default(),
+                ip_preference: None,
+                body_rules: Default::default(),
             };
             let service_cfg = ServiceConfig {
                 name: name.clone(),
                 alias,
                 enabled: !disabled,
                 level: level.clamp(1, 10),
+                budget: Default::default(),
                 upstreams: vec![upstream],
+                body_rules: Default::default(),
             };
 
             if service == "claude" {
@@ -1,6 +1,8 @@
 use crate::CliResult;
+use crate::budget::BudgetStatus;
 use crate::config::{
-    codex_auth_path, codex_config_path, load_config, probe_codex_bootstrap_from_cli, proxy_home_dir,
+    BudgetConfig, codex_auth_path, codex_config_path, load_config, probe_codex_bootstrap_from_cli,
+    proxy_home_dir,
 };
 use owo_colors::OwoColorize;
 use serde::Serialize;
@@ -9,6 +11,7 @@ use std::env;
 use std::fs::File;
 use std::io::BufReader;
 use std::path::PathBuf;
+use std::time::Duration;
 
 #[derive(Debug, Serialize)]
 struct StatusJson<'a> {
@@ -19,10 +22,77 @@ struct StatusJson<'a> {
     claude: &'a crate::config::ServiceConfigManager,
     lb_failure_threshold: u32,
     lb_cooldown_secs: u64,
+    /// Live budget usage from the running proxy; absent when it is not reachable.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    codex_budgets: Option<Vec<BudgetStatus>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    claude_budgets: Option<Vec<BudgetStatus>>,
+}
+
+/// Asks a locally running proxy for its budget usage. `None` when no proxy answers.
+async fn fetch_budgets(
+    cfg: &crate::config::ProxyConfig,
+    mgr: &crate::config::ServiceConfigManager,
+    port: u16,
+) -> Option<Vec<BudgetStatus>> {
+    if mgr.configs.values().all(|svc| svc.budget.is_empty()) {
+        return None;
+    }
+    let url = format!(
+        "{}/__codex_helper/status/budgets",
+        cfg.server.local_base_url(port)
+    );
+    let client = reqwest::Client::builder()
+        .timeout(Duration::from_millis(800))
+        .build()
+        .ok()?;
+    let mut req = client.get(url);
+    if let Some(token) = cfg.server.resolve_admin_token() {
+        req = req.header(crate::proxy::ADMIN_TOKEN_HEADER, token);
+    }
+    let resp = req.send().await.ok()?.error_for_status().ok()?;
+    resp.json::<Vec<BudgetStatus>>().await.ok()
+}
+
+fn print_budget(budget: &BudgetConfig, live: Option<&[BudgetStatus]>, name: &str) {
+    if budget.is_empty() {
+        return;
+    }
+    match live.and_then(|l| l.iter().find(|b| b.config_name == name)) {
+        Some(status) => {
+            let line = format!("budget: {}", status.summary());
+            match status.exceeded.as_deref() {
+                Some(limit) => println!(
+                    "      {} {}",
+                    line.yellow(),
+                    format!("(over {limit}; skipped by automatic routing)").red()
+                ),
+                None => println!("      {line}"),
+            }
+        }
+        None => {
+            let mut parts = Vec::new();
+            if let Some(tokens) = budget.daily_tokens {
+                parts.push(format!("{tokens} tokens/day"));
+            }
+            if let Some(usd) = budget.monthly_cost_usd {
+                parts.push(format!("${usd:.2}/month"));
+            }
+            println!(
+                "      budget: {} {}",
+                parts.join(", "),
+                "(proxy not running; usage unknown)".dimmed()
+            );
+        }
+    }
 }
 
 pub async fn handle_status_cmd(json: bool) -> CliResult<()> {
     let cfg = load_config().await?;
+    let (codex_budgets, claude_budgets) = tokio::join!(
+        fetch_budgets(&cfg, &cfg.codex, 3211),
+        fetch_budgets(&cfg, &cfg.claude, 3210),
+    );
 
     if json {
         let payload = StatusJson {
@@ -31,6 +101,8 @@ pub async fn handle_status_cmd(json: bool) -> CliResult<()> {
             claude: &cfg.claude,
             lb_failure_threshold: crate::lb::FAILURE_THRESHOLD,
             lb_cooldown_secs: crate::lb::COOLDOWN_SECS,
+            codex_budgets,
+            claude_budgets,
         };
         let text = serde_json::to_string_pretty(&payload).unwrap_or_else(|_| "{}".to_string());
         println!("{text}");
@@ -68,6 +140,7 @@ pub async fn handle_status_cmd(json: bool) -> CliResult<()> {
                     println!("      [{}] {} ({})", idx, up.base_url, role);
                 }
             }
+            print_budget(&svc.budget, codex_budgets.as_deref(), name);
         }
         println!(
             "  {}",
@@ -103,6 +176,7 @@ pub async fn handle_status_cmd(json: bool) -> CliResult<()> {
                 .map(|u| u.base_url.as_str())
                 .unwrap_or("<no upstream>");
             println!("  {} {} -> {}", marker, name, base_url);
+            print_budget(&svc.budget, claude_budgets.as_deref(), name);
         }
     }
 
@@ -206,6 +280,57 @@ pub async fn handle_doctor_cmd(json: bool) -> CliResult<()> {
                             message: msg,
                         });
                     }
+                    for path in [
+                        up.auth.auth_token_file.as_deref(),
+                        up.auth.api_key_file.as_deref(),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    {
+                        if crate::credentials::read_secret_file(path).is_some() {
+                            continue;
+                        }
+                        let msg = format!(
+                            "{} active config '{}' upstream[{}] 密钥文件 {} 不存在或为空",
+                            svc_label, active_name, idx, path
+                        );
+                        if !json {
+                            println!("{} {}", "[WARN]".yellow(), msg);
+                        }
+                        checks.push(DoctorCheck {
+                            id: "proxy_config.auth.file_missing",
+                            status: "warn",
+                            message: msg,
+                        });
+                    }
+                    for name in [
+                        up.auth.auth_token_secret.as_deref(),
+                        up.auth.api_key_secret.as_deref(),
+                    ]
+                    .into_iter()
+                    .flatten()
+                    {
+                        if crate::secrets::get_secret(name).is_some() {
+                            continue;
+                        }
+                        let msg = format!(
+                            "{} active config '{}' upstream[{}] 加密存储中找不到密钥 '{}'（可用 `ch secrets set {}` 写入；口令模式需设置 {}）",
+                            svc_label,
+                            active_name,
+                            idx,
+                            name,
+                            name,
+                            crate::secrets::PASSPHRASE_ENV
+                        );
+                        if !json {
+                            println!("{} {}", "[WARN]".yellow(), msg);
+                        }
+                        checks.push(DoctorCheck {
+                            id: "proxy_config.auth.secret_missing",
+                            status: "warn",
+                            message: msg,
+                        });
+                    }
                     if up
                         .auth
                         .auth_token
@@ -451,7 +576,7 @@ pub async fn handle_doctor_cmd(json: bool) -> CliResult<()> {
     }
 
     // 4) 检查请求日志与 usage_providers 配置
-    let log_path: PathBuf = proxy_home_dir().join("logs").join("requests.jsonl");
+    let log_path: PathBuf = crate::logging::log_path();
     if log_path.exists() {
         let msg = format!("检测到请求日志文件：{:?}", log_path);
         if !json {
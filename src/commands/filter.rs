@@ -0,0 +1,88 @@
+use std::io::Read;
+
+use owo_colors::OwoColorize;
+
+use crate::filter::{FilterScope, RequestFilter};
+use crate::{CliError, CliResult, FilterCommand};
+
+pub fn handle_filter_cmd(cmd: FilterCommand) -> CliResult<()> {
+    match cmd {
+        FilterCommand::Test {
+            input,
+            rules,
+            service,
+            config,
+            path,
+            model,
+            session,
+        } => {
+            let body = if input == "-" {
+                let mut buf = Vec::new();
+                std::io::stdin()
+                    .read_to_end(&mut buf)
+                    .map_err(|e| CliError::Other(format!("failed to read stdin: {e}")))?;
+                buf
+            } else {
+                std::fs::read(&input)
+                    .map_err(|e| CliError::Other(format!("failed to read {input}: {e}")))?
+            };
+            let filter = rules
+                .map(RequestFilter::from_path)
+                .unwrap_or_else(RequestFilter::new);
+            if !filter.path().exists() {
+                println!("No filter rules at {:?}", filter.path());
+                return Ok(());
+            }
+            let model = model.or_else(|| {
+                serde_json::from_slice::<serde_json::Value>(&body)
+                    .ok()?
+                    .get("model")?
+                    .as_str()
+                    .map(str::to_string)
+            });
+            let scope = FilterScope {
+                service,
+                config,
+                path,
+                model,
+            };
+            let trace = filter.trace(&body, session.as_deref(), &scope);
+
+            println!("Rules: {:?}", filter.path());
+            for problem in &trace.problems {
+                println!("{} {problem}", "[WARN]".yellow());
+            }
+            if trace.rules.is_empty() {
+                println!("No request-side rules loaded.");
+            }
+            for rule in &trace.rules {
+                let status = if !rule.in_scope {
+                    "out of scope".dimmed().to_string()
+                } else if rule.matches == 0 {
+                    "no match".dimmed().to_string()
+                } else {
+                    format!("{} match(es)", rule.matches).green().to_string()
+                };
+                println!("  {:<20} {:<9} {status}", rule.id, rule.op);
+            }
+            for (name, n) in &trace.redactions {
+                println!("  redacted {name}: {n}");
+            }
+            match &trace.blocked {
+                Some(hit) => println!(
+                    "{} blocked by rule '{}'{}",
+                    "[BLOCK]".red(),
+                    hit.rule_id,
+                    hit.message
+                        .as_deref()
+                        .map(|m| format!(": {m}"))
+                        .unwrap_or_default()
+                ),
+                None => println!("{} not blocked", "[OK]".green()),
+            }
+            println!();
+            println!("{}", String::from_utf8_lossy(&trace.body));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,90 @@
+use owo_colors::OwoColorize;
+
+use crate::client_keys::{ClientKeyRegistry, registry_path};
+use crate::{CliError, CliResult, KeysCommand};
+
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+pub fn handle_keys_cmd(cmd: KeysCommand) -> CliResult<()> {
+    let load = || ClientKeyRegistry::load().map_err(|e| CliError::Other(format!("{e:#}")));
+    let save = |reg: &ClientKeyRegistry| {
+        reg.save()
+            .map_err(|e| CliError::Other(format!("failed to write {:?}: {e:#}", registry_path())))
+    };
+
+    match cmd {
+        KeysCommand::Create {
+            label,
+            configs,
+            daily_token_budget,
+        } => {
+            let mut reg = load()?;
+            let first_key = !reg.has_active_keys();
+            let (key, plaintext) = reg
+                .create(&label, configs, daily_token_budget, now_ms())
+                .map_err(|e| CliError::Other(e.to_string()))?;
+            save(&reg)?;
+            println!("Created key {} for '{}'.", key.id, key.label);
+            println!();
+            println!("  {}", plaintext.bold());
+            println!();
+            println!(
+                "{}",
+                "This is the only time the key is shown; hand it to its owner now.".yellow()
+            );
+            println!(
+                "Clients send it as `Authorization: Bearer <key>` or the `x-codex-helper-token` header."
+            );
+            if first_key {
+                println!(
+                    "{} This is the first active key: the proxy now rejects requests without a valid key.",
+                    "[NOTE]".cyan()
+                );
+            }
+        }
+        KeysCommand::List => {
+            let reg = load()?;
+            if reg.keys.is_empty() {
+                println!("No client keys in {:?}", registry_path());
+                return Ok(());
+            }
+            for key in &reg.keys {
+                let status = if key.is_active() {
+                    "active".green().to_string()
+                } else {
+                    "revoked".dimmed().to_string()
+                };
+                let configs = if key.allowed_configs.is_empty() {
+                    "*".to_string()
+                } else {
+                    key.allowed_configs.join(",")
+                };
+                let budget = key
+                    .daily_token_budget
+                    .map(|b| b.to_string())
+                    .unwrap_or_else(|| "-".to_string());
+                println!(
+                    "{}  {:<20} {:<8} configs={} daily_budget={}",
+                    key.id, key.label, status, configs, budget
+                );
+            }
+        }
+        KeysCommand::Revoke { id_or_label } => {
+            let mut reg = load()?;
+            let Some(key) = reg.revoke(id_or_label.trim(), now_ms()).cloned() else {
+                return Err(CliError::Other(format!(
+                    "no active key with id or label '{}'",
+                    id_or_label.trim()
+                )));
+            };
+            save(&reg)?;
+            println!("Revoked key {} ('{}').", key.id, key.label);
+        }
+    }
+    Ok(())
+}
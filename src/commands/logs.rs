@@ -0,0 +1,537 @@
+use crate::budget::{day_index, format_day};
+use crate::commands::usage::{now_ms, parse_duration_ms};
+use crate::logging::{debug_log_files, log_path, open_log_file, request_log_files};
+use crate::model_routing::match_wildcard;
+use crate::{CliError, CliResult, LogsCommand};
+use owo_colors::OwoColorize;
+use serde_json::Value as JsonValue;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Seek, SeekFrom};
+use std::time::Duration;
+
+pub async fn handle_logs_cmd(cmd: LogsCommand) -> CliResult<()> {
+    match cmd {
+        LogsCommand::Query {
+            status,
+            config,
+            provider,
+            model,
+            session,
+            path,
+            error_class,
+            min_duration_ms,
+            max_duration_ms,
+            since,
+            until,
+            service,
+            id,
+            limit,
+            follow,
+            raw,
+        } => {
+            if let Some(id) = id {
+                return show_request(&id, raw);
+            }
+            let now = now_ms();
+            let ago = |s: Option<String>| -> CliResult<Option<u64>> {
+                s.map(|s| parse_duration_ms(&s).map(|d| now.saturating_sub(d)))
+                    .transpose()
+            };
+            let filter = LogFilter {
+                status: status.as_deref().map(StatusRange::parse).transpose()?,
+                config,
+                provider,
+                model,
+                session,
+                path,
+                error_class,
+                min_duration_ms,
+                max_duration_ms,
+                since_ms: ago(since)?,
+                until_ms: ago(until)?,
+                service,
+            };
+            query(&filter, limit, follow, raw).await
+        }
+    }
+}
+
+/// `--status` accepts `502`, `5xx` or `500-599`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct StatusRange {
+    lo: u16,
+    hi: u16,
+}
+
+impl StatusRange {
+    fn parse(s: &str) -> CliResult<Self> {
+        let s = s.trim().to_ascii_lowercase();
+        let bad = || CliError::Usage(format!("invalid --status {s:?} (e.g. 502, 5xx, 400-499)"));
+        if let Some(class) = s.strip_suffix("xx") {
+            let d = class.parse::<u16>().map_err(|_| bad())?;
+            if !(1..=5).contains(&d) {
+                return Err(bad());
+            }
+            return Ok(Self {
+                lo: d * 100,
+                hi: d * 100 + 99,
+            });
+        }
+        if let Some((lo, hi)) = s.split_once('-') {
+            let lo = lo.trim().parse::<u16>().map_err(|_| bad())?;
+            let hi = hi.trim().parse::<u16>().map_err(|_| bad())?;
+            if lo > hi {
+                return Err(bad());
+            }
+            return Ok(Self { lo, hi });
+        }
+        let code = s.parse::<u16>().map_err(|_| bad())?;
+        Ok(Self { lo: code, hi: code })
+    }
+
+    fn contains(&self, code: u16) -> bool {
+        (self.lo..=self.hi).contains(&code)
+    }
+}
+
+#[derive(Debug, Default)]
+struct LogFilter {
+    status: Option<StatusRange>,
+    config: Option<String>,
+    provider: Option<String>,
+    /// Supports `*` wildcards, like `model_mapping`.
+    model: Option<String>,
+    /// Prefix of the session id.
+    session: Option<String>,
+    /// Substring of the request path.
+    path: Option<String>,
+    error_class: Option<String>,
+    min_duration_ms: Option<u64>,
+    max_duration_ms: Option<u64>,
+    since_ms: Option<u64>,
+    until_ms: Option<u64>,
+    service: Option<String>,
+}
+
+fn str_field<'a>(v: &'a JsonValue, name: &str) -> Option<&'a str> {
+    v.get(name).and_then(|x| x.as_str())
+}
+
+/// Error class of a log line; older lines only carry it inside an inline `http_debug`.
+fn error_class(v: &JsonValue) -> Option<&str> {
+    str_field(v, "upstream_error_class").or_else(|| {
+        v.get("http_debug")
+            .and_then(|h| h.get("upstream_error_class"))
+            .and_then(|x| x.as_str())
+    })
+}
+
+impl LogFilter {
+    fn matches(&self, v: &JsonValue) -> bool {
+        let ts = v.get("timestamp_ms").and_then(|x| x.as_u64()).unwrap_or(0);
+        let status = v.get("status_code").and_then(|x| x.as_u64()).unwrap_or(0) as u16;
+        let duration = v.get("duration_ms").and_then(|x| x.as_u64()).unwrap_or(0);
+        let eq = |want: &Option<String>, field: &str| {
+            want.as_deref()
+                .is_none_or(|w| str_field(v, field) == Some(w))
+        };
+
+        self.since_ms.is_none_or(|since| ts >= since)
+            && self.until_ms.is_none_or(|until| ts <= until)
+            && self.status.is_none_or(|r| r.contains(status))
+            && self.min_duration_ms.is_none_or(|min| duration >= min)
+            && self.max_duration_ms.is_none_or(|max| duration <= max)
+            && eq(&self.service, "service")
+            && eq(&self.config, "config_name")
+            && eq(&self.provider, "provider_id")
+            && self.model.as_deref().is_none_or(|pattern| {
+                str_field(v, "model").is_some_and(|m| match_wildcard(pattern, m))
+            })
+            && self.session.as_deref().is_none_or(|prefix| {
+                str_field(v, "session_id").is_some_and(|sid| sid.starts_with(prefix))
+            })
+            && self
+                .path
+                .as_deref()
+                .is_none_or(|sub| str_field(v, "path").is_some_and(|p| p.contains(sub)))
+            && self
+                .error_class
+                .as_deref()
+                .is_none_or(|want| error_class(v) == Some(want))
+    }
+}
+
+/// `YYYY-MM-DD HH:MM:SS` (UTC).
+fn format_ts(ms: u64) -> String {
+    let secs = (ms / 1000) % 86_400;
+    format!(
+        "{} {:02}:{:02}:{:02}",
+        format_day(day_index(ms)),
+        secs / 3600,
+        (secs / 60) % 60,
+        secs % 60
+    )
+}
+
+fn format_line(v: &JsonValue) -> String {
+    let ts = v.get("timestamp_ms").and_then(|x| x.as_u64()).unwrap_or(0);
+    let status = v.get("status_code").and_then(|x| x.as_u64()).unwrap_or(0);
+    let status_s = if (200..300).contains(&status) {
+        status.to_string().green().to_string()
+    } else {
+        status.to_string().red().to_string()
+    };
+    let mut out = format!(
+        "[{}] {} {} {} {} {}ms config={}",
+        format_ts(ts),
+        str_field(v, "service").unwrap_or("-"),
+        str_field(v, "method").unwrap_or("-"),
+        str_field(v, "path").unwrap_or("-"),
+        status_s,
+        v.get("duration_ms").and_then(|x| x.as_u64()).unwrap_or(0),
+        str_field(v, "config_name").unwrap_or("-"),
+    );
+    for (label, value) in [
+        ("provider", str_field(v, "provider_id")),
+        ("model", str_field(v, "model")),
+        ("session", str_field(v, "session_id")),
+        ("class", error_class(v)),
+    ] {
+        if let Some(value) = value {
+            out.push_str(&format!(" {label}={value}"));
+        }
+    }
+    if let Some(attempts) = v
+        .get("retry")
+        .and_then(|r| r.get("attempts"))
+        .and_then(|x| x.as_u64())
+        .filter(|&a| a > 1)
+    {
+        out.push_str(&format!(" attempts={attempts}"));
+    }
+    if let Some(id) = v
+        .get("http_debug_ref")
+        .and_then(|r| r.get("id"))
+        .and_then(|x| x.as_str())
+    {
+        out.push_str(&format!(" id={id}"));
+    }
+    out
+}
+
+fn print_line(line: &str, v: &JsonValue, raw: bool) {
+    if raw {
+        println!("{line}");
+    } else {
+        println!("{}", format_line(v));
+    }
+}
+
+async fn query(filter: &LogFilter, limit: usize, follow: bool, raw: bool) -> CliResult<()> {
+    // Keep only the last `limit` matches in memory while streaming all files.
+    let mut matches: VecDeque<(String, JsonValue)> = VecDeque::new();
+    for path in request_log_files(filter.since_ms) {
+        for line in open_log_file(&path)?.lines().map_while(Result::ok) {
+            let Ok(v) = serde_json::from_str::<JsonValue>(&line) else {
+                continue;
+            };
+            if !filter.matches(&v) {
+                continue;
+            }
+            if matches.len() == limit {
+                matches.pop_front();
+            }
+            if limit > 0 {
+                matches.push_back((line, v));
+            }
+        }
+    }
+    for (line, v) in &matches {
+        print_line(line, v, raw);
+    }
+    if follow {
+        follow_log(filter, raw).await?;
+    }
+    Ok(())
+}
+
+/// Prints matching lines appended to `requests.jsonl` until interrupted.
+///
+/// The open file is kept across polls: once the path points at a different file (rotation),
+/// the rest of the old file is printed before switching over to the new one.
+async fn follow_log(filter: &LogFilter, raw: bool) -> CliResult<()> {
+    let path = log_path();
+    let mut current: Option<BufReader<File>> = None;
+    let mut pending = String::new();
+    let mut first = true;
+    loop {
+        if !first {
+            tokio::time::sleep(Duration::from_millis(500)).await;
+        }
+        let Ok(meta) = std::fs::metadata(&path) else {
+            first = false;
+            continue;
+        };
+        let reopen = match &current {
+            None => true,
+            Some(reader) => match reader.get_ref().metadata() {
+                Ok(open) => !same_file(&open, &meta) || open.len() < position(reader),
+                Err(_) => true,
+            },
+        };
+        if let Some(reader) = current.as_mut() {
+            // Lines written to the old file right before it was rotated still count.
+            read_new_lines(reader, &mut pending, filter, raw);
+        }
+        if reopen {
+            let mut reader = open_log_file(&path)?;
+            if first {
+                reader
+                    .seek(SeekFrom::End(0))
+                    .map_err(|e| CliError::Usage(format!("无法读取请求日志 {:?}: {}", path, e)))?;
+            }
+            pending.clear();
+            read_new_lines(&mut reader, &mut pending, filter, raw);
+            current = Some(reader);
+        }
+        first = false;
+    }
+}
+
+fn read_new_lines(
+    reader: &mut BufReader<File>,
+    pending: &mut String,
+    filter: &LogFilter,
+    raw: bool,
+) {
+    let mut buf = String::new();
+    while reader.read_line(&mut buf).unwrap_or(0) > 0 {
+        if !buf.ends_with('\n') {
+            // Partially written line; finish it on the next poll.
+            pending.push_str(&buf);
+            buf.clear();
+            break;
+        }
+        let line = std::mem::take(pending) + buf.trim_end();
+        buf.clear();
+        if let Ok(v) = serde_json::from_str::<JsonValue>(&line)
+            && filter.matches(&v)
+        {
+            print_line(&line, &v, raw);
+        }
+    }
+}
+
+fn position(reader: &BufReader<File>) -> u64 {
+    let mut file = reader.get_ref();
+    file.stream_position()
+        .map(|p| p.saturating_sub(reader.buffer().len() as u64))
+        .unwrap_or(0)
+}
+
+/// Whether two metadata entries describe the same file (device + inode on unix).
+#[cfg(unix)]
+fn same_file(a: &std::fs::Metadata, b: &std::fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+    a.dev() == b.dev() && a.ino() == b.ino()
+}
+
+/// Without a stable file id a replaced file is only noticed once it is shorter than what was read.
+#[cfg(not(unix))]
+fn same_file(_: &std::fs::Metadata, _: &std::fs::Metadata) -> bool {
+    true
+}
+
+/// Finds the request line whose `http_debug_ref.id` is `id` and the split debug entry it
+/// points to, then prints headers and body previews.
+fn show_request(id: &str, raw: bool) -> CliResult<()> {
+    // Ids are `<timestamp_ms>-<seq>`, so older rotated files can be skipped.
+    let since_ms = id.split('-').next().and_then(|ts| ts.parse::<u64>().ok());
+    let find = |files: Vec<std::path::PathBuf>,
+                matches: &dyn Fn(&JsonValue) -> bool|
+     -> CliResult<Option<(String, JsonValue)>> {
+        for path in files {
+            for line in open_log_file(&path)?.lines().map_while(Result::ok) {
+                if !line.contains(id) {
+                    continue;
+                }
+                if let Ok(v) = serde_json::from_str::<JsonValue>(&line)
+                    && matches(&v)
+                {
+                    return Ok(Some((line, v)));
+                }
+            }
+        }
+        Ok(None)
+    };
+
+    let request = find(request_log_files(since_ms), &|v| {
+        v.get("http_debug_ref")
+            .and_then(|r| r.get("id"))
+            .and_then(|x| x.as_str())
+            == Some(id)
+    })?;
+    let debug = find(debug_log_files(since_ms), &|v| {
+        str_field(v, "id") == Some(id)
+    })?;
+    if request.is_none() && debug.is_none() {
+        return Err(CliError::Usage(format!("no request with id {id:?} found")));
+    }
+
+    if raw {
+        for (line, _) in request.iter().chain(debug.iter()) {
+            println!("{line}");
+        }
+        return Ok(());
+    }
+    if let Some((_, v)) = request.as_ref().or(debug.as_ref()) {
+        println!("{}", format_line(v));
+    }
+    // Inline `http_debug` (split disabled or failed) works the same as the split entry.
+    let http_debug = debug
+        .as_ref()
+        .and_then(|(_, v)| v.get("http_debug"))
+        .or_else(|| request.as_ref().and_then(|(_, v)| v.get("http_debug")));
+    match http_debug {
+        Some(h) => print_http_debug(h),
+        None => println!("(no HTTP debug entry recorded for this request)"),
+    }
+    Ok(())
+}
+
+fn print_http_debug(h: &JsonValue) {
+    for (label, key) in [
+        ("target", "target_url"),
+        ("error class", "upstream_error_class"),
+        ("hint", "upstream_error_hint"),
+        ("error", "upstream_error"),
+        ("cf-ray", "upstream_cf_ray"),
+        ("remote addr", "upstream_remote_addr"),
+    ] {
+        if let Some(value) = str_field(h, key) {
+            println!("{}: {}", label.bold(), value);
+        }
+    }
+    for (label, key) in [
+        ("client headers", "client_headers"),
+        ("upstream request headers", "upstream_request_headers"),
+        ("upstream response headers", "upstream_response_headers"),
+    ] {
+        let Some(headers) = h.get(key).and_then(|x| x.as_array()) else {
+            continue;
+        };
+        println!("{}", label.bold());
+        for header in headers {
+            println!(
+                "  {}: {}",
+                str_field(header, "name").unwrap_or("-"),
+                str_field(header, "value").unwrap_or("")
+            );
+        }
+    }
+    for (label, key) in [
+        ("client body", "client_body"),
+        ("upstream request body", "upstream_request_body"),
+        ("upstream response body", "upstream_response_body"),
+    ] {
+        let Some(body) = h.get(key) else {
+            continue;
+        };
+        let len = body
+            .get("original_len")
+            .and_then(|x| x.as_u64())
+            .unwrap_or(0);
+        let truncated = body
+            .get("truncated")
+            .and_then(|x| x.as_bool())
+            .unwrap_or(false);
+        println!(
+            "{} ({} bytes{})",
+            label.bold(),
+            len,
+            if truncated { ", truncated" } else { "" }
+        );
+        match str_field(body, "encoding") {
+            Some("utf8") => println!("{}", str_field(body, "data").unwrap_or("")),
+            _ => println!("  <binary, base64 in --raw output>"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn status_ranges_parse() {
+        assert_eq!(
+            StatusRange::parse("5xx").unwrap(),
+            StatusRange { lo: 500, hi: 599 }
+        );
+        assert_eq!(
+            StatusRange::parse("400-429").unwrap(),
+            StatusRange { lo: 400, hi: 429 }
+        );
+        assert_eq!(
+            StatusRange::parse("502").unwrap(),
+            StatusRange { lo: 502, hi: 502 }
+        );
+        assert!(StatusRange::parse("9xx").is_err());
+        assert!(StatusRange::parse("499-400").is_err());
+        assert!(StatusRange::parse("bad").is_err());
+    }
+
+    #[test]
+    fn filter_matches_fields_and_error_class() {
+        let split = json!({
+            "timestamp_ms": 1_000, "service": "codex", "method": "POST", "path": "/v1/responses",
+            "status_code": 403, "duration_ms": 2_500, "config_name": "right", "provider_id": "relay",
+            "model": "gpt-5-codex", "session_id": "019a-abcd",
+            "upstream_error_class": "cloudflare_challenge",
+            "http_debug_ref": {"id": "1000-0", "file": "requests_debug.jsonl"}
+        });
+        let inline = json!({
+            "timestamp_ms": 2_000, "service": "codex", "path": "/v1/responses", "status_code": 502,
+            "duration_ms": 100, "config_name": "right",
+            "http_debug": {"upstream_error_class": "upstream_transport_error"}
+        });
+
+        let filter = LogFilter {
+            status: Some(StatusRange::parse("4xx").unwrap()),
+            config: Some("right".to_string()),
+            provider: Some("relay".to_string()),
+            model: Some("gpt-5*".to_string()),
+            session: Some("019a".to_string()),
+            path: Some("responses".to_string()),
+            error_class: Some("cloudflare_challenge".to_string()),
+            min_duration_ms: Some(2_000),
+            since_ms: Some(500),
+            until_ms: Some(1_500),
+            ..Default::default()
+        };
+        assert!(filter.matches(&split));
+        assert!(!filter.matches(&inline));
+
+        let by_class = LogFilter {
+            error_class: Some("upstream_transport_error".to_string()),
+            ..Default::default()
+        };
+        assert!(by_class.matches(&inline));
+        assert!(!by_class.matches(&split));
+
+        let slow = LogFilter {
+            max_duration_ms: Some(1_000),
+            ..Default::default()
+        };
+        assert!(!slow.matches(&split));
+        assert!(slow.matches(&inline));
+
+        assert_eq!(
+            format_ts(1_710_460_800_000 + 3_723_000),
+            "2024-03-15 01:02:03"
+        );
+        assert!(format_line(&split).contains("id=1000-0"));
+    }
+}
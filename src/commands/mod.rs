@@ -1,4 +1,8 @@
 pub mod config;
 pub mod doctor;
+pub mod filter;
+pub mod keys;
+pub mod logs;
+pub mod secrets;
 pub mod session;
 pub mod usage;
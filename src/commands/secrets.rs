@@ -0,0 +1,342 @@
+use std::io::Read;
+
+use owo_colors::OwoColorize;
+
+use crate::config::{ProxyConfig, ServiceConfigManager, load_config, save_config};
+use crate::secrets::{SecretStore, passphrase_from_env};
+use crate::{CliError, CliResult, SecretsCommand};
+
+fn validate_name(name: &str) -> CliResult<&str> {
+    let name = name.trim();
+    if name.is_empty() || name.chars().any(char::is_whitespace) {
+        return Err(CliError::Secrets(format!(
+            "invalid secret name '{name}' (must be non-empty and contain no whitespace)"
+        )));
+    }
+    Ok(name)
+}
+
+fn read_value_from_stdin() -> CliResult<String> {
+    if atty::is(atty::Stream::Stdin) {
+        eprintln!("Enter secret value, then press Ctrl+D:");
+    }
+    let mut buf = String::new();
+    std::io::stdin()
+        .read_to_string(&mut buf)
+        .map_err(|e| CliError::Secrets(format!("failed to read stdin: {e}")))?;
+    Ok(buf.trim_end_matches(['\r', '\n']).to_string())
+}
+
+/// A plaintext value found in the config, and where it will go in the store.
+#[derive(Debug, PartialEq, Eq)]
+struct MigratedSecret {
+    name: String,
+    value: String,
+}
+
+/// Moves inline `auth_token` / `api_key` values into `*_secret` references, and pooled
+/// `auth_tokens` into `auth_token_secrets`.
+///
+/// Secret names are `<service>.<config>.<upstream index>.<field>` (`...auth_tokens.<n>` for pool
+/// keys), so migrating twice is idempotent.
+/// Returns the secrets to store plus warnings for values that were left in place.
+fn plan_migration(cfg: &mut ProxyConfig) -> (Vec<MigratedSecret>, Vec<String>) {
+    let mut moved = Vec::new();
+    let mut warnings = Vec::new();
+    for (service, mgr) in [("codex", &mut cfg.codex), ("claude", &mut cfg.claude)] {
+        migrate_service(service, mgr, &mut moved, &mut warnings);
+    }
+    (moved, warnings)
+}
+
+fn migrate_service(
+    service: &str,
+    mgr: &mut ServiceConfigManager,
+    moved: &mut Vec<MigratedSecret>,
+    warnings: &mut Vec<String>,
+) {
+    let mut names: Vec<String> = mgr.configs.keys().cloned().collect();
+    names.sort();
+    for config_name in names {
+        let Some(svc) = mgr.configs.get_mut(&config_name) else {
+            continue;
+        };
+        for (idx, up) in svc.upstreams.iter_mut().enumerate() {
+            let prefix = format!(
+                "{service}.{}.{idx}",
+                config_name.replace(char::is_whitespace, "_")
+            );
+            let auth = &mut up.auth;
+            for (field, inline, secret) in [
+                (
+                    "auth_token",
+                    &mut auth.auth_token,
+                    &mut auth.auth_token_secret,
+                ),
+                ("api_key", &mut auth.api_key, &mut auth.api_key_secret),
+            ] {
+                let Some(value) = inline.take_if(|v| !v.trim().is_empty()) else {
+                    continue;
+                };
+                if let Some(existing) = secret.as_deref() {
+                    warnings.push(format!(
+                        "{prefix}: {field} is set inline but {field}_secret already points to '{existing}'; left unchanged"
+                    ));
+                    *inline = Some(value);
+                    continue;
+                }
+                let name = format!("{prefix}.{field}");
+                *secret = Some(name.clone());
+                moved.push(MigratedSecret { name, value });
+            }
+            for value in std::mem::take(&mut auth.auth_tokens) {
+                if value.trim().is_empty() {
+                    continue;
+                }
+                let name = (1..)
+                    .map(|n| format!("{prefix}.auth_tokens.{n}"))
+                    .find(|n| !auth.auth_token_secrets.contains(n))
+                    .expect("unbounded range");
+                auth.auth_token_secrets.push(name.clone());
+                moved.push(MigratedSecret { name, value });
+            }
+        }
+    }
+}
+
+pub async fn handle_secrets_cmd(cmd: SecretsCommand) -> CliResult<()> {
+    let store = SecretStore::default_location();
+    let passphrase = passphrase_from_env();
+    let load = |store: &SecretStore| {
+        store
+            .load(passphrase.as_deref())
+            .map_err(|e| CliError::Secrets(format!("{e:#}")))
+    };
+    let save = |store: &SecretStore, entries| {
+        store
+            .save(entries, passphrase.as_deref())
+            .map_err(|e| CliError::Secrets(format!("{e:#}")))
+    };
+
+    match cmd {
+        SecretsCommand::Set { name, value } => {
+            let name = validate_name(&name)?;
+            let value = match value {
+                Some(v) => v,
+                None => read_value_from_stdin()?,
+            };
+            if value.trim().is_empty() {
+                return Err(CliError::Secrets("refusing to store an empty value".into()));
+            }
+            let mut entries = load(&store)?;
+            let replaced = entries.insert(name.to_string(), value).is_some();
+            save(&store, &entries)?;
+            println!(
+                "{} secret '{}' in {:?}",
+                if replaced { "Updated" } else { "Stored" },
+                name,
+                store.path()
+            );
+            println!("Reference it from an upstream with: auth_token_secret = \"{name}\"");
+        }
+        SecretsCommand::Get { name } => {
+            let entries = load(&store)?;
+            match entries.get(name.trim()) {
+                Some(v) => println!("{v}"),
+                None => {
+                    return Err(CliError::Secrets(format!("secret '{}' not found", name)));
+                }
+            }
+        }
+        SecretsCommand::List => {
+            let entries = load(&store)?;
+            if entries.is_empty() {
+                println!("No secrets stored in {:?}", store.path());
+                return Ok(());
+            }
+            for name in entries.keys() {
+                println!("{name}");
+            }
+        }
+        SecretsCommand::Rm { name } => {
+            let mut entries = load(&store)?;
+            if entries.remove(name.trim()).is_none() {
+                return Err(CliError::Secrets(format!("secret '{}' not found", name)));
+            }
+            save(&store, &entries)?;
+            println!("Removed secret '{}'", name.trim());
+        }
+        SecretsCommand::Migrate { dry_run } => {
+            let mut cfg = load_config()
+                .await
+                .map_err(|e| CliError::ProxyConfig(e.to_string()))?;
+            let (moved, warnings) = plan_migration(&mut cfg);
+            for w in &warnings {
+                println!("{} {}", "[WARN]".yellow(), w);
+            }
+            if moved.is_empty() {
+                println!("No plaintext auth_token / auth_tokens / api_key values to migrate.");
+                return Ok(());
+            }
+            println!("Plaintext values to move into the secret store:");
+            for m in &moved {
+                println!("  {}", m.name);
+            }
+            if dry_run {
+                println!(
+                    "Dry run: {} value(s) would be moved; no files changed.",
+                    moved.len()
+                );
+                return Ok(());
+            }
+            // 先写入加密存储，再改配置：中途失败时配置仍保留明文，不会引用不存在的密钥。
+            let mut entries = load(&store)?;
+            for m in moved.iter() {
+                entries.insert(m.name.clone(), m.value.clone());
+            }
+            save(&store, &entries)?;
+            save_config(&cfg)
+                .await
+                .map_err(|e| CliError::ProxyConfig(e.to_string()))?;
+            println!(
+                "Moved {} value(s) into {:?}. The config backup (*.bak) still holds the plaintext values; delete it once everything works.",
+                moved.len(),
+                store.path()
+            );
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{ServiceConfig, UpstreamAuth, UpstreamConfig};
+    use std::collections::HashMap;
+
+    fn upstream(auth: UpstreamAuth) -> UpstreamConfig {
+        UpstreamConfig {
+            base_url: "https://example.com/v1".to_string(),
+            auth,
+            tags: HashMap::new(),
+            supported_models: HashMap::new(),
+            model_mapping: HashMap::new(),
+            resolve: HashMap::new(),
+            ip_preference: None,
+            body_rules: Default::default(),
+        }
+    }
+
+    #[test]
+    fn plan_migration_moves_inline_values_and_keeps_conflicts() {
+        let mut cfg = ProxyConfig::default();
+        cfg.codex.configs.insert(
+            "relay main".to_string(),
+            ServiceConfig {
+                name: "relay main".to_string(),
+                alias: None,
+                enabled: true,
+                level: 1,
+                budget: Default::default(),
+                upstreams: vec![
+                    upstream(UpstreamAuth {
+                        auth_token: Some("sk-inline".to_string()),
+                        api_key: Some("key-inline".to_string()),
+                        api_key_secret: Some("already".to_string()),
+                        ..Default::default()
+                    }),
+                    upstream(UpstreamAuth {
+                        auth_token_env: Some("OPENAI_API_KEY".to_string()),
+                        ..Default::default()
+                    }),
+                ],
+                body_rules: Default::default(),
+            },
+        );
+
+        let (moved, warnings) = plan_migration(&mut cfg);
+        assert_eq!(
+            moved,
+            vec![MigratedSecret {
+                name: "codex.relay_main.0.auth_token".to_string(),
+                value: "sk-inline".to_string(),
+            }]
+        );
+        assert_eq!(warnings.len(), 1);
+
+        let auth = &cfg.codex.configs["relay main"].upstreams[0].auth;
+        assert_eq!(auth.auth_token, None);
+        assert_eq!(
+            auth.auth_token_secret.as_deref(),
+            Some("codex.relay_main.0.auth_token")
+        );
+        assert_eq!(auth.api_key.as_deref(), Some("key-inline"));
+
+        // A second run finds nothing left to move.
+        let (moved, _) = plan_migration(&mut cfg);
+        assert!(moved.is_empty());
+    }
+
+    #[test]
+    fn migrated_key_pool_still_rotates_across_all_keys() {
+        use crate::config::{AuthKeySource, KeyRotation};
+        use crate::lb::LoadBalancer;
+        use std::sync::{Arc, Mutex};
+
+        let mut cfg = ProxyConfig::default();
+        cfg.codex.configs.insert(
+            "pool".to_string(),
+            ServiceConfig {
+                name: "pool".to_string(),
+                alias: None,
+                enabled: true,
+                level: 1,
+                budget: Default::default(),
+                upstreams: vec![upstream(UpstreamAuth {
+                    auth_token: Some("sk-a".to_string()),
+                    auth_tokens: vec!["sk-b".to_string(), "sk-c".to_string()],
+                    ..Default::default()
+                })],
+                body_rules: Default::default(),
+            },
+        );
+
+        let (moved, warnings) = plan_migration(&mut cfg);
+        assert!(warnings.is_empty(), "{warnings:?}");
+        assert_eq!(moved.len(), 3);
+        let service = cfg.codex.configs["pool"].clone();
+        let auth = &service.upstreams[0].auth;
+        assert!(auth.auth_token.is_none() && auth.auth_tokens.is_empty());
+        assert!(auth.has_token_pool());
+
+        // Every pool key now comes from the store and maps to one of the moved values.
+        let pool = auth.token_pool();
+        let mut values = pool
+            .iter()
+            .map(|(_, src)| match src {
+                AuthKeySource::Secret(name) => moved
+                    .iter()
+                    .find(|m| m.name == *name)
+                    .map(|m| m.value.as_str())
+                    .expect("migrated secret"),
+                other => panic!("unexpected pool source {other:?}"),
+            })
+            .collect::<Vec<_>>();
+        values.sort();
+        assert_eq!(values, vec!["sk-a", "sk-b", "sk-c"]);
+
+        let ids = pool.iter().map(|(id, _)| id.as_str()).collect::<Vec<_>>();
+        let lb = LoadBalancer::new(
+            Arc::new(service.clone()),
+            Arc::new(Mutex::new(HashMap::new())),
+        );
+        let mut picked = (0..3)
+            .map(|_| {
+                lb.select_key(0, &ids, KeyRotation::RoundRobin)
+                    .expect("key")
+            })
+            .collect::<Vec<_>>();
+        picked.sort();
+        assert_eq!(picked, vec![0, 1, 2]);
+    }
+}
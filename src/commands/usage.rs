@@ -1,34 +1,36 @@
-use crate::config::proxy_home_dir;
+use crate::budget::{day_index, format_day};
+use crate::logging::{log_path, open_log_file, request_log_files};
+use crate::pricing::{Cost, CostTotals, format_amount};
+use crate::usage::UsageMetrics;
 use crate::{CliError, CliResult, UsageCommand};
 use owo_colors::OwoColorize;
-use serde_json::Value as JsonValue;
-use std::collections::HashMap;
+use serde_json::{Value as JsonValue, json};
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
 use std::fs::File;
 use std::io::{BufRead, BufReader};
 use std::path::PathBuf;
 
 pub async fn handle_usage_cmd(cmd: UsageCommand) -> CliResult<()> {
-    let log_path: PathBuf = proxy_home_dir().join("logs").join("requests.jsonl");
-    if !log_path.exists() {
-        println!("No request logs found at {:?}", log_path);
-        return Ok(());
-    }
-
+    let log_path: PathBuf = log_path();
     match cmd {
+        UsageCommand::Report {
+            since,
+            group_by,
+            format,
+            service,
+        } => return report(since, &group_by, &format, service.as_deref()),
+        _ if !log_path.exists() => {
+            println!("No request logs found at {:?}", log_path);
+            return Ok(());
+        }
         UsageCommand::Tail { limit, raw } => {
-            let file = File::open(&log_path)
-                .map_err(|e| CliError::Usage(format!("无法打开请求日志 {:?}: {}", log_path, e)))?;
-            let reader = BufReader::new(file);
-            let lines: Vec<String> = reader.lines().map_while(Result::ok).collect();
-            let total = lines.len();
-            let start = total.saturating_sub(limit);
-            for line in &lines[start..] {
+            for line in tail_lines(limit)? {
                 if raw {
                     // 原样输出 JSON 行，方便 jq/脚本进一步处理
                     println!("{line}");
                     continue;
                 }
-                if let Ok(v) = serde_json::from_str::<JsonValue>(line) {
+                if let Ok(v) = serde_json::from_str::<JsonValue>(&line) {
                     let ts = v.get("timestamp_ms").and_then(|x| x.as_i64()).unwrap_or(0);
                     let service = v.get("service").and_then(|x| x.as_str()).unwrap_or("-");
                     let method = v.get("method").and_then(|x| x.as_str()).unwrap_or("-");
@@ -40,9 +42,14 @@ pub async fn handle_usage_cmd(cmd: UsageCommand) -> CliResult<()> {
                         .and_then(|u| u.get("total_tokens"))
                         .and_then(|x| x.as_i64())
                         .unwrap_or(0);
+                    let cost = v
+                        .get("cost")
+                        .and_then(|c| serde_json::from_value::<Cost>(c.clone()).ok())
+                        .map(|c| format!(", cost: {}", format_amount(c.amount, &c.currency)))
+                        .unwrap_or_default();
                     println!(
-                        "[{}] {} {} {} (config: {}, tokens: {})",
-                        ts, service, method, path, config_name, total_tokens
+                        "[{}] {} {} {} (config: {}, tokens: {}{})",
+                        ts, service, method, path, config_name, total_tokens, cost
                     );
                     println!("    status: {}", status);
                 }
@@ -53,6 +60,7 @@ pub async fn handle_usage_cmd(cmd: UsageCommand) -> CliResult<()> {
                 .map_err(|e| CliError::Usage(format!("无法打开请求日志 {:?}: {}", log_path, e)))?;
             let reader = BufReader::new(file);
             let mut aggregate: HashMap<String, (u64, i64, i64, i64)> = HashMap::new();
+            let mut costs: HashMap<String, CostTotals> = HashMap::new();
 
             for line in reader.lines().map_while(Result::ok) {
                 if let Ok(v) = serde_json::from_str::<JsonValue>(&line) {
@@ -79,6 +87,12 @@ pub async fn handle_usage_cmd(cmd: UsageCommand) -> CliResult<()> {
                         .and_then(|x| x.as_i64())
                         .unwrap_or(input + output);
 
+                    if let Some(cost) = v
+                        .get("cost")
+                        .and_then(|c| serde_json::from_value::<Cost>(c.clone()).ok())
+                    {
+                        costs.entry(config_name.clone()).or_default().add(&cost);
+                    }
                     let entry = aggregate
                         .entry(config_name)
                         .or_insert((0u64, 0i64, 0i64, 0i64));
@@ -90,6 +104,7 @@ pub async fn handle_usage_cmd(cmd: UsageCommand) -> CliResult<()> {
             }
 
             let mut items: Vec<(String, (u64, i64, i64, i64))> = aggregate.into_iter().collect();
+            #[allow(clippy::unnecessary_sort_by)]
             items.sort_by(|a, b| b.1.3.cmp(&a.1.3));
 
             println!(
@@ -98,13 +113,515 @@ pub async fn handle_usage_cmd(cmd: UsageCommand) -> CliResult<()> {
             );
             println!(
                 "{}",
-                "config_name | requests | input_tokens | output_tokens | total_tokens".bold()
+                "config_name | requests | input_tokens | output_tokens | total_tokens | est_cost"
+                    .bold()
             );
             for (name, (count, input, output, total)) in items.into_iter().take(limit) {
-                println!("{} | {} | {} | {} | {}", name, count, input, output, total);
+                let cost = costs.get(&name).and_then(CostTotals::display);
+                println!(
+                    "{} | {} | {} | {} | {} | {}",
+                    name,
+                    count,
+                    input,
+                    output,
+                    total,
+                    cost.unwrap_or_else(|| "-".to_string())
+                );
             }
         }
     }
 
     Ok(())
 }
+
+/// The last `limit` log lines, newest file first, keeping at most `limit` lines in memory.
+fn tail_lines(limit: usize) -> CliResult<Vec<String>> {
+    let mut out: VecDeque<String> = VecDeque::new();
+    for path in request_log_files(None).iter().rev() {
+        let needed = limit.saturating_sub(out.len());
+        if needed == 0 {
+            break;
+        }
+        let mut tail: VecDeque<String> = VecDeque::with_capacity(needed);
+        for line in open_log_file(path)?.lines().map_while(Result::ok) {
+            if tail.len() == needed {
+                tail.pop_front();
+            }
+            tail.push_back(line);
+        }
+        for line in tail.into_iter().rev() {
+            out.push_front(line);
+        }
+    }
+    Ok(out.into())
+}
+
+/// Parses a relative duration such as `90s`, `30m`, `24h`, `7d` or `2w` into milliseconds.
+pub(crate) fn parse_duration_ms(s: &str) -> CliResult<u64> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let n = num
+        .parse::<u64>()
+        .map_err(|_| CliError::Usage(format!("invalid duration {s:?} (expected e.g. 24h, 7d)")))?;
+    let unit_ms: u64 = match unit {
+        "s" => 1_000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        "d" => 86_400_000,
+        "w" => 7 * 86_400_000,
+        _ => {
+            return Err(CliError::Usage(format!(
+                "invalid duration unit in {s:?} (use s, m, h, d or w)"
+            )));
+        }
+    };
+    Ok(n.saturating_mul(unit_ms))
+}
+
+pub(crate) fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum GroupBy {
+    Day,
+    Model,
+    Provider,
+    Config,
+    Session,
+    Cwd,
+    Service,
+}
+
+impl GroupBy {
+    fn parse_list(s: &str) -> CliResult<Vec<GroupBy>> {
+        let mut out = Vec::new();
+        for part in s.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+            let g = match part.to_ascii_lowercase().as_str() {
+                "day" => GroupBy::Day,
+                "model" => GroupBy::Model,
+                "provider" => GroupBy::Provider,
+                "config" => GroupBy::Config,
+                "session" => GroupBy::Session,
+                "cwd" | "project" => GroupBy::Cwd,
+                "service" => GroupBy::Service,
+                other => {
+                    return Err(CliError::Usage(format!(
+                        "unknown --group-by {other:?} (use day, model, provider, config, session, cwd, service)"
+                    )));
+                }
+            };
+            if !out.contains(&g) {
+                out.push(g);
+            }
+        }
+        Ok(out)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            GroupBy::Day => "day",
+            GroupBy::Model => "model",
+            GroupBy::Provider => "provider",
+            GroupBy::Config => "config",
+            GroupBy::Session => "session",
+            GroupBy::Cwd => "cwd",
+            GroupBy::Service => "service",
+        }
+    }
+
+    fn key(self, v: &JsonValue) -> String {
+        let field = |name: &str| {
+            v.get(name)
+                .and_then(|x| x.as_str())
+                .unwrap_or("-")
+                .to_string()
+        };
+        match self {
+            GroupBy::Day => {
+                let ts = v.get("timestamp_ms").and_then(|x| x.as_u64()).unwrap_or(0);
+                format_day(day_index(ts))
+            }
+            GroupBy::Model => field("model"),
+            GroupBy::Provider => field("provider_id"),
+            GroupBy::Config => field("config_name"),
+            GroupBy::Session => field("session_id"),
+            GroupBy::Cwd => field("cwd"),
+            GroupBy::Service => field("service"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct ReportRow {
+    requests: u64,
+    ok: u64,
+    retries: u64,
+    durations_ms: Vec<u64>,
+    usage: UsageMetrics,
+    cost: CostTotals,
+}
+
+impl ReportRow {
+    fn add_line(&mut self, v: &JsonValue) {
+        self.requests += 1;
+        let status = v.get("status_code").and_then(|x| x.as_u64()).unwrap_or(0);
+        if status < 400 {
+            self.ok += 1;
+        }
+        let attempts = v
+            .get("retry")
+            .and_then(|r| r.get("attempts"))
+            .and_then(|x| x.as_u64())
+            .unwrap_or(1);
+        self.retries += attempts.saturating_sub(1);
+        self.durations_ms
+            .push(v.get("duration_ms").and_then(|x| x.as_u64()).unwrap_or(0));
+        if let Some(u) = v
+            .get("usage")
+            .and_then(|u| serde_json::from_value::<UsageMetrics>(u.clone()).ok())
+        {
+            self.usage.add_assign(&u);
+        }
+        if let Some(c) = v
+            .get("cost")
+            .and_then(|c| serde_json::from_value::<Cost>(c.clone()).ok())
+        {
+            self.cost.add(&c);
+        }
+    }
+
+    fn merge(&mut self, other: &ReportRow) {
+        self.requests += other.requests;
+        self.ok += other.ok;
+        self.retries += other.retries;
+        self.durations_ms.extend_from_slice(&other.durations_ms);
+        self.usage.add_assign(&other.usage);
+        self.cost.merge(&other.cost);
+    }
+
+    fn success_rate(&self) -> f64 {
+        if self.requests == 0 {
+            return 0.0;
+        }
+        self.ok as f64 / self.requests as f64
+    }
+
+    /// Nearest-rank percentile of request durations; call after `finish`.
+    fn percentile_ms(&self, p: f64) -> u64 {
+        let n = self.durations_ms.len();
+        if n == 0 {
+            return 0;
+        }
+        let rank = ((p * n as f64).ceil() as usize).clamp(1, n);
+        self.durations_ms[rank - 1]
+    }
+
+    fn finish(&mut self) {
+        self.durations_ms.sort_unstable();
+    }
+}
+
+/// Groups matching log lines by the requested dimensions.
+fn aggregate(
+    lines: impl Iterator<Item = String>,
+    group_by: &[GroupBy],
+    since_ms: Option<u64>,
+    service: Option<&str>,
+) -> BTreeMap<Vec<String>, ReportRow> {
+    let mut rows: BTreeMap<Vec<String>, ReportRow> = BTreeMap::new();
+    for line in lines {
+        let Ok(v) = serde_json::from_str::<JsonValue>(&line) else {
+            continue;
+        };
+        let ts = v.get("timestamp_ms").and_then(|x| x.as_u64()).unwrap_or(0);
+        if since_ms.is_some_and(|since| ts < since) {
+            continue;
+        }
+        if let Some(svc) = service
+            && v.get("service").and_then(|x| x.as_str()) != Some(svc)
+        {
+            continue;
+        }
+        let key = group_by.iter().map(|g| g.key(&v)).collect::<Vec<_>>();
+        rows.entry(key).or_default().add_line(&v);
+    }
+    for row in rows.values_mut() {
+        row.finish();
+    }
+    rows
+}
+
+fn report(
+    since: Option<String>,
+    group_by: &str,
+    format: &str,
+    service: Option<&str>,
+) -> CliResult<()> {
+    let group_by = GroupBy::parse_list(group_by)?;
+    let format = format.to_ascii_lowercase();
+    if !matches!(format.as_str(), "table" | "json" | "csv") {
+        return Err(CliError::Usage(format!(
+            "unknown --format {format:?} (use table, json or csv)"
+        )));
+    }
+    let since_ms = match since.as_deref() {
+        Some(s) => Some(now_ms().saturating_sub(parse_duration_ms(s)?)),
+        None => None,
+    };
+
+    let files = request_log_files(since_ms);
+    let mut readers = Vec::new();
+    for path in &files {
+        readers.push(open_log_file(path)?);
+    }
+    let lines = readers
+        .into_iter()
+        .flat_map(|r| r.lines().map_while(Result::ok));
+    let rows = aggregate(lines, &group_by, since_ms, service);
+
+    match format.as_str() {
+        "json" => print_report_json(&group_by, &rows),
+        "csv" => print_report_csv(&group_by, &rows),
+        _ => print_report_table(&group_by, &rows, since.as_deref(), files.len()),
+    }
+    Ok(())
+}
+
+fn print_report_json(group_by: &[GroupBy], rows: &BTreeMap<Vec<String>, ReportRow>) {
+    let items = rows
+        .iter()
+        .map(|(key, row)| {
+            let mut obj = serde_json::Map::new();
+            for (g, k) in group_by.iter().zip(key) {
+                obj.insert(g.name().to_string(), json!(k));
+            }
+            obj.insert("requests".into(), json!(row.requests));
+            obj.insert("success_rate".into(), json!(row.success_rate()));
+            obj.insert("p50_ms".into(), json!(row.percentile_ms(0.5)));
+            obj.insert("p95_ms".into(), json!(row.percentile_ms(0.95)));
+            obj.insert("retries".into(), json!(row.retries));
+            obj.insert("usage".into(), json!(row.usage));
+            obj.insert("cost".into(), json!(row.cost));
+            JsonValue::Object(obj)
+        })
+        .collect::<Vec<_>>();
+    println!(
+        "{}",
+        serde_json::to_string_pretty(&items).unwrap_or_else(|_| "[]".to_string())
+    );
+}
+
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+fn print_report_csv(group_by: &[GroupBy], rows: &BTreeMap<Vec<String>, ReportRow>) {
+    let currencies: BTreeSet<String> = rows
+        .values()
+        .flat_map(|r| r.cost.currencies().map(str::to_string).collect::<Vec<_>>())
+        .collect();
+    let mut header: Vec<String> = group_by.iter().map(|g| g.name().to_string()).collect();
+    header.extend(
+        [
+            "requests",
+            "success_rate",
+            "p50_ms",
+            "p95_ms",
+            "retries",
+            "input_tokens",
+            "cached_input_tokens",
+            "output_tokens",
+            "reasoning_tokens",
+            "total_tokens",
+        ]
+        .map(str::to_string),
+    );
+    header.extend(currencies.iter().map(|c| format!("cost_{c}")));
+    println!("{}", header.join(","));
+
+    for (key, row) in rows {
+        let mut fields: Vec<String> = key.iter().map(|k| csv_field(k)).collect();
+        fields.extend([
+            row.requests.to_string(),
+            format!("{:.4}", row.success_rate()),
+            row.percentile_ms(0.5).to_string(),
+            row.percentile_ms(0.95).to_string(),
+            row.retries.to_string(),
+            row.usage.input_tokens.to_string(),
+            row.usage.cached_input_tokens.to_string(),
+            row.usage.output_tokens.to_string(),
+            row.usage.reasoning_tokens.to_string(),
+            row.usage.total_tokens.to_string(),
+        ]);
+        fields.extend(currencies.iter().map(|c| {
+            row.cost
+                .get(c)
+                .map(|amount| format!("{amount:.6}"))
+                .unwrap_or_default()
+        }));
+        println!("{}", fields.join(","));
+    }
+}
+
+fn print_report_table(
+    group_by: &[GroupBy],
+    rows: &BTreeMap<Vec<String>, ReportRow>,
+    since: Option<&str>,
+    file_count: usize,
+) {
+    println!(
+        "{}",
+        format!(
+            "Usage report ({}, {} log file(s))",
+            since
+                .map(|s| format!("last {s}"))
+                .unwrap_or_else(|| "all logs".to_string()),
+            file_count
+        )
+        .bold()
+    );
+    if rows.is_empty() {
+        println!("No matching requests.");
+        return;
+    }
+
+    let fmt_row = |row: &ReportRow| {
+        vec![
+            row.requests.to_string(),
+            format!("{:.1}%", row.success_rate() * 100.0),
+            row.percentile_ms(0.5).to_string(),
+            row.percentile_ms(0.95).to_string(),
+            row.retries.to_string(),
+            row.usage.input_tokens.to_string(),
+            row.usage.cached_input_tokens.to_string(),
+            row.usage.output_tokens.to_string(),
+            row.usage.total_tokens.to_string(),
+            row.cost.display().unwrap_or_else(|| "-".to_string()),
+        ]
+    };
+    let mut header: Vec<String> = group_by.iter().map(|g| g.name().to_string()).collect();
+    header.extend(
+        [
+            "requests", "success", "p50_ms", "p95_ms", "retries", "input", "cached", "output",
+            "total", "est_cost",
+        ]
+        .map(str::to_string),
+    );
+    let mut lines: Vec<Vec<String>> = rows
+        .iter()
+        .map(|(key, row)| {
+            let mut cells = key.clone();
+            cells.extend(fmt_row(row));
+            cells
+        })
+        .collect();
+    if rows.len() > 1 {
+        let mut total = ReportRow::default();
+        for row in rows.values() {
+            total.merge(row);
+        }
+        total.finish();
+        let mut cells = vec!["-".to_string(); group_by.len()];
+        if let Some(first) = cells.first_mut() {
+            *first = "total".to_string();
+        }
+        cells.extend(fmt_row(&total));
+        lines.push(cells);
+    }
+
+    let mut widths: Vec<usize> = header.iter().map(|h| h.chars().count()).collect();
+    for cells in &lines {
+        for (w, c) in widths.iter_mut().zip(cells) {
+            *w = (*w).max(c.chars().count());
+        }
+    }
+    let render = |cells: &[String]| {
+        cells
+            .iter()
+            .zip(&widths)
+            .enumerate()
+            .map(|(i, (c, w))| {
+                // Group columns left-aligned, numbers right-aligned.
+                if i < group_by.len() {
+                    format!("{c:<w$}")
+                } else {
+                    format!("{c:>w$}")
+                }
+            })
+            .collect::<Vec<_>>()
+            .join("  ")
+    };
+    println!("{}", render(&header).bold());
+    for cells in &lines {
+        println!("{}", render(cells));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_relative_durations() {
+        assert_eq!(parse_duration_ms("30m").unwrap(), 30 * 60_000);
+        assert_eq!(parse_duration_ms(" 7d ").unwrap(), 7 * 86_400_000);
+        assert_eq!(parse_duration_ms("2w").unwrap(), 14 * 86_400_000);
+        assert!(parse_duration_ms("7").is_err());
+        assert!(parse_duration_ms("7y").is_err());
+        assert!(parse_duration_ms("d").is_err());
+    }
+
+    #[test]
+    fn report_groups_lines_with_latency_retries_and_cost() {
+        let day1 = 19_797u64 * 86_400_000; // 2024-03-15
+        let day2 = day1 + 86_400_000;
+        let lines = [
+            json!({"timestamp_ms": day1 + 1, "service": "codex", "status_code": 200, "duration_ms": 100,
+                "model": "gpt-5", "usage": {"input_tokens": 10, "output_tokens": 5, "total_tokens": 15},
+                "cost": {"amount": 0.5, "currency": "USD"}}),
+            json!({"timestamp_ms": day1 + 2, "service": "codex", "status_code": 502, "duration_ms": 900,
+                "model": "gpt-5", "retry": {"attempts": 3, "upstream_chain": []}}),
+            json!({"timestamp_ms": day1 + 3, "service": "codex", "status_code": 200, "duration_ms": 300,
+                "model": "gpt-5", "usage": {"input_tokens": 20, "cached_input_tokens": 8, "output_tokens": 5, "total_tokens": 25},
+                "cost": {"amount": 0.25, "currency": "USD"}}),
+            json!({"timestamp_ms": day2, "service": "codex", "status_code": 200, "duration_ms": 50}),
+            json!({"timestamp_ms": day2, "service": "claude", "status_code": 200, "duration_ms": 50}),
+            json!({"timestamp_ms": day1 - 1, "service": "codex", "status_code": 200, "duration_ms": 50}),
+        ]
+        .map(|v| v.to_string());
+
+        let group_by = GroupBy::parse_list("day, model,day").unwrap();
+        assert_eq!(group_by, vec![GroupBy::Day, GroupBy::Model]);
+        let rows = aggregate(lines.into_iter(), &group_by, Some(day1), Some("codex"));
+        assert_eq!(
+            rows.keys().cloned().collect::<Vec<_>>(),
+            vec![
+                vec!["2024-03-15".to_string(), "gpt-5".to_string()],
+                vec!["2024-03-16".to_string(), "-".to_string()],
+            ]
+        );
+
+        let row = &rows[&vec!["2024-03-15".to_string(), "gpt-5".to_string()]];
+        assert_eq!(row.requests, 3);
+        assert_eq!(row.ok, 2);
+        assert_eq!(row.retries, 2);
+        assert_eq!(row.percentile_ms(0.5), 300);
+        assert_eq!(row.percentile_ms(0.95), 900);
+        assert_eq!(row.usage.total_tokens, 40);
+        assert_eq!(row.usage.cached_input_tokens, 8);
+        assert_eq!(row.cost.get("USD"), Some(0.75));
+
+        assert!(GroupBy::parse_list("day,team").is_err());
+        assert_eq!(csv_field("a,b"), "\"a,b\"");
+        assert_eq!(csv_field("say \"hi\""), "\"say \"\"hi\"\"\"");
+    }
+}
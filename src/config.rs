@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::env;
 use std::fs as stdfs;
 use std::path::{Path, PathBuf};
@@ -11,6 +11,8 @@ use tokio::fs;
 use toml::Value as TomlValue;
 use tracing::{info, warn};
 
+use crate::body_rules::BodyRule;
+
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
 pub struct UpstreamAuth {
     /// Bearer token, e.g. OpenAI style
@@ -25,10 +27,130 @@ pub struct UpstreamAuth {
     /// Environment variable name for API key header value
     #[serde(skip_serializing_if = "Option::is_none")]
     pub api_key_env: Option<String>,
+    /// File containing the bearer token (e.g. mounted by a secret manager)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_token_file: Option<String>,
+    /// Command printing the bearer token on its first stdout line, e.g. `["pass", "show", "relay/key"]`
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auth_token_cmd: Vec<String>,
+    /// File containing the API key header value
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_file: Option<String>,
+    /// Command printing the API key header value on its first stdout line
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub api_key_cmd: Vec<String>,
+    /// How long `*_cmd` output is cached, in seconds (default 300)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cmd_cache_ttl_secs: Option<u64>,
+    /// Name of a bearer token in the encrypted secret store (`ch secrets set <name>`)
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub auth_token_secret: Option<String>,
+    /// Name of an API key header value in the encrypted secret store
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub api_key_secret: Option<String>,
+    /// Additional bearer tokens for a key pool (rotated together with `auth_token` / `auth_token_env`).
+    ///
+    /// Like a single configured token, a pool key replaces the client's `Authorization` header, so
+    /// client passthrough only happens when no pool key resolves; the request log's auth resolution
+    /// then reads `pool:<key> (client_auth_overridden)`.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auth_tokens: Vec<String>,
+    /// Additional environment variable names for a bearer token pool.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auth_token_envs: Vec<String>,
+    /// Additional bearer tokens from the encrypted secret store for a key pool.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub auth_token_secrets: Vec<String>,
+    /// How the proxy picks a key from the pool (default: `round_robin`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_rotation: Option<KeyRotation>,
+}
+
+/// 多 key 池的轮换策略。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum KeyRotation {
+    /// Spread requests across all available keys.
+    #[default]
+    RoundRobin,
+    /// Stick to one key and only move on after it hits 429 / quota errors.
+    OnError,
+}
+
+/// Where a bearer token in the pool comes from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthKeySource {
+    Inline(String),
+    Env(String),
+    Secret(String),
+    File(String),
+    Cmd(Vec<String>),
+}
+
+/// Short id for a pooled inline token: the first 4 bytes of its SHA-256. Unlike a list index it
+/// stays put when other keys are added or removed, so cooldowns and rollups follow the key.
+fn inline_key_id(token: &str) -> String {
+    let digest = ring::digest::digest(&ring::digest::SHA256, token.trim().as_bytes());
+    digest.as_ref()[..4]
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// Resolves a `*_secret` / `*_file` / `*_cmd` secret; the second value is a log-safe source label.
+async fn resolve_external_secret(
+    secret: Option<&str>,
+    file: Option<&str>,
+    cmd: &[String],
+    cmd_ttl_secs: u64,
+) -> Option<(String, String)> {
+    if let Some(name) = secret.map(str::trim).filter(|n| !n.is_empty())
+        && let Some(v) = crate::secrets::get_secret(name)
+    {
+        return Some((v, format!("secret:{name}")));
+    }
+    if let Some(path) = file
+        && let Some(v) = crate::credentials::read_secret_file(path)
+    {
+        return Some((v, format!("file:{path}")));
+    }
+    if !cmd.is_empty()
+        && let Some(v) = crate::credentials::run_secret_command(cmd, cmd_ttl_secs).await
+    {
+        return Some((v, format!("cmd:{}", cmd[0])));
+    }
+    None
 }
 
 impl UpstreamAuth {
-    pub fn resolve_auth_token(&self) -> Option<String> {
+    fn cmd_cache_ttl(&self) -> u64 {
+        self.cmd_cache_ttl_secs
+            .unwrap_or(crate::credentials::DEFAULT_CMD_CACHE_TTL_SECS)
+    }
+
+    /// Bearer token from `auth_token_secret` / `auth_token_file` / `auth_token_cmd`, with its source label.
+    pub async fn resolve_external_auth_token(&self) -> Option<(String, String)> {
+        resolve_external_secret(
+            self.auth_token_secret.as_deref(),
+            self.auth_token_file.as_deref(),
+            &self.auth_token_cmd,
+            self.cmd_cache_ttl(),
+        )
+        .await
+    }
+
+    /// API key from `api_key_secret` / `api_key_file` / `api_key_cmd`, with its source label.
+    pub async fn resolve_external_api_key(&self) -> Option<(String, String)> {
+        resolve_external_secret(
+            self.api_key_secret.as_deref(),
+            self.api_key_file.as_deref(),
+            &self.api_key_cmd,
+            self.cmd_cache_ttl(),
+        )
+        .await
+    }
+
+    pub async fn resolve_auth_token(&self) -> Option<String> {
         if let Some(token) = self.auth_token.as_deref()
             && !token.trim().is_empty()
         {
@@ -40,10 +162,123 @@ impl UpstreamAuth {
         {
             return Some(v);
         }
+        if let Some((v, _)) = self.resolve_external_auth_token().await {
+            return Some(v);
+        }
+        for (_, src) in self.token_pool() {
+            if let Some(v) = self.resolve_pool_key(&src).await {
+                return Some(v);
+            }
+        }
         None
     }
 
-    pub fn resolve_api_key(&self) -> Option<String> {
+    pub fn has_token_pool(&self) -> bool {
+        !self.auth_tokens.is_empty()
+            || !self.auth_token_envs.is_empty()
+            || !self.auth_token_secrets.is_empty()
+    }
+
+    /// Current value of one key from [`Self::token_pool`], if it resolves.
+    pub async fn resolve_pool_key(&self, src: &AuthKeySource) -> Option<String> {
+        match src {
+            AuthKeySource::Inline(v) => Some(v.clone()),
+            AuthKeySource::Env(name) => env::var(name).ok(),
+            AuthKeySource::Secret(name) => crate::secrets::get_secret(name),
+            AuthKeySource::File(path) => crate::credentials::read_secret_file(path),
+            AuthKeySource::Cmd(argv) => {
+                crate::credentials::run_secret_command(argv, self.cmd_cache_ttl()).await
+            }
+        }
+        .filter(|v| !v.trim().is_empty())
+    }
+
+    /// All bearer tokens configured for this upstream as `(key_id, source)`, in config order.
+    ///
+    /// `key_id` (`inline`, `inline:HASH`, `env:NAME`, `secret:NAME`, `file:PATH`, `cmd:PROGRAM`) is
+    /// what LB state, logs and usage rollups use to refer to a key, so the secret itself never
+    /// leaves the auth header. The single-token fields (`auth_token*`) count as pool keys too.
+    pub fn token_pool(&self) -> Vec<(String, AuthKeySource)> {
+        fn push(out: &mut Vec<(String, AuthKeySource)>, id: String, src: AuthKeySource) {
+            if !out.iter().any(|(k, _)| *k == id) {
+                out.push((id, src));
+            }
+        }
+        fn named(name: Option<&str>) -> Option<&str> {
+            name.map(str::trim).filter(|n| !n.is_empty())
+        }
+
+        let mut out = Vec::new();
+        if let Some(token) = self.auth_token.as_deref()
+            && !token.trim().is_empty()
+        {
+            push(
+                &mut out,
+                "inline".to_string(),
+                AuthKeySource::Inline(token.to_string()),
+            );
+        }
+        if let Some(name) = named(self.auth_token_env.as_deref()) {
+            push(
+                &mut out,
+                format!("env:{name}"),
+                AuthKeySource::Env(name.to_string()),
+            );
+        }
+        if let Some(name) = named(self.auth_token_secret.as_deref()) {
+            push(
+                &mut out,
+                format!("secret:{name}"),
+                AuthKeySource::Secret(name.to_string()),
+            );
+        }
+        if let Some(path) = named(self.auth_token_file.as_deref()) {
+            push(
+                &mut out,
+                format!("file:{path}"),
+                AuthKeySource::File(path.to_string()),
+            );
+        }
+        if let Some(program) = self.auth_token_cmd.first()
+            && !program.trim().is_empty()
+        {
+            push(
+                &mut out,
+                format!("cmd:{program}"),
+                AuthKeySource::Cmd(self.auth_token_cmd.clone()),
+            );
+        }
+        for token in &self.auth_tokens {
+            if !token.trim().is_empty() {
+                push(
+                    &mut out,
+                    format!("inline:{}", inline_key_id(token)),
+                    AuthKeySource::Inline(token.clone()),
+                );
+            }
+        }
+        for name in &self.auth_token_envs {
+            if let Some(name) = named(Some(name)) {
+                push(
+                    &mut out,
+                    format!("env:{name}"),
+                    AuthKeySource::Env(name.to_string()),
+                );
+            }
+        }
+        for name in &self.auth_token_secrets {
+            if let Some(name) = named(Some(name)) {
+                push(
+                    &mut out,
+                    format!("secret:{name}"),
+                    AuthKeySource::Secret(name.to_string()),
+                );
+            }
+        }
+        out
+    }
+
+    pub async fn resolve_api_key(&self) -> Option<String> {
         if let Some(key) = self.api_key.as_deref()
             && !key.trim().is_empty()
         {
@@ -55,7 +290,7 @@ impl UpstreamAuth {
         {
             return Some(v);
         }
-        None
+        self.resolve_external_api_key().await.map(|(v, _)| v)
     }
 }
 
@@ -81,6 +316,28 @@ pub struct UpstreamConfig {
         alias = "modelMapping"
     )]
     pub model_mapping: HashMap<String, String>,
+    /// Optional DNS overrides: hostname -> one or more IPs (like curl `--resolve`).
+    /// The request still uses the original hostname for TLS SNI and the `Host` header.
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    pub resolve: HashMap<String, Vec<String>>,
+    /// Optional address family preference when connecting to this upstream.
+    #[serde(
+        default,
+        skip_serializing_if = "Option::is_none",
+        alias = "ipPreference"
+    )]
+    pub ip_preference: Option<IpPreference>,
+    /// Request JSON rewrites for this upstream only.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub body_rules: Vec<BodyRule>,
+}
+
+/// 连接上游时优先使用的 IP 协议族。
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum IpPreference {
+    Ipv4,
+    Ipv6,
 }
 
 pub fn model_routing_warnings(cfg: &ProxyConfig, service_name: &str) -> Vec<String> {
@@ -151,6 +408,34 @@ pub fn model_routing_warnings(cfg: &ProxyConfig, service_name: &str) -> Vec<Stri
     warnings
 }
 
+pub fn body_rule_warnings(cfg: &ProxyConfig, service_name: &str) -> Vec<String> {
+    let mgr = match service_name {
+        "claude" => &cfg.claude,
+        _ => &cfg.codex,
+    };
+    let mut names: Vec<&String> = mgr.configs.keys().collect();
+    names.sort();
+    let mut warnings = Vec::new();
+    for cfg_name in names {
+        let svc = &mgr.configs[cfg_name];
+        let scopes = std::iter::once((format!("{service_name}:{cfg_name}"), &svc.body_rules))
+            .chain(svc.upstreams.iter().enumerate().map(|(idx, u)| {
+                (
+                    format!("{service_name}:{cfg_name} upstream[{idx}]"),
+                    &u.body_rules,
+                )
+            }));
+        for (scope, rules) in scopes {
+            for (i, rule) in rules.iter().enumerate() {
+                if let Some(problem) = rule.problem() {
+                    warnings.push(format!("[{scope}] body_rules[{i}] 将被忽略：{problem}"));
+                }
+            }
+        }
+    }
+    warnings
+}
+
 /// A logical config entry (roughly corresponds to cli_proxy 的一个配置名)
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ServiceConfig {
@@ -167,6 +452,29 @@ pub struct ServiceConfig {
     pub level: u8,
     #[serde(default)]
     pub upstreams: Vec<UpstreamConfig>,
+    /// Request JSON rewrites applied to every upstream of this config (before upstream-level rules).
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub body_rules: Vec<BodyRule>,
+    /// Usage limits; a config over budget leaves automatic routing until the period rolls over.
+    #[serde(default, skip_serializing_if = "BudgetConfig::is_empty")]
+    pub budget: BudgetConfig,
+}
+
+/// 配置级用量预算（按 UTC 自然日 / 自然月计算）。
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct BudgetConfig {
+    /// Maximum total tokens per UTC day.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub daily_tokens: Option<u64>,
+    /// Maximum estimated cost per UTC calendar month, in USD.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub monthly_cost_usd: Option<f64>,
+}
+
+impl BudgetConfig {
+    pub fn is_empty(&self) -> bool {
+        self.daily_tokens.is_none() && self.monthly_cost_usd.is_none()
+    }
 }
 
 fn default_service_config_enabled() -> bool {
@@ -208,6 +516,13 @@ pub struct RetryConfig {
     pub cloudflare_challenge_cooldown_secs: u64,
     pub cloudflare_timeout_cooldown_secs: u64,
     pub transport_cooldown_secs: u64,
+    /// Cooldown for a single pooled key after a 429 / quota error (used when `Retry-After` is absent).
+    #[serde(default = "default_key_cooldown_secs")]
+    pub key_cooldown_secs: u64,
+}
+
+fn default_key_cooldown_secs() -> u64 {
+    60
 }
 
 impl Default for RetryConfig {
@@ -226,6 +541,7 @@ impl Default for RetryConfig {
             cloudflare_challenge_cooldown_secs: 300,
             cloudflare_timeout_cooldown_secs: 60,
             transport_cooldown_secs: 30,
+            key_cooldown_secs: default_key_cooldown_secs(),
         }
     }
 }
@@ -310,6 +626,305 @@ pub struct ProxyConfig {
     /// UI settings (mainly for the built-in TUI).
     #[serde(default)]
     pub ui: UiConfig,
+    /// Proxy listener settings (bind address and access control).
+    #[serde(default)]
+    pub server: ServerConfig,
+    /// Per-project settings keyed on the session's working directory.
+    #[serde(default)]
+    pub projects: ProjectsConfig,
+    /// Token prices used to estimate the cost of each request.
+    #[serde(default, skip_serializing_if = "PricingConfig::is_empty")]
+    pub pricing: PricingConfig,
+    /// Usage accounting (per-day rollups behind the Stats page and budgets).
+    #[serde(default, skip_serializing_if = "UsageConfig::is_empty")]
+    pub usage: UsageConfig,
+}
+
+/// 用量统计设置。
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct UsageConfig {
+    /// Days of per-day rollups kept in memory and on disk (default 60, at least 31 so monthly
+    /// budgets see the whole month). `CODEX_HELPER_USAGE_ROLLUP_KEEP_DAYS` overrides it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rollup_keep_days: Option<u32>,
+}
+
+impl UsageConfig {
+    pub fn is_empty(&self) -> bool {
+        self.rollup_keep_days.is_none()
+    }
+}
+
+/// 价格表：按模型（可选再按 provider_id）匹配，用于估算每个请求的费用。
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct PricingConfig {
+    /// Currency for entries that do not set their own; defaults to `USD`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+    /// Checked in order; the first entry matching the model (and provider, if set) wins.
+    #[serde(default)]
+    pub models: Vec<ModelPrice>,
+}
+
+impl PricingConfig {
+    pub fn is_empty(&self) -> bool {
+        self.currency.is_none() && self.models.is_empty()
+    }
+}
+
+/// Prices per million tokens for one model pattern (`*` wildcard, as in `model_mapping`).
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+pub struct ModelPrice {
+    pub model: String,
+    /// Only applies to upstreams tagged with this `provider_id`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub provider_id: Option<String>,
+    #[serde(default)]
+    pub input_per_1m: f64,
+    /// Price for cached input tokens; defaults to `input_per_1m`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cached_input_per_1m: Option<f64>,
+    /// Price for tokens written to the prompt cache (Anthropic); defaults to `input_per_1m`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub cache_write_per_1m: Option<f64>,
+    #[serde(default)]
+    pub output_per_1m: f64,
+    /// Price for reasoning tokens (part of the output); defaults to `output_per_1m`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_per_1m: Option<f64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub currency: Option<String>,
+}
+
+/// 按会话工作目录（cwd）生效的项目设置。
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProjectsConfig {
+    /// Read project files from the repository (`.codex-helper.toml` and
+    /// `.codex-helper/instructions.md`, found by walking up from the cwd to the repo root).
+    /// Default: only inside a directory matched by a `[[projects.rules]]` path, so a freshly
+    /// cloned repo cannot change prompts or routing; `true` reads them in every repo.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repo_files: Option<bool>,
+    /// Rules matched against the cwd in order; every matching rule applies.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub rules: Vec<ProjectRule>,
+}
+
+impl ProjectsConfig {
+    /// Whether repo files are read for a cwd; `trusted` is whether a config rule matches it.
+    pub fn repo_files_enabled(&self, trusted: bool) -> bool {
+        self.repo_files.unwrap_or(trusted)
+    }
+}
+
+/// One `[[projects.rules]]` entry; a repo's `.codex-helper.toml` has the same fields minus `path`.
+///
+/// When several rules match, the first one setting a field wins (config rules before the repo
+/// file); instructions from all of them are combined.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ProjectRule {
+    /// Glob on the cwd: `~/` is expanded, `*` stays within one path segment, `**` spans several.
+    /// A rule also covers every directory below what it matches.
+    #[serde(default)]
+    pub path: String,
+    /// Route the project's requests through this config (a TUI session pin still wins).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub config: Option<String>,
+    /// Per-config `level` overrides for level-based routing inside this project.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub levels: BTreeMap<String, u8>,
+    /// Model to send instead of the one the client asked for.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    /// `reasoning.effort` to send (a TUI session effort override still wins).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub reasoning_effort: Option<String>,
+    /// Text placed before the request's `instructions` (Responses API only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions_prepend: Option<String>,
+    /// Text placed after the request's `instructions` (Responses API only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub instructions_append: Option<String>,
+}
+
+/// 代理监听与访问控制配置。
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct ServerConfig {
+    /// Listen IP address (default `127.0.0.1`); use `0.0.0.0` / `::` to accept LAN clients.
+    /// A specific non-loopback address requires `admin_token`, since local tools then connect
+    /// from that address rather than loopback.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub listen: Option<String>,
+    /// Token clients must send (`X-Codex-Helper-Token` or `Authorization: Bearer`) on proxy routes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_token: Option<String>,
+    /// Environment variable holding the client token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub client_token_env: Option<String>,
+    /// Token required on `/__codex_helper/*` (`X-Codex-Helper-Admin-Token` or `Authorization: Bearer`).
+    ///
+    /// When unset, admin endpoints only answer loopback clients (and Unix socket clients with
+    /// `trust_unix_socket`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_token: Option<String>,
+    /// Environment variable holding the admin token.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub admin_token_env: Option<String>,
+    /// Client IPs / CIDR ranges allowed to connect (loopback is always allowed). Empty = no filter.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allowed_ips: Vec<String>,
+    /// PEM certificate chain; with `tls_key` set the TCP listener serves HTTPS instead of HTTP.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_cert: Option<String>,
+    /// PEM private key matching `tls_cert`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_key: Option<String>,
+    /// Host name written into client base URLs when TLS is on; it must match the certificate.
+    /// Defaults to `localhost` for loopback / wildcard listen addresses.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tls_host: Option<String>,
+    /// Additionally listen on this Unix domain socket (Unix only), e.g. on a volume shared with containers.
+    ///
+    /// The socket is created with mode `unix_socket_mode`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unix_socket: Option<String>,
+    /// Octal permission bits for `unix_socket`, e.g. "0660" to let a shared group connect
+    /// (default "0600", owner only).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unix_socket_mode: Option<String>,
+    /// Treat Unix socket clients like loopback ones, i.e. allow admin endpoints without
+    /// `admin_token` (default false).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub trust_unix_socket: Option<bool>,
+    /// Seconds to let in-flight requests finish after Ctrl+C before exiting (default 30).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub drain_timeout_secs: Option<u64>,
+    /// Largest client request body accepted, in bytes (default 10 MiB).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_request_body_bytes: Option<usize>,
+}
+
+fn resolve_inline_or_env(inline: Option<&str>, env_name: Option<&str>) -> Option<String> {
+    if let Some(v) = inline
+        && !v.trim().is_empty()
+    {
+        return Some(v.to_string());
+    }
+    env_name
+        .and_then(|name| env::var(name).ok())
+        .filter(|v| !v.trim().is_empty())
+}
+
+impl ServerConfig {
+    pub fn listen_ip(&self) -> Result<std::net::IpAddr> {
+        match self.listen.as_deref().map(str::trim) {
+            None | Some("") => Ok(std::net::IpAddr::from([127, 0, 0, 1])),
+            Some(s) => s
+                .trim_start_matches('[')
+                .trim_end_matches(']')
+                .parse()
+                .with_context(|| format!("invalid server.listen address '{s}'")),
+        }
+    }
+
+    /// Certificate and key paths when TLS is configured; setting only one of them is an error.
+    pub fn tls_files(&self) -> Result<Option<(PathBuf, PathBuf)>> {
+        let non_empty = |v: &Option<String>| {
+            v.as_deref()
+                .map(str::trim)
+                .filter(|s| !s.is_empty())
+                .map(crate::credentials::expand_home)
+        };
+        match (non_empty(&self.tls_cert), non_empty(&self.tls_key)) {
+            (Some(cert), Some(key)) => Ok(Some((cert, key))),
+            (None, None) => Ok(None),
+            _ => anyhow::bail!("server.tls_cert and server.tls_key must be set together"),
+        }
+    }
+
+    pub fn max_request_body_bytes(&self) -> usize {
+        self.max_request_body_bytes
+            .filter(|&n| n > 0)
+            .unwrap_or(10 * 1024 * 1024)
+    }
+
+    pub fn drain_timeout(&self) -> std::time::Duration {
+        std::time::Duration::from_secs(self.drain_timeout_secs.unwrap_or(30))
+    }
+
+    pub fn unix_socket_path(&self) -> Option<PathBuf> {
+        self.unix_socket
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+            .map(crate::credentials::expand_home)
+    }
+
+    /// Base URL local clients (Codex, the TUI, `notify`) should use to reach the proxy.
+    ///
+    /// Wildcard listen addresses map to loopback; a specific address is used as-is.
+    /// With TLS the scheme is `https` and loopback is replaced by `tls_host` (default `localhost`),
+    /// since certificates are rarely issued for bare IPs.
+    pub fn local_base_url(&self, port: u16) -> String {
+        if matches!(self.tls_files(), Ok(Some(_))) {
+            let host = match (self.tls_host.as_deref().map(str::trim), self.listen_ip()) {
+                (Some(h), _) if !h.is_empty() => h.to_string(),
+                (_, Ok(std::net::IpAddr::V4(ip))) if !ip.is_loopback() && !ip.is_unspecified() => {
+                    ip.to_string()
+                }
+                (_, Ok(std::net::IpAddr::V6(ip))) if !ip.is_loopback() && !ip.is_unspecified() => {
+                    format!("[{ip}]")
+                }
+                _ => "localhost".to_string(),
+            };
+            return format!("https://{host}:{port}");
+        }
+        let ip = match self.listen_ip() {
+            Ok(ip) if ip.is_unspecified() => {
+                if ip.is_ipv4() {
+                    std::net::IpAddr::from([127, 0, 0, 1])
+                } else {
+                    std::net::IpAddr::from(std::net::Ipv6Addr::LOCALHOST)
+                }
+            }
+            Ok(ip) => ip,
+            Err(_) => std::net::IpAddr::from([127, 0, 0, 1]),
+        };
+        format!("http://{}", std::net::SocketAddr::new(ip, port))
+    }
+
+    pub fn unix_socket_mode(&self) -> Result<u32> {
+        let Some(raw) = self
+            .unix_socket_mode
+            .as_deref()
+            .map(str::trim)
+            .filter(|s| !s.is_empty())
+        else {
+            return Ok(0o600);
+        };
+        let digits = raw.strip_prefix("0o").unwrap_or(raw);
+        match u32::from_str_radix(digits, 8) {
+            Ok(mode) if mode <= 0o777 => Ok(mode),
+            _ => anyhow::bail!(
+                "server.unix_socket_mode = {raw:?} is not an octal permission like \"0660\""
+            ),
+        }
+    }
+
+    pub fn trust_unix_socket(&self) -> bool {
+        self.trust_unix_socket.unwrap_or(false)
+    }
+
+    pub fn resolve_client_token(&self) -> Option<String> {
+        resolve_inline_or_env(
+            self.client_token.as_deref(),
+            self.client_token_env.as_deref(),
+        )
+    }
+
+    pub fn resolve_admin_token(&self) -> Option<String> {
+        resolve_inline_or_env(self.admin_token.as_deref(), self.admin_token_env.as_deref())
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, Default)]
@@ -409,6 +1024,11 @@ version = 1
 # alias = "primary+backup"
 # # enabled = true
 # # level = 1
+# # Optional budget: once reached, this config leaves automatic routing until the period rolls
+# # over (UTC day / month). The cost limit counts USD prices from [pricing].
+# # [codex.configs.codex-main.budget]
+# # daily_tokens = 2000000
+# # monthly_cost_usd = 50.0
 #
 # # Primary upstream
 # [[codex.configs.codex-main.upstreams]]
@@ -416,6 +1036,10 @@ version = 1
 # [codex.configs.codex-main.upstreams.auth]
 # auth_token_env = "OPENAI_API_KEY"
 # # or: api_key_env = "OPENAI_API_KEY"
+# # or read it from a secret manager (stdout first line; cached for cmd_cache_ttl_secs, default 300):
+# # auth_token_cmd = ["pass", "show", "openai/api-key"]
+# # or from a mounted file: auth_token_file = "/run/secrets/openai_api_key"
+# # or from the encrypted local store (`ch secrets set openai`): auth_token_secret = "openai"
 # # (not recommended) auth_token = "sk-..."
 # [codex.configs.codex-main.upstreams.tags]
 # provider_id = "openai"
@@ -423,10 +1047,29 @@ version = 1
 # # Backup upstream
 # [[codex.configs.codex-main.upstreams]]
 # base_url = "https://your-backup-provider.example/v1"
+# # Optional: prefer one address family when connecting ("ipv4" / "ipv6").
+# # ip_preference = "ipv4"
 # [codex.configs.codex-main.upstreams.auth]
 # auth_token_env = "BACKUP_API_KEY"
+# # Optional key pool: rotate across several keys of the same account.
+# # auth_token_envs = ["BACKUP_API_KEY_2", "BACKUP_API_KEY_3"]
+# # auth_token_secrets = ["backup-3"]   # keys from `ch secrets set`; `ch secrets migrate` moves auth_tokens here
+# # key_rotation = "round_robin"   # or "on_error" (stick to one key until 429/quota)
 # [codex.configs.codex-main.upstreams.tags]
 # provider_id = "backup"
+# # Optional: pin the hostname to specific edge IPs (like curl `--resolve`); SNI/Host stay unchanged.
+# # [codex.configs.codex-main.upstreams.resolve]
+# # "your-backup-provider.example" = ["203.0.113.10", "203.0.113.11"]
+# # Optional: rewrite the request JSON for this upstream (op: set / remove / rename / default / cap).
+# # `body_rules` can also sit on the config itself, applying to all of its upstreams.
+# # [[codex.configs.codex-main.upstreams.body_rules]]
+# # op = "remove"
+# # path = "/parallel_tool_calls"
+# # [[codex.configs.codex-main.upstreams.body_rules]]
+# # op = "cap"
+# # path = "/max_output_tokens"
+# # value = 32000
+# # models = ["gpt-5*"]
 #
 # Claude configs share the same structure under [claude].
 #
@@ -503,6 +1146,102 @@ on_class = ["upstream_transport_error", "cloudflare_timeout", "cloudflare_challe
 cloudflare_challenge_cooldown_secs = 300
 cloudflare_timeout_cooldown_secs = 60
 transport_cooldown_secs = 30
+# Cooldown for one key of a multi-key pool after 429 / quota errors (Retry-After wins when present).
+key_cooldown_secs = 60
+
+# ---
+#
+# --- Listener / access control ---
+#
+# By default the proxy listens on 127.0.0.1 and accepts any local client.
+# Changing `listen` needs a restart; tokens and the allowlist are re-read on config reload.
+#
+# [server]
+# # Listen address; "0.0.0.0" shares the proxy with the LAN (set client_token when you do).
+# # A single LAN address (e.g. "192.168.1.5") also needs admin_token: local tools then connect from it.
+# listen = "127.0.0.1"
+# # Required on proxy routes; `switch on` writes it into Codex / Claude config automatically.
+# client_token_env = "CODEX_HELPER_CLIENT_TOKEN"
+# # Required on /__codex_helper/* admin endpoints (without it they only answer loopback clients).
+# admin_token_env = "CODEX_HELPER_ADMIN_TOKEN"
+# # Client IPs / CIDRs allowed to connect (loopback is always allowed).
+# allowed_ips = ["192.168.1.0/24"]
+# # Serve HTTPS with your own certificate; `switch on` then writes an https:// base_url.
+# tls_cert = "~/.codex-helper/tls/cert.pem"
+# tls_key = "~/.codex-helper/tls/key.pem"
+# tls_host = "devbox.example.internal"
+# # Extra Unix domain socket listener, e.g. on a volume shared with dev containers.
+# unix_socket = "/var/run/codex-helper/proxy.sock"
+# # Socket permissions (octal, default "0600"); "0660" lets the socket's group connect too.
+# unix_socket_mode = "0600"
+# # Socket clients need admin_token for /__codex_helper/* unless you trust everyone who can open it.
+# trust_unix_socket = false
+# # On Ctrl+C, wait this long for in-flight requests / streams; a second Ctrl+C quits at once.
+# drain_timeout_secs = 30
+# # Largest accepted request body; bodies that need no rewriting are streamed (or spooled to a
+# # temp file when retries are on) instead of being held in memory.
+# max_request_body_bytes = 67108864
+
+# ---
+#
+# --- Projects (matched on the Codex session's working directory) ---
+#
+# Per-project routing and extra instructions for `/responses` requests. A repository can also
+# ship `.codex-helper.toml` (same fields as a rule, without `path`) and
+# `.codex-helper/instructions.md` (appended); both are looked up from the cwd to the repo root.
+# When several rules match, the first one setting a field wins; config rules beat repo files.
+#
+# [projects]
+# # `.codex-helper.toml` / `.codex-helper/instructions.md` in repositories are only read below a
+# # `[[projects.rules]]` path by default; true reads them in every repo, false never.
+# # repo_files = true
+#
+# [[projects.rules]]
+# # `*` matches within one directory name, `**` across several; subdirectories match too.
+# path = "~/work/acme-*"
+# # Always use this config (a session pin from the TUI still wins).
+# config = "company-relay"
+# # Or keep level routing but reorder configs for this project:
+# # levels = { company-relay = 1, cheap-relay = 5 }
+# # model = "gpt-5"
+# # reasoning_effort = "high"
+# instructions_append = """
+# Follow the ACME coding standards: no unwrap() in library code, every public fn documented.
+# """
+#
+# [[projects.rules]]
+# path = "~/personal/**"
+# config = "cheap-relay"
+#
+# --- Pricing (cost estimates in the TUI Stats page, request logs and `usage summary`) ---
+#
+# Prices are per million tokens. Entries are checked in order against the model sent upstream
+# (`*` wildcard); the first match wins. `provider_id` restricts an entry to upstreams with that tag.
+#
+# [pricing]
+# currency = "USD"
+#
+# [[pricing.models]]
+# model = "gpt-5*"
+# provider_id = "cheap-relay"
+# input_per_1m = 2.0
+# output_per_1m = 8.0
+# currency = "CNY"
+#
+# [[pricing.models]]
+# model = "gpt-5*"
+# input_per_1m = 1.25
+# cached_input_per_1m = 0.125
+# # cache_write_per_1m = 1.5625   # Anthropic cache writes; defaults to input_per_1m
+# output_per_1m = 10.0
+# # reasoning_per_1m = 10.0   # defaults to output_per_1m
+#
+# --- Usage rollups (TUI Stats page, budgets; persisted under ~/.codex-helper/usage) ---
+#
+# [usage]
+# # Days of per-day buckets to keep (default 60; values below 31 are raised to 31 so monthly
+# # budgets see the whole month). Read at startup.
+# rollup_keep_days = 60
 "#;
 
 pub async fn init_config_toml(force: bool) -> Result<PathBuf> {
@@ -868,10 +1607,24 @@ fn bootstrap_from_codex(cfg: &mut ProxyConfig) -> Result<()> {
                 auth_token_env,
                 api_key: None,
                 api_key_env: None,
+                auth_token_file: None,
+                auth_token_cmd: Vec::new(),
+                api_key_file: None,
+                api_key_cmd: Vec::new(),
+                cmd_cache_ttl_secs: None,
+                auth_token_secret: None,
+                api_key_secret: None,
+                auth_tokens: Vec::new(),
+                auth_token_envs: Vec::new(),
+                auth_token_secrets: Vec::new(),
+                key_rotation: None,
             },
             tags,
             supported_models: HashMap::new(),
             model_mapping: HashMap::new(),
+            resolve: HashMap::new(),
+            ip_preference: None,
+            body_rules: Vec::new(),
         };
 
         let service = ServiceConfig {
@@ -879,7 +1632,9 @@ fn bootstrap_from_codex(cfg: &mut ProxyConfig) -> Result<()> {
             alias,
             enabled: true,
             level: 1,
+            budget: Default::default(),
             upstreams: vec![upstream],
+            body_rules: Vec::new(),
         };
 
         cfg.codex.configs.insert(provider_id.to_string(), service);
@@ -902,6 +1657,7 @@ fn bootstrap_from_codex(cfg: &mut ProxyConfig) -> Result<()> {
                 alias: None,
                 enabled: true,
                 level: 1,
+                budget: Default::default(),
                 upstreams: vec![UpstreamConfig {
                     base_url: "https://api.openai.com/v1".into(),
                     auth: UpstreamAuth {
@@ -909,11 +1665,26 @@ fn bootstrap_from_codex(cfg: &mut ProxyConfig) -> Result<()> {
                         auth_token_env: None,
                         api_key: None,
                         api_key_env: None,
+                        auth_token_file: None,
+                        auth_token_cmd: Vec::new(),
+                        api_key_file: None,
+                        api_key_cmd: Vec::new(),
+                        cmd_cache_ttl_secs: None,
+                        auth_token_secret: None,
+                        api_key_secret: None,
+                        auth_tokens: Vec::new(),
+                        auth_token_envs: Vec::new(),
+                        auth_token_secrets: Vec::new(),
+                        key_rotation: None,
                     },
                     tags,
                     supported_models: HashMap::new(),
                     model_mapping: HashMap::new(),
+                    resolve: HashMap::new(),
+                    ip_preference: None,
+                    body_rules: Vec::new(),
                 }],
+                body_rules: Vec::new(),
             },
         );
         imported_any = true;
@@ -1023,10 +1794,24 @@ fn bootstrap_from_claude(cfg: &mut ProxyConfig) -> Result<()> {
             auth_token_env: None,
             api_key: None,
             api_key_env: Some(api_key_env),
+            auth_token_file: None,
+            auth_token_cmd: Vec::new(),
+            api_key_file: None,
+            api_key_cmd: Vec::new(),
+            cmd_cache_ttl_secs: None,
+            auth_token_secret: None,
+            api_key_secret: None,
+            auth_tokens: Vec::new(),
+            auth_token_envs: Vec::new(),
+            auth_token_secrets: Vec::new(),
+            key_rotation: None,
         },
         tags,
         supported_models: HashMap::new(),
         model_mapping: HashMap::new(),
+        resolve: HashMap::new(),
+        ip_preference: None,
+        body_rules: Vec::new(),
     };
 
     let service = ServiceConfig {
@@ -1034,7 +1819,9 @@ fn bootstrap_from_claude(cfg: &mut ProxyConfig) -> Result<()> {
         alias: Some("Claude default".to_string()),
         enabled: true,
         level: 1,
+        budget: Default::default(),
         upstreams: vec![upstream],
+        body_rules: Vec::new(),
     };
 
     cfg.claude.configs.insert("default".to_string(), service);
@@ -1305,6 +2092,9 @@ pub fn sync_codex_auth_from_codex_cli(
                     tags,
                     supported_models: HashMap::new(),
                     model_mapping: HashMap::new(),
+                    resolve: HashMap::new(),
+                    ip_preference: None,
+                    body_rules: Vec::new(),
                 };
                 if !pvd.requires_openai_auth {
                     if let Some(env_key) = pvd.env_key.as_deref().filter(|s| !s.trim().is_empty()) {
@@ -1321,7 +2111,9 @@ pub fn sync_codex_auth_from_codex_cli(
                     alias: pvd.alias.clone(),
                     enabled: true,
                     level: 1,
+                    budget: Default::default(),
                     upstreams: vec![upstream],
+                    body_rules: Vec::new(),
                 };
 
                 cfg.codex.configs.insert(pid.to_string(), service);
@@ -1490,6 +2282,78 @@ mod tests {
         assert!(inferred.is_none());
     }
 
+    #[test]
+    fn server_local_base_url_maps_wildcard_to_loopback() {
+        let mut server = ServerConfig::default();
+        assert_eq!(server.local_base_url(3211), "http://127.0.0.1:3211");
+        server.listen = Some("0.0.0.0".to_string());
+        assert_eq!(server.local_base_url(3211), "http://127.0.0.1:3211");
+        server.listen = Some("::".to_string());
+        assert_eq!(server.local_base_url(3211), "http://[::1]:3211");
+        server.listen = Some("192.168.1.5".to_string());
+        assert_eq!(server.local_base_url(3211), "http://192.168.1.5:3211");
+        server.listen = Some("lan".to_string());
+        assert!(server.listen_ip().is_err());
+    }
+
+    #[test]
+    fn server_tls_switches_base_url_to_https() {
+        let mut server = ServerConfig {
+            tls_cert: Some("/etc/codex-helper/cert.pem".to_string()),
+            ..Default::default()
+        };
+        assert!(server.tls_files().is_err());
+        server.tls_key = Some("/etc/codex-helper/key.pem".to_string());
+        assert_eq!(server.local_base_url(3211), "https://localhost:3211");
+        server.listen = Some("192.168.1.5".to_string());
+        assert_eq!(server.local_base_url(3211), "https://192.168.1.5:3211");
+        server.tls_host = Some("devbox.lan".to_string());
+        assert_eq!(server.local_base_url(3211), "https://devbox.lan:3211");
+    }
+
+    #[test]
+    fn token_pool_ids_are_stable_and_deduplicated() {
+        let auth: UpstreamAuth = toml::from_str(
+            r#"
+auth_token_env = "KEY_A"
+auth_tokens = ["sk-1", "", "sk-3"]
+auth_token_envs = ["KEY_B", "KEY_A"]
+auth_token_secrets = ["team", ""]
+auth_token_file = "/run/secrets/key"
+key_rotation = "on_error"
+"#,
+        )
+        .expect("parse auth");
+        assert!(auth.has_token_pool());
+        assert_eq!(auth.key_rotation, Some(KeyRotation::OnError));
+        let ids = auth
+            .token_pool()
+            .into_iter()
+            .map(|(id, _)| id)
+            .collect::<Vec<_>>();
+        assert_eq!(
+            ids,
+            vec![
+                "env:KEY_A",
+                "file:/run/secrets/key",
+                "inline:0f2c10bf",
+                "inline:4d89207f",
+                "env:KEY_B",
+                "secret:team"
+            ]
+        );
+
+        // Dropping a key leaves the ids of the others alone.
+        let mut fewer = auth.clone();
+        fewer.auth_tokens.remove(0);
+        assert!(
+            fewer
+                .token_pool()
+                .iter()
+                .any(|(id, _)| id == "inline:4d89207f")
+        );
+    }
+
     struct ScopedEnv {
         saved: Vec<(String, Option<String>)>,
     }
@@ -1889,6 +2753,7 @@ env_key = "RIGHTCODE_API_KEY"
                 alias: None,
                 enabled: false,
                 level: 7,
+                budget: Default::default(),
                 upstreams: vec![UpstreamConfig {
                     base_url: "https://www.right.codes/codex/v1".to_string(),
                     auth: UpstreamAuth {
@@ -1896,6 +2761,17 @@ env_key = "RIGHTCODE_API_KEY"
                         auth_token_env: Some("OLD_KEY".to_string()),
                         api_key: None,
                         api_key_env: None,
+                        auth_token_file: None,
+                        auth_token_cmd: Vec::new(),
+                        api_key_file: None,
+                        api_key_cmd: Vec::new(),
+                        cmd_cache_ttl_secs: None,
+                        auth_token_secret: None,
+                        api_key_secret: None,
+                        auth_tokens: Vec::new(),
+                        auth_token_envs: Vec::new(),
+                        auth_token_secrets: Vec::new(),
+                        key_rotation: None,
                     },
                     tags: {
                         let mut t = HashMap::new();
@@ -1905,7 +2781,11 @@ env_key = "RIGHTCODE_API_KEY"
                     },
                     supported_models: HashMap::new(),
                     model_mapping: HashMap::new(),
+                    resolve: HashMap::new(),
+                    ip_preference: None,
+                    body_rules: Vec::new(),
                 }],
+                body_rules: Vec::new(),
             },
         );
 
@@ -0,0 +1,201 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::{Duration, Instant};
+
+use tracing::warn;
+
+pub const DEFAULT_CMD_CACHE_TTL_SECS: u64 = 300;
+
+/// Failed commands are retried at most this often, so a broken helper does not run on every request.
+const CMD_FAILURE_BACKOFF: Duration = Duration::from_secs(10);
+const CMD_TIMEOUT: Duration = Duration::from_secs(15);
+
+pub(crate) fn expand_home(path: &str) -> PathBuf {
+    if let Some(rest) = path.strip_prefix("~/")
+        && let Some(home) = dirs::home_dir()
+    {
+        return home.join(rest);
+    }
+    PathBuf::from(path)
+}
+
+/// Reads a secret from a file, trimming surrounding whitespace (files usually end with a newline).
+pub fn read_secret_file(path: &str) -> Option<String> {
+    let path = path.trim();
+    if path.is_empty() {
+        return None;
+    }
+    match std::fs::read_to_string(expand_home(path)) {
+        Ok(s) => Some(s.trim().to_string()).filter(|s| !s.is_empty()),
+        Err(e) => {
+            warn!("failed to read secret file {}: {}", path, e);
+            None
+        }
+    }
+}
+
+struct CmdCacheEntry {
+    value: Option<String>,
+    fetched_at: Instant,
+}
+
+/// One slot per argv. The async lock is held while the command runs, so concurrent callers
+/// wait for that single refresh instead of starting their own copy.
+type CmdSlot = Arc<tokio::sync::Mutex<Option<CmdCacheEntry>>>;
+
+fn cmd_slot(argv: &[String]) -> CmdSlot {
+    static SLOTS: OnceLock<Mutex<HashMap<Vec<String>, CmdSlot>>> = OnceLock::new();
+    let mut guard = match SLOTS.get_or_init(|| Mutex::new(HashMap::new())).lock() {
+        Ok(g) => g,
+        Err(e) => e.into_inner(),
+    };
+    guard.entry(argv.to_vec()).or_default().clone()
+}
+
+async fn run_command(argv: &[String]) -> Result<String, String> {
+    let (program, args) = argv.split_first().ok_or("empty command")?;
+    let child = tokio::process::Command::new(program)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("spawn failed: {e}"))?;
+
+    // stdout/stderr are drained while waiting, so a chatty helper cannot fill the pipe and hang;
+    // on timeout the future is dropped and `kill_on_drop` ends the child.
+    let output = match tokio::time::timeout(CMD_TIMEOUT, child.wait_with_output()).await {
+        Ok(r) => r.map_err(|e| format!("wait failed: {e}"))?,
+        Err(_) => return Err(format!("timed out after {}s", CMD_TIMEOUT.as_secs())),
+    };
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(format!("exited with {}: {}", output.status, stderr.trim()));
+    }
+    // Helpers like `pass` print the secret on the first line and metadata afterwards.
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    let first = stdout.lines().next().unwrap_or("").trim().to_string();
+    if first.is_empty() {
+        return Err("produced no output".to_string());
+    }
+    Ok(first)
+}
+
+/// Runs `argv` and returns the first line of stdout, cached for `ttl_secs`.
+pub async fn run_secret_command(argv: &[String], ttl_secs: u64) -> Option<String> {
+    if argv.is_empty() || argv[0].trim().is_empty() {
+        return None;
+    }
+    let slot = cmd_slot(argv);
+    let mut entry = slot.lock().await;
+    if let Some(cached) = entry.as_ref() {
+        let max_age = if cached.value.is_some() {
+            Duration::from_secs(ttl_secs)
+        } else {
+            CMD_FAILURE_BACKOFF.min(Duration::from_secs(ttl_secs))
+        };
+        if cached.fetched_at.elapsed() < max_age {
+            return cached.value.clone();
+        }
+    }
+
+    let value = match run_command(argv).await {
+        Ok(v) => Some(v),
+        Err(e) => {
+            // Never log stdout here: it may contain the secret.
+            warn!("credential command '{}' failed: {}", argv[0], e);
+            None
+        }
+    };
+    *entry = Some(CmdCacheEntry {
+        value: value.clone(),
+        fetched_at: Instant::now(),
+    });
+    value
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn read_secret_file_trims_trailing_newline() {
+        let dir = std::env::temp_dir().join(format!("codex-helper-cred-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("token");
+        std::fs::write(&path, "sk-from-file\n").unwrap();
+        assert_eq!(
+            read_secret_file(path.to_str().unwrap()).as_deref(),
+            Some("sk-from-file")
+        );
+        assert!(read_secret_file(dir.join("missing").to_str().unwrap()).is_none());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_secret_command_uses_first_line_and_caches() {
+        let marker = std::env::temp_dir().join(format!(
+            "codex-helper-cred-cmd-{}-{}",
+            std::process::id(),
+            line!()
+        ));
+        let _ = std::fs::remove_file(&marker);
+        // Each run appends to the marker so we can count invocations.
+        let argv = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!(
+                "echo x >> '{}'; printf 'sk-from-cmd\\nmeta: 1\\n'",
+                marker.display()
+            ),
+        ];
+        assert_eq!(
+            run_secret_command(&argv, 60).await.as_deref(),
+            Some("sk-from-cmd")
+        );
+        assert_eq!(
+            run_secret_command(&argv, 60).await.as_deref(),
+            Some("sk-from-cmd")
+        );
+        let runs = std::fs::read_to_string(&marker).unwrap_or_default();
+        assert_eq!(runs.lines().count(), 1);
+        let _ = std::fs::remove_file(&marker);
+
+        let failing = vec!["sh".to_string(), "-c".to_string(), "exit 3".to_string()];
+        assert!(run_secret_command(&failing, 60).await.is_none());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn run_secret_command_shares_one_refresh_and_drains_large_output() {
+        let marker = std::env::temp_dir().join(format!(
+            "codex-helper-cred-cmd-{}-{}",
+            std::process::id(),
+            line!()
+        ));
+        let _ = std::fs::remove_file(&marker);
+        // Slow, and writes well past a pipe buffer after the secret line.
+        let argv = vec![
+            "sh".to_string(),
+            "-c".to_string(),
+            format!(
+                "echo x >> '{}'; sleep 0.3; echo sk-slow; head -c 300000 /dev/zero | tr '\\0' m; echo",
+                marker.display()
+            ),
+        ];
+        let runs = (0..5).map(|_| {
+            let argv = argv.clone();
+            tokio::spawn(async move { run_secret_command(&argv, 60).await })
+        });
+        for run in futures_util::future::join_all(runs).await {
+            assert_eq!(run.unwrap().as_deref(), Some("sk-slow"));
+        }
+        let runs = std::fs::read_to_string(&marker).unwrap_or_default();
+        assert_eq!(runs.lines().count(), 1);
+        let _ = std::fs::remove_file(&marker);
+    }
+}
@@ -2,18 +2,29 @@ use std::collections::HashMap;
 use std::collections::HashSet;
 use std::sync::{Arc, Mutex};
 
-use crate::config::{ServiceConfig, UpstreamConfig};
+use crate::config::{KeyRotation, ServiceConfig, UpstreamConfig};
 use tracing::info;
 
 pub const FAILURE_THRESHOLD: u32 = 3;
 pub const COOLDOWN_SECS: u64 = 30;
 
+/// Per-upstream key pool state. Keys are tracked by key id (`inline:HASH` / `env:NAME` / ...), never by value.
+#[derive(Debug, Default, Clone)]
+pub struct KeyPoolState {
+    /// Round-robin cursor into the resolvable key list.
+    pub cursor: usize,
+    pub failure_counts: HashMap<String, u32>,
+    /// Keys temporarily taken out of rotation (429 / quota errors, or repeated auth failures).
+    pub exhausted_until: HashMap<String, std::time::Instant>,
+}
+
 #[derive(Debug, Default)]
 pub struct LbState {
     pub failure_counts: Vec<u32>,
     pub cooldown_until: Vec<Option<std::time::Instant>>,
     pub usage_exhausted: Vec<bool>,
     pub last_good_index: Option<usize>,
+    pub key_pools: Vec<KeyPoolState>,
 }
 
 impl LbState {
@@ -22,6 +33,7 @@ impl LbState {
             self.failure_counts = vec![0; len];
             self.cooldown_until = vec![None; len];
             self.usage_exhausted = vec![false; len];
+            self.key_pools = vec![KeyPoolState::default(); len];
             // 如果 upstream 数量发生变化，原来的 last_good_index 很可能已经无效，直接清空。
             self.last_good_index = None;
         }
@@ -227,6 +239,108 @@ impl LoadBalancer {
             }
         }
     }
+
+    fn with_key_pool<R>(
+        &self,
+        index: usize,
+        f: impl FnOnce(&mut KeyPoolState, std::time::Instant) -> R,
+    ) -> Option<R> {
+        let mut map = match self.states.lock() {
+            Ok(m) => m,
+            Err(e) => e.into_inner(),
+        };
+        let entry = map.entry(self.service.name.clone()).or_default();
+        entry.ensure_len(self.service.upstreams.len());
+        let pool = entry.key_pools.get_mut(index)?;
+        let now = std::time::Instant::now();
+        pool.exhausted_until.retain(|_, until| *until > now);
+        Some(f(pool, now))
+    }
+
+    /// Picks a key for upstream `index` from `key_ids` (the keys that currently resolve, in config order).
+    ///
+    /// Keys in cooldown are skipped; if every key is cooling down, the one that recovers first is used so
+    /// the request still goes out.
+    pub fn select_key(
+        &self,
+        index: usize,
+        key_ids: &[&str],
+        rotation: KeyRotation,
+    ) -> Option<usize> {
+        if key_ids.is_empty() {
+            return None;
+        }
+        self.with_key_pool(index, |pool, _now| {
+            let n = key_ids.len();
+            let start = pool.cursor % n;
+            let picked = (0..n)
+                .map(|off| (start + off) % n)
+                .find(|&i| !pool.exhausted_until.contains_key(key_ids[i]));
+            match picked {
+                Some(i) => {
+                    pool.cursor = match rotation {
+                        KeyRotation::RoundRobin => i + 1,
+                        KeyRotation::OnError => i,
+                    };
+                    i
+                }
+                None => (0..n)
+                    .min_by_key(|&i| pool.exhausted_until.get(key_ids[i]).copied())
+                    .unwrap_or(0),
+            }
+        })
+    }
+
+    /// Whether upstream `index` has a usable key other than `except`.
+    pub fn has_other_available_key(&self, index: usize, key_ids: &[&str], except: &str) -> bool {
+        self.with_key_pool(index, |pool, _now| {
+            key_ids
+                .iter()
+                .any(|id| *id != except && !pool.exhausted_until.contains_key(*id))
+        })
+        .unwrap_or(false)
+    }
+
+    pub fn mark_key_exhausted(&self, index: usize, key_id: &str, cooldown_secs: u64, reason: &str) {
+        self.with_key_pool(index, |pool, now| {
+            pool.exhausted_until.insert(
+                key_id.to_string(),
+                now + std::time::Duration::from_secs(cooldown_secs),
+            );
+        });
+        info!(
+            "lb: upstream '{}' index {} key '{}' out of rotation for {}s (reason: {})",
+            self.service.name, index, key_id, cooldown_secs, reason
+        );
+    }
+
+    /// Records an auth-level outcome for a pooled key. Repeated failures (401/403) take the key out of
+    /// rotation for `COOLDOWN_SECS`, mirroring upstream-level circuit breaking.
+    pub fn record_key_result(&self, index: usize, key_id: &str, success: bool) {
+        let tripped = self.with_key_pool(index, |pool, now| {
+            if success {
+                pool.failure_counts.remove(key_id);
+                return false;
+            }
+            let count = pool.failure_counts.entry(key_id.to_string()).or_default();
+            *count = count.saturating_add(1);
+            if *count < FAILURE_THRESHOLD {
+                return false;
+            }
+            *count = 0;
+            pool.exhausted_until.insert(
+                key_id.to_string(),
+                now + std::time::Duration::from_secs(COOLDOWN_SECS),
+            );
+            true
+        });
+        if tripped == Some(true) {
+            info!(
+                "lb: upstream '{}' index {} key '{}' reached failure threshold {}, out of rotation for {}s",
+                self.service.name, index, key_id, FAILURE_THRESHOLD, COOLDOWN_SECS
+            );
+        }
+    }
 }
 
 #[cfg(test)]
@@ -240,6 +354,7 @@ mod tests {
             alias: None,
             enabled: true,
             level: 1,
+            budget: Default::default(),
             upstreams: urls
                 .iter()
                 .map(|u| UpstreamConfig {
@@ -249,12 +364,27 @@ mod tests {
                         auth_token_env: None,
                         api_key: None,
                         api_key_env: None,
+                        auth_token_file: None,
+                        auth_token_cmd: Vec::new(),
+                        api_key_file: None,
+                        api_key_cmd: Vec::new(),
+                        cmd_cache_ttl_secs: None,
+                        auth_token_secret: None,
+                        api_key_secret: None,
+                        auth_tokens: Vec::new(),
+                        auth_token_envs: Vec::new(),
+                        auth_token_secrets: Vec::new(),
+                        key_rotation: None,
                     },
                     tags: HashMap::new(),
                     supported_models: HashMap::new(),
                     model_mapping: HashMap::new(),
+                    resolve: HashMap::new(),
+                    ip_preference: None,
+                    body_rules: Vec::new(),
                 })
                 .collect(),
+            body_rules: Vec::new(),
         }
     }
 
@@ -336,4 +466,44 @@ mod tests {
             .expect("should select backup after failures");
         assert_eq!(selected.index, 1);
     }
+
+    #[test]
+    fn key_pool_round_robin_skips_exhausted_keys() {
+        let service = make_service("codex-main", &["https://primary.example"]);
+        let states = Arc::new(Mutex::new(HashMap::new()));
+        let lb = LoadBalancer::new(Arc::new(service), states);
+        let keys = ["env:K1", "env:K2", "env:K3"];
+
+        let picks = (0..3)
+            .map(|_| lb.select_key(0, &keys, KeyRotation::RoundRobin))
+            .collect::<Vec<_>>();
+        assert_eq!(picks, vec![Some(0), Some(1), Some(2)]);
+
+        lb.mark_key_exhausted(0, "env:K1", 60, "test");
+        assert_eq!(lb.select_key(0, &keys, KeyRotation::RoundRobin), Some(1));
+        assert_eq!(lb.select_key(0, &keys, KeyRotation::RoundRobin), Some(2));
+        assert_eq!(lb.select_key(0, &keys, KeyRotation::RoundRobin), Some(1));
+        assert!(lb.has_other_available_key(0, &keys, "env:K2"));
+    }
+
+    #[test]
+    fn key_pool_on_error_sticks_until_key_fails() {
+        let service = make_service("codex-main", &["https://primary.example"]);
+        let states = Arc::new(Mutex::new(HashMap::new()));
+        let lb = LoadBalancer::new(Arc::new(service), states);
+        let keys = ["inline", "inline#1"];
+
+        assert_eq!(lb.select_key(0, &keys, KeyRotation::OnError), Some(0));
+        assert_eq!(lb.select_key(0, &keys, KeyRotation::OnError), Some(0));
+
+        for _ in 0..FAILURE_THRESHOLD {
+            lb.record_key_result(0, "inline", false);
+        }
+        assert_eq!(lb.select_key(0, &keys, KeyRotation::OnError), Some(1));
+        assert!(!lb.has_other_available_key(0, &keys, "inline#1"));
+
+        // When every key is cooling down we still hand one out.
+        lb.mark_key_exhausted(0, "inline#1", 60, "test");
+        assert!(lb.select_key(0, &keys, KeyRotation::OnError).is_some());
+    }
 }
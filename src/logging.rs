@@ -1,14 +1,17 @@
-use std::fs::{self, OpenOptions};
-use std::io::Write;
-use std::path::PathBuf;
+use std::fs::{self, File, OpenOptions};
+use std::io::{BufReader, Write};
+use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::{Mutex, OnceLock};
 use std::time::{SystemTime, UNIX_EPOCH};
 
+use anyhow::Context;
 use base64::Engine;
 use serde::Serialize;
 
 use crate::config::proxy_home_dir;
+use crate::filter::FilterReport;
+use crate::pricing::Cost;
 use crate::usage::UsageMetrics;
 
 #[derive(Debug, Clone, Copy)]
@@ -208,6 +211,9 @@ pub struct HttpDebugLog {
     /// Cloudflare request id when present (from `cf-ray` response header).
     #[serde(skip_serializing_if = "Option::is_none")]
     pub upstream_cf_ray: Option<String>,
+    /// Upstream peer address actually connected to (reflects `resolve` / `ip_preference` overrides).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_remote_addr: Option<String>,
     pub client_uri: String,
     pub target_url: String,
     pub client_headers: Vec<HeaderEntry>,
@@ -218,6 +224,9 @@ pub struct HttpDebugLog {
     pub client_body: Option<BodyPreview>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub upstream_request_body: Option<BodyPreview>,
+    /// Changes made by `body_rules`, e.g. `set /store: true -> false`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub body_rewrites: Vec<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub upstream_response_headers: Option<Vec<HeaderEntry>>,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -239,19 +248,34 @@ pub struct RequestLog<'a> {
     pub provider_id: Option<String>,
     pub upstream_base_url: &'a str,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub session_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cwd: Option<String>,
+    /// Model sent upstream (after `model_mapping`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reasoning_effort: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<UsageMetrics>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<Cost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub http_debug: Option<HttpDebugLog>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub http_debug_ref: Option<HttpDebugRef>,
+    /// Copied from `http_debug` so it stays filterable when the debug blob is split out.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub upstream_error_class: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub retry: Option<RetryInfo>,
+    /// Redaction counts / blocking rule from the request filter, as top-level fields.
+    #[serde(flatten)]
+    pub filter_report: Option<FilterReport>,
 }
 
 #[derive(Debug, Serialize, Clone)]
@@ -280,14 +304,23 @@ struct HttpDebugLogEntry<'a> {
     pub provider_id: Option<String>,
     pub upstream_base_url: &'a str,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub session_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub cwd: Option<String>,
+    /// Model sent upstream (after `model_mapping`).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub reasoning_effort: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<UsageMetrics>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<Cost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub retry: Option<RetryInfo>,
     pub http_debug: HttpDebugLog,
 }
@@ -299,7 +332,8 @@ struct RequestLogOptions {
     only_errors: bool,
 }
 
-fn log_path() -> PathBuf {
+/// The live request log, `~/.codex-helper/logs/requests.jsonl`.
+pub fn log_path() -> PathBuf {
     proxy_home_dir().join("logs").join("requests.jsonl")
 }
 
@@ -307,6 +341,60 @@ fn debug_log_path() -> PathBuf {
     proxy_home_dir().join("logs").join("requests_debug.jsonl")
 }
 
+/// Request log files oldest first: rotated `requests.<ts>.jsonl` files, then `requests.jsonl`.
+///
+/// With `since_ms`, rotated files that were closed before that time are skipped, since every
+/// line in them is older.
+pub fn request_log_files(since_ms: Option<u64>) -> Vec<PathBuf> {
+    log_files_with_rotations(log_path(), since_ms)
+}
+
+/// Opens one of the files returned by [`request_log_files`] / [`debug_log_files`] for reading.
+pub fn open_log_file(path: &Path) -> anyhow::Result<BufReader<File>> {
+    File::open(path)
+        .map(BufReader::new)
+        .with_context(|| format!("无法打开请求日志 {:?}", path))
+}
+
+/// Same as [`request_log_files`] for the split `requests_debug.jsonl` log.
+pub fn debug_log_files(since_ms: Option<u64>) -> Vec<PathBuf> {
+    log_files_with_rotations(debug_log_path(), since_ms)
+}
+
+fn log_files_with_rotations(current: PathBuf, since_ms: Option<u64>) -> Vec<PathBuf> {
+    let prefix = format!(
+        "{}.",
+        current
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("requests")
+    );
+    let mut files: Vec<(u64, PathBuf)> = current
+        .parent()
+        .and_then(|dir| fs::read_dir(dir).ok())
+        .into_iter()
+        .flatten()
+        .filter_map(|e| e.ok())
+        .filter_map(|e| {
+            let path = e.path();
+            let ts = path
+                .file_name()
+                .and_then(|n| n.to_str())
+                .and_then(|n| n.strip_prefix(prefix.as_str()))
+                .and_then(|n| n.strip_suffix(".jsonl"))
+                .and_then(|ts| ts.parse::<u64>().ok())?;
+            Some((ts, path))
+        })
+        .filter(|(ts, _)| since_ms.is_none_or(|since| *ts >= since))
+        .collect();
+    files.sort_by_key(|(ts, _)| *ts);
+    let mut out: Vec<PathBuf> = files.into_iter().map(|(_, p)| p).collect();
+    if current.exists() {
+        out.push(current);
+    }
+    out
+}
+
 fn log_lock() -> &'static Mutex<()> {
     static LOCK: OnceLock<Mutex<()>> = OnceLock::new();
     LOCK.get_or_init(|| Mutex::new(()))
@@ -399,11 +487,16 @@ pub fn log_request_with_debug(
     config_name: &str,
     provider_id: Option<String>,
     upstream_base_url: &str,
+    auth_key: Option<String>,
+    client: Option<String>,
     session_id: Option<String>,
     cwd: Option<String>,
+    model: Option<String>,
     reasoning_effort: Option<String>,
     usage: Option<UsageMetrics>,
+    cost: Option<Cost>,
     retry: Option<RetryInfo>,
+    filter_report: Option<FilterReport>,
     http_debug: Option<HttpDebugLog>,
 ) {
     let opt = request_log_options();
@@ -417,6 +510,9 @@ pub fn log_request_with_debug(
         .unwrap_or(0);
 
     static DEBUG_SEQ: AtomicU64 = AtomicU64::new(0);
+    let upstream_error_class = http_debug
+        .as_ref()
+        .and_then(|h| h.upstream_error_class.clone());
     let mut http_debug_for_main = http_debug;
     let mut http_debug_ref: Option<HttpDebugRef> = None;
 
@@ -447,10 +543,14 @@ pub fn log_request_with_debug(
             config_name,
             provider_id: provider_id.clone(),
             upstream_base_url,
+            auth_key: auth_key.clone(),
+            client: client.clone(),
             session_id: session_id.clone(),
             cwd: cwd.clone(),
+            model: model.clone(),
             reasoning_effort: reasoning_effort.clone(),
             usage: usage.clone(),
+            cost: cost.clone(),
             retry: retry.clone(),
             http_debug: h,
         };
@@ -494,13 +594,19 @@ pub fn log_request_with_debug(
         config_name,
         provider_id,
         upstream_base_url,
+        auth_key,
+        client,
         session_id,
         cwd,
+        model,
         reasoning_effort,
         usage,
+        cost,
         http_debug: http_debug_for_main,
         http_debug_ref,
+        upstream_error_class,
         retry,
+        filter_report,
     };
 
     rotate_and_prune_if_needed(&log_file_path, opt);
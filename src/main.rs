@@ -1,12 +1,19 @@
+mod body_rules;
+mod budget;
+mod client_keys;
 mod codex_integration;
 mod commands;
 mod config;
+mod credentials;
 mod filter;
 mod lb;
 mod logging;
 mod model_routing;
 mod notify;
+mod pricing;
+mod projects;
 mod proxy;
+mod secrets;
 mod sessions;
 mod state;
 mod tui;
@@ -18,9 +25,9 @@ use clap::{Parser, Subcommand};
 use owo_colors::OwoColorize;
 use reqwest::Client;
 use std::collections::HashMap;
-use std::net::SocketAddr;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, Ordering};
 use tracing_appender::non_blocking::WorkerGuard;
 use tracing_subscriber::EnvFilter;
 
@@ -48,6 +55,8 @@ pub enum CliError {
     CodexConfig(String),
     /// Errors while working with usage logs / usage_providers.json
     Usage(String),
+    /// Errors while reading or writing the encrypted secret store
+    Secrets(String),
     /// Generic fallback for other failures
     Other(String),
 }
@@ -58,6 +67,7 @@ impl std::fmt::Display for CliError {
             CliError::ProxyConfig(msg) => write!(f, "Proxy config error: {}", msg),
             CliError::CodexConfig(msg) => write!(f, "Codex config error: {}", msg),
             CliError::Usage(msg) => write!(f, "Usage error: {}", msg),
+            CliError::Secrets(msg) => write!(f, "Secret store error: {}", msg),
             CliError::Other(msg) => write!(f, "{}", msg),
         }
     }
@@ -142,6 +152,26 @@ enum Command {
         #[command(subcommand)]
         cmd: UsageCommand,
     },
+    /// Query request logs written by codex-helper (including rotated files)
+    Logs {
+        #[command(subcommand)]
+        cmd: LogsCommand,
+    },
+    /// Manage upstream keys in the encrypted local secret store (~/.codex-helper/secrets.enc)
+    Secrets {
+        #[command(subcommand)]
+        cmd: SecretsCommand,
+    },
+    /// Manage per-person client keys for a shared proxy (~/.codex-helper/client_keys.json)
+    Keys {
+        #[command(subcommand)]
+        cmd: KeysCommand,
+    },
+    /// Inspect request filter rules (~/.codex-helper/filter.json)
+    Filter {
+        #[command(subcommand)]
+        cmd: FilterCommand,
+    },
     /// Handle Codex notifications (for Codex `notify` hook)
     Notify {
         #[command(subcommand)]
@@ -380,6 +410,145 @@ enum UsageCommand {
         #[arg(long, default_value_t = 20)]
         limit: usize,
     },
+    /// Aggregate requests across current and rotated request logs
+    Report {
+        /// Only include requests from the last duration, e.g. 30m, 24h, 7d, 2w (default: all logs)
+        #[arg(long)]
+        since: Option<String>,
+        /// Comma-separated grouping: day, model, provider, config, session, cwd, service
+        #[arg(long, default_value = "day")]
+        group_by: String,
+        /// Output format: table, json or csv
+        #[arg(long, default_value = "table")]
+        format: String,
+        /// Only include requests of one service (codex or claude)
+        #[arg(long)]
+        service: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum LogsCommand {
+    /// Filter request log lines; with --id, show one request with its HTTP debug entry
+    Query {
+        /// Status code, class or range, e.g. 502, 5xx, 400-499
+        #[arg(long)]
+        status: Option<String>,
+        /// Config name
+        #[arg(long)]
+        config: Option<String>,
+        /// Provider id
+        #[arg(long)]
+        provider: Option<String>,
+        /// Model sent upstream; `*` wildcards allowed
+        #[arg(long)]
+        model: Option<String>,
+        /// Session id (prefix match)
+        #[arg(long)]
+        session: Option<String>,
+        /// Substring of the request path
+        #[arg(long)]
+        path: Option<String>,
+        /// Upstream error class, e.g. cloudflare_challenge, upstream_transport_error
+        #[arg(long)]
+        error_class: Option<String>,
+        /// Only requests that took at least this many milliseconds
+        #[arg(long)]
+        min_duration_ms: Option<u64>,
+        /// Only requests that took at most this many milliseconds
+        #[arg(long)]
+        max_duration_ms: Option<u64>,
+        /// Only requests from the last duration, e.g. 30m, 24h, 7d
+        #[arg(long)]
+        since: Option<String>,
+        /// Only requests older than this duration ago, e.g. 1h
+        #[arg(long)]
+        until: Option<String>,
+        /// Only requests of one service (codex or claude)
+        #[arg(long)]
+        service: Option<String>,
+        /// Show one request (the `id` printed for lines with a split HTTP debug entry)
+        #[arg(long)]
+        id: Option<String>,
+        /// Maximum number of matching lines to print (the most recent ones)
+        #[arg(long, default_value_t = 50)]
+        limit: usize,
+        /// Keep printing new matching requests as they are logged
+        #[arg(short = 'f', long)]
+        follow: bool,
+        /// Print raw JSON lines instead of the human-friendly format
+        #[arg(long)]
+        raw: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum SecretsCommand {
+    /// Store a secret (reads the value from stdin unless --value is given)
+    Set {
+        name: String,
+        /// Secret value (visible in shell history; prefer piping it via stdin)
+        #[arg(long)]
+        value: Option<String>,
+    },
+    /// Print a secret to stdout
+    Get { name: String },
+    /// List stored secret names (values are never printed)
+    List,
+    /// Remove a secret
+    Rm { name: String },
+    /// Move plaintext auth_token / api_key values from the config into the secret store
+    Migrate {
+        /// Show what would be moved without changing any files
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+#[derive(Subcommand, Debug)]
+enum KeysCommand {
+    /// Issue a new client key; the key is printed once and only its hash is stored
+    Create {
+        /// Who the key belongs to (shown in usage reports)
+        label: String,
+        /// Restrict the key to these configs (repeatable); defaults to all configs
+        #[arg(long = "config")]
+        configs: Vec<String>,
+        /// Maximum total tokens per UTC day
+        #[arg(long)]
+        daily_token_budget: Option<u64>,
+    },
+    /// List client keys (hashes and plaintext are never printed)
+    List,
+    /// Revoke a key by id or label
+    Revoke { id_or_label: String },
+}
+
+#[derive(Subcommand, Debug)]
+enum FilterCommand {
+    /// Run a request body through the filter rules and show what each rule did
+    Test {
+        /// Request body file, or `-` to read from stdin
+        input: String,
+        /// Rules file to test instead of ~/.codex-helper/filter.json
+        #[arg(long)]
+        rules: Option<std::path::PathBuf>,
+        /// Service the request is scoped to (`codex` or `claude`)
+        #[arg(long, default_value = "codex")]
+        service: String,
+        /// Config (station) name the request is routed to
+        #[arg(long)]
+        config: Option<String>,
+        /// Request path, e.g. /v1/responses
+        #[arg(long, default_value = "/v1/responses")]
+        path: String,
+        /// Model; defaults to the body's top-level `model`
+        #[arg(long)]
+        model: Option<String>,
+        /// Session id used for tokenize placeholders
+        #[arg(long)]
+        session: Option<String>,
+    },
 }
 
 #[tokio::main]
@@ -410,7 +579,7 @@ async fn real_main() -> CliResult<()> {
                     port,
                     codex,
                     claude,
-                } => do_switch_on(port, codex, claude)?,
+                } => do_switch_on(port, codex, claude).await?,
                 SwitchCommand::Off { codex, claude } => do_switch_off(codex, claude)?,
                 SwitchCommand::Status { codex, claude } => do_switch_status(codex, claude),
             }
@@ -425,7 +594,7 @@ async fn real_main() -> CliResult<()> {
                 "{}",
                 "Warning: `switch-on` is deprecated, please use `switch on` instead.".yellow()
             );
-            do_switch_on(port, codex, claude)?;
+            do_switch_on(port, codex, claude).await?;
             return Ok(());
         }
         Command::SwitchOff { codex, claude } => {
@@ -456,6 +625,22 @@ async fn real_main() -> CliResult<()> {
             commands::usage::handle_usage_cmd(cmd).await?;
             return Ok(());
         }
+        Command::Logs { cmd } => {
+            commands::logs::handle_logs_cmd(cmd).await?;
+            return Ok(());
+        }
+        Command::Secrets { cmd } => {
+            commands::secrets::handle_secrets_cmd(cmd).await?;
+            return Ok(());
+        }
+        Command::Keys { cmd } => {
+            commands::keys::handle_keys_cmd(cmd)?;
+            return Ok(());
+        }
+        Command::Filter { cmd } => {
+            commands::filter::handle_filter_cmd(cmd)?;
+            return Ok(());
+        }
         Command::Notify { cmd } => {
             match cmd {
                 NotifyCommand::Codex {
@@ -611,27 +796,21 @@ async fn run_server(service_name: &'static str, port: u16, enable_tui: bool) ->
 
     impl Drop for AutoRestoreGuard {
         fn drop(&mut self) {
-            // Always try to restore the upstream config on exit; if no backup exists, this is a no-op.
-            if self.service_name == "claude" {
-                match codex_integration::claude_switch_off() {
-                    Ok(()) => tracing::info!("Claude settings restored from backup"),
-                    Err(err) => {
-                        tracing::warn!("Failed to restore Claude settings from backup: {}", err)
-                    }
-                }
-            } else if self.service_name == "codex" {
-                match codex_integration::switch_off() {
-                    Ok(()) => tracing::info!("Codex config restored from backup"),
-                    Err(err) => {
-                        tracing::warn!("Failed to restore Codex config from backup: {}", err)
-                    }
-                }
-            }
+            restore_client_config(self.service_name);
         }
     }
 
     let _restore_guard = AutoRestoreGuard { service_name };
 
+    // Listener settings are needed before the switch so Codex/Claude get the right URL and token.
+    let server_cfg = load_config()
+        .await
+        .map(|cfg| cfg.server)
+        .unwrap_or_default();
+    let listen_ip = server_cfg.listen_ip()?;
+    // Bind before switching Codex/Claude over, so a bad address or certificate leaves them untouched.
+    let listeners = proxy::bind_listeners(&proxy::listener_specs(&server_cfg, port)?).await?;
+
     // In Codex mode, automatically switch Codex to the local proxy; in Claude mode, try updating
     // settings.json as well (experimental).
     if service_name == "codex" {
@@ -640,7 +819,7 @@ async fn run_server(service_name: &'static str, port: u16, enable_tui: bool) ->
         if let Err(err) = codex_integration::guard_codex_config_before_switch_on_interactive() {
             tracing::warn!("Failed to guard Codex config before switch-on: {}", err);
         }
-        match codex_integration::switch_on(port) {
+        match codex_integration::switch_on(port, &server_cfg) {
             Ok(()) => {
                 tracing::info!("Codex config switched to local proxy on port {}", port);
             }
@@ -652,7 +831,7 @@ async fn run_server(service_name: &'static str, port: u16, enable_tui: bool) ->
         if let Err(err) = codex_integration::guard_claude_settings_before_switch_on_interactive() {
             tracing::warn!("Failed to guard Claude settings before switch-on: {}", err);
         }
-        match codex_integration::claude_switch_on(port) {
+        match codex_integration::claude_switch_on(port, &server_cfg) {
             Ok(()) => {
                 tracing::info!(
                     "Claude settings updated to use local proxy on port {}",
@@ -723,6 +902,7 @@ async fn run_server(service_name: &'static str, port: u16, enable_tui: bool) ->
         );
     }
     let client = Client::builder().build()?;
+    proxy::clear_stale_spool_dirs();
 
     // Shared LB state (failure counters, cooldowns, usage flags).
     let lb_states = Arc::new(Mutex::new(HashMap::new()));
@@ -732,14 +912,14 @@ async fn run_server(service_name: &'static str, port: u16, enable_tui: bool) ->
     let state = proxy.state_handle();
     let app: Router = proxy_router(proxy);
 
-    let addr: SocketAddr = SocketAddr::from(([127, 0, 0, 1], port));
-    tracing::info!(
-        "codex-helper listening on http://{} (service: {})",
-        addr,
-        service_name
-    );
+    tracing::info!("codex-helper serving {} on port {}", service_name, port);
+    if !listen_ip.is_loopback() && cfg.server.resolve_client_token().is_none() {
+        tracing::warn!(
+            "listening on non-loopback address {} without server.client_token; anyone who can reach it can use your upstream keys",
+            listen_ip
+        );
+    }
 
-    let listener = tokio::net::TcpListener::bind(addr).await?;
     let (shutdown_tx, shutdown_rx) = tokio::sync::watch::channel(false);
 
     let warnings = model_routing_warnings(&cfg, service_name);
@@ -750,32 +930,43 @@ async fn run_server(service_name: &'static str, port: u16, enable_tui: bool) ->
         }
         tracing::warn!("==============================================");
     }
+    for w in config::body_rule_warnings(&cfg, service_name) {
+        tracing::warn!("{}", w);
+    }
 
     {
         let shutdown_tx = shutdown_tx.clone();
+        let state = state.clone();
         tokio::spawn(async move {
-            wait_for_shutdown_signal().await;
-            let _ = shutdown_tx.send(true);
+            loop {
+                wait_for_shutdown_signal().await;
+                if *shutdown_tx.borrow() {
+                    // Second signal while draining: give up on in-flight requests.
+                    force_quit(&state, service_name, interactive).await;
+                }
+                let _ = shutdown_tx.send(true);
+            }
         });
     }
 
+    let drain_timeout = cfg.server.drain_timeout();
+    let server_handle = tokio::spawn(proxy::serve_listeners(listeners, app, shutdown_rx.clone()));
+    // While the dashboard is up it shows the drain countdown itself; printing to the console
+    // would only scribble over the alternate screen.
+    let console_drain = AtomicBool::new(!interactive);
+    let drain = wait_until_drained(
+        server_handle,
+        shutdown_rx.clone(),
+        &state,
+        drain_timeout,
+        &console_drain,
+    );
     let result = if interactive {
-        let server_shutdown = {
-            let mut rx = shutdown_rx.clone();
-            async move {
-                let _ = rx.changed().await;
-            }
-        };
-        let mut server_handle = tokio::spawn(async move {
-            axum::serve(listener, app.into_make_service())
-                .with_graceful_shutdown(server_shutdown)
-                .await
-        });
-
+        tokio::pin!(drain);
         let providers = tui::build_provider_options(&cfg, service_name);
 
         let mut tui_handle = tokio::spawn(tui::run_dashboard(
-            state,
+            state.clone(),
             service_name,
             port,
             providers,
@@ -785,67 +976,68 @@ async fn run_server(service_name: &'static str, port: u16, enable_tui: bool) ->
         ));
 
         tokio::select! {
-            server_res = &mut server_handle => {
+            res = &mut drain => {
+                // Server stopped (drained or failed); the dashboard exits once it sees `Done`.
                 let _ = shutdown_tx.send(true);
                 let _ = tui_handle.await;
-                server_res.map_err(|e| anyhow::anyhow!("server task join error: {e}"))??;
-                Ok::<(), anyhow::Error>(())
+                res
             }
             tui_res = &mut tui_handle => {
+                console_drain.store(true, Ordering::Relaxed);
                 match tui_res {
-                    Ok(Ok(())) => {
-                        // The dashboard requested a shutdown (or exited because shutdown was already triggered).
+                    Ok(Ok(true)) => force_quit(&state, service_name, false).await,
+                    Ok(Ok(false)) => {
+                        // The dashboard was closed (possibly mid-drain); make sure shutdown is on.
                         let _ = shutdown_tx.send(true);
-                        server_handle.await.map_err(|e| anyhow::anyhow!("server task join error: {e}"))??;
-                        Ok::<(), anyhow::Error>(())
                     }
                     Ok(Err(err)) => {
                         // If the dashboard fails (e.g. terminal issues), keep running without it.
                         tracing::warn!("TUI dashboard failed; continuing without TUI: {}", err);
-                        server_handle.await.map_err(|e| anyhow::anyhow!("server task join error: {e}"))??;
-                        Ok::<(), anyhow::Error>(())
                     }
                     Err(join_err) => {
                         tracing::warn!("TUI task join error; continuing without TUI: {}", join_err);
-                        server_handle.await.map_err(|e| anyhow::anyhow!("server task join error: {e}"))??;
-                        Ok::<(), anyhow::Error>(())
                     }
                 }
+                drain.await
             }
         }
     } else {
-        let server_shutdown = {
-            let mut rx = shutdown_rx.clone();
-            async move {
-                let _ = rx.changed().await;
-            }
-        };
-        axum::serve(listener, app.into_make_service())
-            .with_graceful_shutdown(server_shutdown)
-            .await?;
-        Ok(())
+        drain.await
     };
 
+    // Requests finished since the last periodic flush would otherwise be lost.
+    if let Err(e) = state
+        .flush_usage_rollup(service_name, &crate::state::usage_rollup_path(service_name))
+        .await
+    {
+        tracing::warn!("failed to persist usage rollup: {}", e);
+    }
+
     result?;
 
     Ok(())
 }
 
-fn do_switch_on(port: u16, codex: bool, claude: bool) -> CliResult<()> {
+async fn do_switch_on(port: u16, codex: bool, claude: bool) -> CliResult<()> {
     if codex && claude {
         return Err(CliError::Other(
             "Please specify at most one of --codex / --claude".to_string(),
         ));
     }
+    let server = load_config()
+        .await
+        .map(|cfg| cfg.server)
+        .unwrap_or_default();
     if claude {
         if let Err(err) = codex_integration::guard_claude_settings_before_switch_on_interactive() {
             tracing::warn!("Failed to guard Claude settings before switch-on: {}", err);
         }
-        codex_integration::claude_switch_on(port)
+        codex_integration::claude_switch_on(port, &server)
             .map_err(|e| CliError::CodexConfig(e.to_string()))?;
     } else {
         codex_integration::guard_codex_config_before_switch_on_interactive()?;
-        codex_integration::switch_on(port).map_err(|e| CliError::CodexConfig(e.to_string()))?;
+        codex_integration::switch_on(port, &server)
+            .map_err(|e| CliError::CodexConfig(e.to_string()))?;
     }
     Ok(())
 }
@@ -1063,6 +1255,137 @@ fn print_claude_switch_status() {
     }
 }
 
+/// Restores Codex / Claude to their pre-proxy config; a no-op when no backup exists.
+fn restore_client_config(service_name: &str) {
+    if service_name == "claude" {
+        match codex_integration::claude_switch_off() {
+            Ok(()) => tracing::info!("Claude settings restored from backup"),
+            Err(err) => tracing::warn!("Failed to restore Claude settings from backup: {}", err),
+        }
+    } else if service_name == "codex" {
+        match codex_integration::switch_off() {
+            Ok(()) => tracing::info!("Codex config restored from backup"),
+            Err(err) => tracing::warn!("Failed to restore Codex config from backup: {}", err),
+        }
+    }
+}
+
+/// Exits right away, abandoning in-flight requests. The terminal and client config are still
+/// restored and usage persisted, since `process::exit` skips destructors and the normal shutdown
+/// flush.
+async fn force_quit(state: &state::ProxyState, service_name: &'static str, tui: bool) -> ! {
+    if tui {
+        tui::restore_terminal();
+    }
+    eprintln!("Forced shutdown; abandoning active requests.");
+    restore_client_config(service_name);
+    let path = crate::state::usage_rollup_path(service_name);
+    let flush = state.flush_usage_rollup(service_name, &path);
+    match tokio::time::timeout(std::time::Duration::from_secs(2), flush).await {
+        Ok(Ok(())) => {}
+        Ok(Err(e)) => tracing::warn!("failed to persist usage rollup: {}", e),
+        Err(_) => tracing::warn!("timed out persisting usage rollup"),
+    }
+    std::process::exit(130);
+}
+
+/// Waits for the server task to finish. Once shutdown is requested the listeners stop accepting
+/// and in-flight requests (including SSE streams) get up to `deadline` to complete. Progress is
+/// published via [`state::DrainStatus`] for the dashboard; while `console` is set a countdown with
+/// the remaining requests is also printed every second.
+async fn wait_until_drained(
+    server: tokio::task::JoinHandle<anyhow::Result<()>>,
+    shutdown_rx: tokio::sync::watch::Receiver<bool>,
+    state: &state::ProxyState,
+    deadline: std::time::Duration,
+    console: &AtomicBool,
+) -> anyhow::Result<()> {
+    let res = drain_server(server, shutdown_rx, state, deadline, console).await;
+    state.set_drain_status(state::DrainStatus::Done);
+    res
+}
+
+async fn drain_server(
+    mut server: tokio::task::JoinHandle<anyhow::Result<()>>,
+    mut shutdown_rx: tokio::sync::watch::Receiver<bool>,
+    state: &state::ProxyState,
+    deadline: std::time::Duration,
+    console: &AtomicBool,
+) -> anyhow::Result<()> {
+    let join = |res: Result<anyhow::Result<()>, tokio::task::JoinError>| {
+        res.map_err(|e| anyhow::anyhow!("server task join error: {e}"))?
+    };
+    tokio::select! {
+        res = &mut server => return join(res),
+        _ = shutdown_rx.wait_for(|stop| *stop) => {}
+    }
+
+    let unix_ms = || {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0)
+    };
+    state.set_drain_status(state::DrainStatus::Draining {
+        deadline_ms: unix_ms().saturating_add(deadline.as_millis() as u64),
+    });
+    let started = std::time::Instant::now();
+    let mut ticker = tokio::time::interval(std::time::Duration::from_secs(1));
+    let mut last_reported = None;
+    loop {
+        tokio::select! {
+            res = &mut server => return join(res),
+            _ = ticker.tick() => {}
+        }
+        let active = state.list_active_requests().await;
+        let elapsed = started.elapsed();
+        if elapsed >= deadline {
+            if !active.is_empty() {
+                tracing::warn!(
+                    "drain timeout reached; abandoning {} active request(s)",
+                    active.len()
+                );
+                if console.load(Ordering::Relaxed) {
+                    eprintln!(
+                        "Drain timeout reached; abandoning {} active request(s).",
+                        active.len()
+                    );
+                }
+            }
+            server.abort();
+            return Ok(());
+        }
+        if active.is_empty() {
+            // Only idle keep-alive connections are left; hyper closes those on its own.
+            continue;
+        }
+        let remaining = (deadline - elapsed).as_secs();
+        if !console.load(Ordering::Relaxed) {
+            continue;
+        }
+        let now_ms = unix_ms();
+        let ids: Vec<u64> = active.iter().map(|r| r.id).collect();
+        if last_reported.as_ref() != Some(&ids) || remaining.is_multiple_of(5) {
+            eprintln!(
+                "Waiting for {} active request(s) to finish ({}s left, Ctrl+C again to quit now):",
+                active.len(),
+                remaining
+            );
+            for r in active.iter().take(5) {
+                eprintln!(
+                    "  #{} {} {} [{}] {}s",
+                    r.id,
+                    r.method,
+                    r.path,
+                    r.config_name.as_deref().unwrap_or("-"),
+                    now_ms.saturating_sub(r.started_at_ms) / 1000
+                );
+            }
+            last_reported = Some(ids);
+        }
+    }
+}
+
 async fn wait_for_shutdown_signal() {
     #[cfg(unix)]
     {
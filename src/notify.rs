@@ -188,6 +188,7 @@ fn request_path_score(path: &str) -> u8 {
 
 async fn fetch_recent_finished(
     proxy_base_url: &str,
+    admin_token: Option<&str>,
     timeout_ms: u64,
 ) -> anyhow::Result<Vec<FinishedRequestLite>> {
     let url = format!(
@@ -197,7 +198,11 @@ async fn fetch_recent_finished(
     let client = reqwest::Client::builder()
         .timeout(Duration::from_millis(timeout_ms))
         .build()?;
-    let resp = client.get(url).send().await?;
+    let mut req = client.get(url);
+    if let Some(token) = admin_token {
+        req = req.header(crate::proxy::ADMIN_TOKEN_HEADER, token);
+    }
+    let resp = req.send().await?;
     let status = resp.status();
     if !status.is_success() {
         anyhow::bail!("proxy status/recent returned {}", status.as_u16());
@@ -278,8 +283,10 @@ pub async fn handle_codex_notify(
         }
     };
 
+    let admin_token = cfg.server.resolve_admin_token();
     let recent = match fetch_recent_finished(
         &proxy_base_url,
+        admin_token.as_deref(),
         notify_cfg.policy.recent_endpoint_timeout_ms,
     )
     .await
@@ -476,6 +483,35 @@ fn render_body(events: &[QueuedEvent]) -> String {
     lines.join("\n")
 }
 
+/// Runs the `notify.exec` command for an event raised by the proxy itself (e.g. a request
+/// rejected by a filter `block` rule). No-op unless notify and exec are both enabled.
+pub fn spawn_exec_event(cfg: &NotifyConfig, event: serde_json::Value) {
+    if !cfg.enabled || !cfg.exec.enabled || cfg.exec.command.is_empty() {
+        return;
+    }
+    let command = cfg.exec.command.clone();
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = run_exec_callback(&command, &event.to_string()) {
+            tracing::warn!("notify exec callback failed: {err}");
+        }
+    });
+}
+
+/// Raises a proxy-side alert: the `notify.exec` callback plus, where supported (Windows/macOS),
+/// a system notification when `notify.system` is enabled.
+pub fn spawn_proxy_alert(cfg: &NotifyConfig, title: &str, body: &str, event: serde_json::Value) {
+    spawn_exec_event(cfg, event);
+    if !cfg.enabled || !cfg.system.enabled || !cfg!(any(windows, target_os = "macos")) {
+        return;
+    }
+    let (title, body) = (title.to_string(), body.to_string());
+    tokio::task::spawn_blocking(move || {
+        if let Err(err) = send_system_notification(&title, &body) {
+            tracing::warn!("system notification failed: {err}");
+        }
+    });
+}
+
 fn run_exec_callback(command: &[String], input_json: &str) -> anyhow::Result<()> {
     if command.is_empty() {
         return Ok(());
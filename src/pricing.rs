@@ -0,0 +1,209 @@
+//! 费用估算：按 `[pricing]` 价格表把一次请求的 token 用量折算为金额。
+
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::{ModelPrice, PricingConfig};
+use crate::model_routing::match_wildcard;
+use crate::usage::UsageMetrics;
+
+pub const DEFAULT_CURRENCY: &str = "USD";
+
+/// Estimated cost of one request.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Cost {
+    pub amount: f64,
+    pub currency: String,
+}
+
+/// Estimated spend, summed per currency.
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(transparent)]
+pub struct CostTotals(BTreeMap<String, f64>);
+
+impl CostTotals {
+    pub fn add(&mut self, cost: &Cost) {
+        *self.0.entry(cost.currency.clone()).or_default() += cost.amount;
+    }
+
+    pub fn merge(&mut self, other: &CostTotals) {
+        for (currency, amount) in &other.0 {
+            *self.0.entry(currency.clone()).or_default() += amount;
+        }
+    }
+
+    pub fn get(&self, currency: &str) -> Option<f64> {
+        self.0.get(currency).copied()
+    }
+
+    pub fn currencies(&self) -> impl Iterator<Item = &str> {
+        self.0.keys().map(String::as_str)
+    }
+
+    /// `$1.23`, or `8.40 CNY + $1.23` when several currencies were used; `None` if nothing was
+    /// priced.
+    pub fn display(&self) -> Option<String> {
+        if self.0.is_empty() {
+            return None;
+        }
+        Some(
+            self.0
+                .iter()
+                .map(|(currency, amount)| format_amount(*amount, currency))
+                .collect::<Vec<_>>()
+                .join(" + "),
+        )
+    }
+}
+
+pub fn format_amount(amount: f64, currency: &str) -> String {
+    if currency.eq_ignore_ascii_case(DEFAULT_CURRENCY) {
+        format!("${amount:.2}")
+    } else {
+        format!("{amount:.2} {currency}")
+    }
+}
+
+/// Flat USD prices per 1K tokens from `CODEX_HELPER_PRICE_INPUT/OUTPUT_PER_1K_USD`, kept as a
+/// catch-all for setups that predate `[pricing]`.
+fn legacy_env_price() -> Option<ModelPrice> {
+    let read = |name: &str| {
+        std::env::var(name)
+            .ok()
+            .and_then(|s| s.trim().parse::<f64>().ok())
+            .filter(|v| v.is_finite() && *v >= 0.0)
+    };
+    let input = read("CODEX_HELPER_PRICE_INPUT_PER_1K_USD")?;
+    let output = read("CODEX_HELPER_PRICE_OUTPUT_PER_1K_USD")?;
+    Some(ModelPrice {
+        model: "*".to_string(),
+        input_per_1m: input * 1000.0,
+        output_per_1m: output * 1000.0,
+        currency: Some(DEFAULT_CURRENCY.to_string()),
+        ..Default::default()
+    })
+}
+
+/// The first `[[pricing.models]]` entry matching `model` and `provider_id`.
+pub fn find_price(
+    cfg: &PricingConfig,
+    model: Option<&str>,
+    provider_id: Option<&str>,
+) -> Option<ModelPrice> {
+    let model = model.unwrap_or("");
+    cfg.models
+        .iter()
+        .find(|p| {
+            match_wildcard(p.model.trim(), model)
+                && p.provider_id
+                    .as_deref()
+                    .is_none_or(|pid| Some(pid) == provider_id)
+        })
+        .cloned()
+        .or_else(legacy_env_price)
+}
+
+/// Cost of one request, or `None` when no price matches.
+pub fn estimate(
+    cfg: &PricingConfig,
+    model: Option<&str>,
+    provider_id: Option<&str>,
+    usage: &UsageMetrics,
+) -> Option<Cost> {
+    let price = find_price(cfg, model, provider_id)?;
+    let input = usage.input_tokens.max(0) as f64;
+    let cached = (usage.cached_input_tokens.max(0) as f64).min(input);
+    let cache_write = (usage.cache_write_tokens.max(0) as f64).min(input - cached);
+    let output = usage.output_tokens.max(0) as f64;
+    // Cached/cache-write tokens are part of the input, reasoning tokens part of the output.
+    let reasoning = (usage.reasoning_tokens.max(0) as f64).min(output);
+    let amount = ((input - cached - cache_write) * price.input_per_1m
+        + cached * price.cached_input_per_1m.unwrap_or(price.input_per_1m)
+        + cache_write * price.cache_write_per_1m.unwrap_or(price.input_per_1m)
+        + (output - reasoning) * price.output_per_1m
+        + reasoning * price.reasoning_per_1m.unwrap_or(price.output_per_1m))
+        / 1_000_000.0;
+    let currency = price
+        .currency
+        .or_else(|| cfg.currency.clone())
+        .unwrap_or_else(|| DEFAULT_CURRENCY.to_string());
+    Some(Cost { amount, currency })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_price_wins_and_splits_reasoning() {
+        let cfg = PricingConfig {
+            currency: Some("CNY".to_string()),
+            models: vec![
+                ModelPrice {
+                    model: "gpt-5*".to_string(),
+                    provider_id: Some("relay".to_string()),
+                    input_per_1m: 2.0,
+                    output_per_1m: 8.0,
+                    ..Default::default()
+                },
+                ModelPrice {
+                    model: "gpt-5*".to_string(),
+                    input_per_1m: 1.25,
+                    output_per_1m: 10.0,
+                    reasoning_per_1m: Some(20.0),
+                    currency: Some("USD".to_string()),
+                    ..Default::default()
+                },
+            ],
+        };
+        let usage = UsageMetrics {
+            input_tokens: 1_000_000,
+            output_tokens: 200_000,
+            reasoning_tokens: 100_000,
+            total_tokens: 1_200_000,
+            ..Default::default()
+        };
+
+        let relay = estimate(&cfg, Some("gpt-5-codex"), Some("relay"), &usage).unwrap();
+        assert_eq!(relay.currency, "CNY");
+        assert!((relay.amount - 3.6).abs() < 1e-9);
+
+        let direct = estimate(&cfg, Some("gpt-5"), Some("openai"), &usage).unwrap();
+        assert_eq!(direct.currency, "USD");
+        assert!((direct.amount - 4.25).abs() < 1e-9);
+
+        assert_eq!(find_price(&cfg, Some("claude-sonnet-4"), None), None);
+
+        let mut totals = CostTotals::default();
+        totals.add(&direct);
+        totals.add(&relay);
+        totals.add(&direct);
+        assert_eq!(totals.get("USD"), Some(8.5));
+        assert_eq!(totals.display().as_deref(), Some("3.60 CNY + $8.50"));
+
+        // 600k cached at 0.125, 200k written at 1.5625, 200k uncached at 1.25; 10k output.
+        let claude = PricingConfig {
+            currency: None,
+            models: vec![ModelPrice {
+                model: "claude-*".to_string(),
+                input_per_1m: 1.25,
+                cached_input_per_1m: Some(0.125),
+                cache_write_per_1m: Some(1.5625),
+                output_per_1m: 10.0,
+                ..Default::default()
+            }],
+        };
+        let cached = UsageMetrics {
+            input_tokens: 1_000_000,
+            cached_input_tokens: 600_000,
+            cache_write_tokens: 200_000,
+            output_tokens: 10_000,
+            total_tokens: 1_010_000,
+            ..Default::default()
+        };
+        let cost = estimate(&claude, Some("claude-sonnet-4"), None, &cached).unwrap();
+        assert_eq!(cost.currency, "USD");
+        assert!((cost.amount - (0.25 + 0.075 + 0.3125 + 0.1)).abs() < 1e-9);
+    }
+}
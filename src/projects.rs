@@ -0,0 +1,366 @@
+//! 按会话工作目录（cwd）匹配项目：`[projects]` 配置中的规则，以及仓库内的项目文件。
+
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde_json::Value;
+
+use crate::config::{ProjectRule, ProjectsConfig};
+
+/// Settings file a repository can ship: the fields of a `[[projects.rules]]` entry minus `path`.
+pub const REPO_SETTINGS_FILE: &str = ".codex-helper.toml";
+
+/// Instructions file a repository can ship; appended to every `/responses` turn in it.
+pub const REPO_INSTRUCTIONS_FILE: &str = ".codex-helper/instructions.md";
+
+/// Largest repo instructions file that is read; anything bigger is ignored with a warning.
+const MAX_REPO_FILE_BYTES: u64 = 64 * 1024;
+
+fn normalize(path: &str) -> String {
+    path.replace('\\', "/").trim_end_matches('/').to_string()
+}
+
+fn glob_regex(pattern: &str) -> Option<Regex> {
+    let pattern = normalize(&crate::credentials::expand_home(pattern.trim()).to_string_lossy());
+    if pattern.is_empty() {
+        return None;
+    }
+    let mut re = String::from("^");
+    let mut chars = pattern.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '*' if chars.peek() == Some(&'*') => {
+                chars.next();
+                re.push_str(".*");
+            }
+            '*' => re.push_str("[^/]*"),
+            '?' => re.push_str("[^/]"),
+            c => re.push_str(&regex::escape(&c.to_string())),
+        }
+    }
+    // A rule matching a directory also covers everything below it.
+    re.push_str("(?:/.*)?$");
+    Regex::new(&re).ok()
+}
+
+/// Whether `cwd` is, or is inside, a directory matched by the glob `pattern`.
+pub fn path_matches(pattern: &str, cwd: &str) -> bool {
+    glob_regex(pattern).is_some_and(|re| re.is_match(&normalize(cwd)))
+}
+
+/// Config rules whose `path` matches `cwd`, in config order.
+pub fn matching_rules<'a>(
+    cfg: &'a ProjectsConfig,
+    cwd: &'a str,
+) -> impl Iterator<Item = &'a ProjectRule> + 'a {
+    cfg.rules.iter().filter(move |r| path_matches(&r.path, cwd))
+}
+
+/// Looks for `rel` in `cwd` and its parents, stopping at the repository root (the first
+/// directory containing `.git`).
+async fn find_repo_file(cwd: &Path, rel: &str) -> Option<PathBuf> {
+    for dir in cwd.ancestors() {
+        let candidate = dir.join(rel);
+        if tokio::fs::metadata(&candidate)
+            .await
+            .is_ok_and(|m| m.is_file())
+        {
+            return Some(candidate);
+        }
+        if tokio::fs::metadata(dir.join(".git")).await.is_ok() {
+            break;
+        }
+    }
+    None
+}
+
+/// Extra instructions collected for one request.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProjectInstructions {
+    pub prepend: Vec<String>,
+    pub append: Vec<String>,
+    /// Where each piece came from (rule paths / files), for the request log.
+    pub sources: Vec<String>,
+}
+
+impl ProjectInstructions {
+    pub fn is_empty(&self) -> bool {
+        self.prepend.is_empty() && self.append.is_empty()
+    }
+}
+
+/// Everything the project rules decide for one request.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ProjectSettings {
+    pub config: Option<String>,
+    pub levels: BTreeMap<String, u8>,
+    pub model: Option<String>,
+    pub reasoning_effort: Option<String>,
+    pub instructions: ProjectInstructions,
+    /// Repo files that contributed to these settings, for the request log.
+    pub repo_files: Vec<String>,
+}
+
+impl ProjectSettings {
+    fn is_empty(&self) -> bool {
+        self.config.is_none()
+            && self.levels.is_empty()
+            && self.model.is_none()
+            && self.reasoning_effort.is_none()
+            && self.instructions.is_empty()
+    }
+}
+
+/// Logs the first time a repo file is applied; every request it affects also notes it in the
+/// request log.
+fn log_repo_file(file: &Path) {
+    static SEEN: std::sync::OnceLock<std::sync::Mutex<std::collections::HashSet<PathBuf>>> =
+        std::sync::OnceLock::new();
+    let mut seen = match SEEN.get_or_init(Default::default).lock() {
+        Ok(g) => g,
+        Err(e) => e.into_inner(),
+    };
+    if seen.insert(file.to_path_buf()) {
+        tracing::info!("applying repo project file {:?}", file);
+    }
+}
+
+async fn read_repo_file(file: &Path) -> Option<String> {
+    match tokio::fs::metadata(file).await {
+        Ok(m) if m.len() > MAX_REPO_FILE_BYTES => {
+            tracing::warn!(
+                "ignoring {:?}: larger than {} bytes",
+                file,
+                MAX_REPO_FILE_BYTES
+            );
+            None
+        }
+        _ => tokio::fs::read_to_string(file)
+            .await
+            .map_err(|e| tracing::warn!("failed to read {:?}: {}", file, e))
+            .ok(),
+    }
+}
+
+/// Resolves the project settings for a session running in `cwd`: matching config rules first,
+/// then the repository's `.codex-helper.toml` and instructions file.
+pub async fn resolve(cfg: &ProjectsConfig, cwd: &str) -> Option<ProjectSettings> {
+    fn non_empty(s: &Option<String>) -> Option<&str> {
+        s.as_deref().map(str::trim).filter(|s| !s.is_empty())
+    }
+
+    let mut rules = matching_rules(cfg, cwd)
+        .map(|r| (format!("projects.rules[{}]", r.path), r.clone()))
+        .collect::<Vec<_>>();
+    let repo_files = cfg.repo_files_enabled(!rules.is_empty());
+    let mut out = ProjectSettings::default();
+    if repo_files
+        && let Some(file) = find_repo_file(Path::new(cwd), REPO_SETTINGS_FILE).await
+        && let Some(text) = read_repo_file(&file).await
+    {
+        match toml::from_str::<ProjectRule>(&text) {
+            Ok(rule) => {
+                log_repo_file(&file);
+                out.repo_files.push(file.display().to_string());
+                rules.push((file.display().to_string(), rule));
+            }
+            Err(e) => tracing::warn!("ignoring {:?}: {}", file, e),
+        }
+    }
+
+    for (source, rule) in &rules {
+        out.config = out.config.or(non_empty(&rule.config).map(str::to_string));
+        out.model = out.model.or(non_empty(&rule.model).map(str::to_string));
+        out.reasoning_effort = out
+            .reasoning_effort
+            .or(non_empty(&rule.reasoning_effort).map(str::to_string));
+        for (name, level) in &rule.levels {
+            out.levels
+                .entry(name.clone())
+                .or_insert((*level).clamp(1, 10));
+        }
+
+        let before = non_empty(&rule.instructions_prepend);
+        let after = non_empty(&rule.instructions_append);
+        if before.is_some() || after.is_some() {
+            out.instructions.prepend.extend(before.map(str::to_string));
+            out.instructions.append.extend(after.map(str::to_string));
+            out.instructions.sources.push(source.clone());
+        }
+    }
+
+    if repo_files
+        && let Some(file) = find_repo_file(Path::new(cwd), REPO_INSTRUCTIONS_FILE).await
+        && let Some(text) = read_repo_file(&file).await
+        && !text.trim().is_empty()
+    {
+        log_repo_file(&file);
+        out.repo_files.push(file.display().to_string());
+        out.instructions.append.push(text.trim().to_string());
+        out.instructions.sources.push(file.display().to_string());
+    }
+
+    (!out.is_empty()).then_some(out)
+}
+
+/// Adds the project text around the request's top-level `instructions` string. Returns `None`
+/// when the body is not a JSON object or `instructions` is present but not a string.
+pub fn inject_instructions(body: &[u8], extra: &ProjectInstructions) -> Option<Vec<u8>> {
+    let mut v: Value = serde_json::from_slice(body).ok()?;
+    let obj = v.as_object_mut()?;
+    let current = match obj.get("instructions") {
+        None | Some(Value::Null) => "",
+        Some(Value::String(s)) => s.as_str(),
+        Some(_) => return None,
+    };
+    let merged = extra
+        .prepend
+        .iter()
+        .map(String::as_str)
+        .chain(std::iter::once(current.trim()))
+        .chain(extra.append.iter().map(String::as_str))
+        .filter(|s| !s.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n\n");
+    obj.insert("instructions".to_string(), Value::String(merged));
+    serde_json::to_vec(&v).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globs_match_the_directory_and_below() {
+        assert!(path_matches("/work/acme-*", "/work/acme-api"));
+        assert!(path_matches("/work/acme-*", "/work/acme-api/crates/core"));
+        assert!(!path_matches("/work/acme-*", "/work/other/acme-api"));
+        assert!(path_matches("/work/**/payments", "/work/a/b/payments/src"));
+        assert!(path_matches("/work/site/", "/work/site"));
+        assert!(!path_matches("/work/site", "/work/site-old"));
+        assert!(path_matches(r"C:\work\*", r"C:\work\acme\src"));
+    }
+
+    #[tokio::test]
+    async fn resolves_rules_and_repo_file_and_injects() {
+        let root =
+            std::env::temp_dir().join(format!("codex-helper-projects-{}", uuid::Uuid::new_v4()));
+        let repo = root.join("acme-api");
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::create_dir_all(repo.join(".codex-helper")).unwrap();
+        std::fs::create_dir_all(repo.join("src/deep")).unwrap();
+        std::fs::write(
+            repo.join(REPO_INSTRUCTIONS_FILE),
+            "Run cargo fmt before finishing.\n",
+        )
+        .unwrap();
+        // Above the repo root: never picked up.
+        std::fs::create_dir_all(root.join(".codex-helper")).unwrap();
+        std::fs::write(root.join(REPO_INSTRUCTIONS_FILE), "outside").unwrap();
+
+        let cfg = ProjectsConfig {
+            repo_files: None,
+            rules: vec![
+                ProjectRule {
+                    path: format!("{}/acme-*", root.display()),
+                    instructions_prepend: Some("You work for ACME.".to_string()),
+                    ..Default::default()
+                },
+                ProjectRule {
+                    path: "/elsewhere".to_string(),
+                    instructions_append: Some("nope".to_string()),
+                    ..Default::default()
+                },
+            ],
+        };
+        let cwd = repo.join("src/deep").display().to_string();
+        let extra = resolve(&cfg, &cwd).await.expect("settings").instructions;
+        assert_eq!(extra.prepend, vec!["You work for ACME."]);
+        assert_eq!(extra.append, vec!["Run cargo fmt before finishing."]);
+        assert_eq!(extra.sources.len(), 2);
+
+        let body = inject_instructions(br#"{"instructions":"Base prompt.","input":[]}"#, &extra)
+            .expect("rewritten");
+        let v: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            v["instructions"],
+            "You work for ACME.\n\nBase prompt.\n\nRun cargo fmt before finishing."
+        );
+        let body = inject_instructions(br#"{"input":[]}"#, &extra).expect("rewritten");
+        let v: Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(
+            v["instructions"],
+            "You work for ACME.\n\nRun cargo fmt before finishing."
+        );
+        assert_eq!(inject_instructions(br#"{"instructions":1}"#, &extra), None);
+
+        let no_repo = ProjectsConfig {
+            repo_files: Some(false),
+            rules: Vec::new(),
+        };
+        assert_eq!(resolve(&no_repo, &cwd).await, None);
+        // Without a matching rule the repo is untrusted unless repo files are enabled everywhere.
+        let untrusted = ProjectsConfig {
+            repo_files: None,
+            rules: Vec::new(),
+        };
+        assert_eq!(resolve(&untrusted, &cwd).await, None);
+        let everywhere = ProjectsConfig {
+            repo_files: Some(true),
+            rules: Vec::new(),
+        };
+        let settings = resolve(&everywhere, &cwd).await.expect("settings");
+        assert_eq!(
+            settings.repo_files,
+            vec![repo.join(REPO_INSTRUCTIONS_FILE).display().to_string()]
+        );
+        let _ = std::fs::remove_dir_all(&root);
+    }
+
+    #[tokio::test]
+    async fn config_rules_win_over_the_repo_settings_file() {
+        let repo =
+            std::env::temp_dir().join(format!("codex-helper-projects-{}", uuid::Uuid::new_v4()));
+        std::fs::create_dir_all(repo.join(".git")).unwrap();
+        std::fs::write(
+            repo.join(REPO_SETTINGS_FILE),
+            r#"
+config = "repo-relay"
+model = "gpt-5-mini"
+reasoning_effort = "low"
+instructions_append = "Repo note."
+
+[levels]
+repo-relay = 1
+company = 3
+"#,
+        )
+        .unwrap();
+        let cwd = repo.display().to_string();
+
+        let cfg = ProjectsConfig {
+            repo_files: None,
+            rules: vec![ProjectRule {
+                path: cwd.clone(),
+                config: Some("company".to_string()),
+                levels: BTreeMap::from([("company".to_string(), 0)]),
+                ..Default::default()
+            }],
+        };
+        let settings = resolve(&cfg, &cwd).await.expect("settings");
+        assert_eq!(settings.config.as_deref(), Some("company"));
+        assert_eq!(settings.model.as_deref(), Some("gpt-5-mini"));
+        assert_eq!(settings.reasoning_effort.as_deref(), Some("low"));
+        assert_eq!(
+            settings.levels,
+            BTreeMap::from([("company".to_string(), 1), ("repo-relay".to_string(), 1)])
+        );
+        assert_eq!(settings.instructions.append, vec!["Repo note."]);
+
+        std::fs::write(repo.join(REPO_SETTINGS_FILE), "config = [").unwrap();
+        let settings = resolve(&cfg, &cwd).await.expect("settings");
+        assert_eq!(settings.model, None);
+        let _ = std::fs::remove_dir_all(&repo);
+    }
+}
@@ -0,0 +1,467 @@
+use std::net::IpAddr;
+
+use axum::extract::{ConnectInfo, Request, State};
+use axum::http::{HeaderMap, StatusCode, header};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tracing::warn;
+
+use crate::client_keys::{ClientIdentity, ClientKeyRegistry};
+use crate::config::ServerConfig;
+
+use super::ProxyService;
+use super::listen::PeerAddr;
+
+pub const CLIENT_TOKEN_HEADER: &str = "x-codex-helper-token";
+pub const ADMIN_TOKEN_HEADER: &str = "x-codex-helper-admin-token";
+const ADMIN_PATH_PREFIX: &str = "/__codex_helper/";
+
+/// An `allowed_ips` entry: a single address or a CIDR range.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct IpRule {
+    net: IpAddr,
+    prefix: u8,
+}
+
+impl IpRule {
+    fn parse(raw: &str) -> Option<Self> {
+        let raw = raw.trim();
+        let (addr, prefix) = match raw.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix.trim().parse::<u8>().ok()?)),
+            None => (raw, None),
+        };
+        let net: IpAddr = addr
+            .trim()
+            .trim_start_matches('[')
+            .trim_end_matches(']')
+            .parse()
+            .ok()?;
+        let max = if net.is_ipv4() { 32 } else { 128 };
+        let prefix = prefix.unwrap_or(max);
+        (prefix <= max).then_some(Self { net, prefix })
+    }
+
+    fn contains(&self, ip: IpAddr) -> bool {
+        match (self.net, normalize(ip)) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                prefix_eq(&net.octets(), &ip.octets(), self.prefix)
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                prefix_eq(&net.octets(), &ip.octets(), self.prefix)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Dual-stack listeners report IPv4 clients as `::ffff:a.b.c.d`.
+fn normalize(ip: IpAddr) -> IpAddr {
+    match ip {
+        IpAddr::V6(v6) => v6
+            .to_ipv4_mapped()
+            .map(IpAddr::V4)
+            .unwrap_or(IpAddr::V6(v6)),
+        v4 => v4,
+    }
+}
+
+fn prefix_eq(a: &[u8], b: &[u8], prefix: u8) -> bool {
+    let full = usize::from(prefix / 8);
+    if a[..full] != b[..full] {
+        return false;
+    }
+    let rem = prefix % 8;
+    if rem == 0 {
+        return true;
+    }
+    let mask = 0xffu8 << (8 - rem);
+    a[full] & mask == b[full] & mask
+}
+
+fn ip_allowed(allowed: &[String], ip: IpAddr) -> bool {
+    if allowed.is_empty() || normalize(ip).is_loopback() {
+        return true;
+    }
+    allowed.iter().any(|raw| match IpRule::parse(raw) {
+        Some(rule) => rule.contains(ip),
+        None => {
+            warn!("server.allowed_ips: ignoring invalid entry '{}'", raw);
+            false
+        }
+    })
+}
+
+/// Compares without short-circuiting so response timing does not leak the token prefix.
+fn token_eq(a: &str, b: &str) -> bool {
+    let (a, b) = (a.as_bytes(), b.as_bytes());
+    a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Which header carried a valid token, so it can be stripped before forwarding.
+#[derive(Debug, PartialEq, Eq)]
+enum TokenMatch {
+    Header,
+    Bearer,
+}
+
+fn match_token(headers: &HeaderMap, header_name: &str, expected: &str) -> Option<TokenMatch> {
+    let header_value = headers.get(header_name).and_then(|v| v.to_str().ok());
+    if header_value.is_some_and(|v| token_eq(v.trim(), expected)) {
+        return Some(TokenMatch::Header);
+    }
+    let bearer = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    bearer
+        .is_some_and(|v| token_eq(v.trim(), expected))
+        .then_some(TokenMatch::Bearer)
+}
+
+/// The token a client presented: the dedicated header wins over `Authorization: Bearer`.
+fn presented_token(headers: &HeaderMap, header_name: &str) -> Option<(String, TokenMatch)> {
+    if let Some(v) = headers.get(header_name).and_then(|v| v.to_str().ok()) {
+        return Some((v.trim().to_string(), TokenMatch::Header));
+    }
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .map(|v| (v.trim().to_string(), TokenMatch::Bearer))
+}
+
+fn reject(status: StatusCode, message: &str) -> Response {
+    let kind = if status == StatusCode::TOO_MANY_REQUESTS {
+        "insufficient_quota"
+    } else {
+        "codex_helper_access_denied"
+    };
+    let body = serde_json::json!({
+        "error": {
+            "message": message,
+            "type": kind,
+        }
+    });
+    (status, axum::Json(body)).into_response()
+}
+
+/// Checks IP, admin and client credentials; returns the client key identity when one was used.
+fn check_request(
+    server: &ServerConfig,
+    registry: &ClientKeyRegistry,
+    peer: Option<PeerAddr>,
+    path: &str,
+    headers: &mut HeaderMap,
+) -> Result<Option<ClientIdentity>, (StatusCode, &'static str)> {
+    if let Some(PeerAddr::Tcp(addr)) = peer
+        && !ip_allowed(&server.allowed_ips, addr.ip())
+    {
+        warn!(
+            "rejected request from {} (not in server.allowed_ips)",
+            addr.ip()
+        );
+        return Err((StatusCode::FORBIDDEN, "client IP is not allowed"));
+    }
+
+    // In-process callers (tests, embedding) have no peer address and are treated as local.
+    // Anyone who can open the Unix socket could be a container or another user, so socket
+    // clients only count as local when the config says so.
+    let is_local = match peer {
+        None => true,
+        Some(PeerAddr::Tcp(addr)) => normalize(addr.ip()).is_loopback(),
+        Some(PeerAddr::Unix) => server.trust_unix_socket(),
+    };
+
+    if path.starts_with(ADMIN_PATH_PREFIX) {
+        return match server.resolve_admin_token() {
+            Some(expected) => match match_token(headers, ADMIN_TOKEN_HEADER, &expected) {
+                Some(_) => Ok(None),
+                None => Err((StatusCode::UNAUTHORIZED, "missing or invalid admin token")),
+            },
+            None if is_local => Ok(None),
+            None if peer == Some(PeerAddr::Unix) => Err((
+                StatusCode::FORBIDDEN,
+                "admin endpoints over the Unix socket need server.admin_token (or server.trust_unix_socket = true)",
+            )),
+            None => Err((
+                StatusCode::FORBIDDEN,
+                "admin endpoints are only available from loopback unless server.admin_token is set",
+            )),
+        };
+    }
+
+    // Any active client key turns on team mode: every proxy request must then carry a credential.
+    let shared = server.resolve_client_token();
+    if shared.is_none() && !registry.has_active_keys() {
+        return Ok(None);
+    }
+    const INVALID: (StatusCode, &str) = (
+        StatusCode::UNAUTHORIZED,
+        "missing or invalid codex-helper client token",
+    );
+    let (token, via) = presented_token(headers, CLIENT_TOKEN_HEADER).ok_or(INVALID)?;
+    let identity = if shared.as_deref().is_some_and(|t| token_eq(&token, t)) {
+        None
+    } else {
+        let key = registry.find_by_token(&token).ok_or(INVALID)?;
+        Some(ClientIdentity {
+            label: key.label.clone(),
+            allowed_configs: key.allowed_configs.clone(),
+            daily_token_budget: key.daily_token_budget,
+        })
+    };
+    match via {
+        TokenMatch::Header => headers.remove(CLIENT_TOKEN_HEADER),
+        // Our token must never reach the upstream as a passthrough credential.
+        TokenMatch::Bearer => headers.remove(header::AUTHORIZATION),
+    };
+    Ok(identity)
+}
+
+/// Enforces `[server]` access control before any route runs.
+///
+/// The config snapshot is read per request, so token and allowlist changes apply on reload.
+pub(super) async fn enforce_access(
+    State(proxy): State<ProxyService>,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    let cfg = proxy.config.snapshot().await;
+    let peer = req
+        .extensions()
+        .get::<ConnectInfo<PeerAddr>>()
+        .map(|ci| ci.0);
+    let path = req.uri().path().to_string();
+    let registry = crate::client_keys::current_registry();
+    let identity = match check_request(&cfg.server, &registry, peer, &path, req.headers_mut()) {
+        Ok(identity) => identity,
+        Err((status, message)) => return reject(status, message),
+    };
+    if let Some(identity) = identity {
+        if let Some(budget) = identity.daily_token_budget {
+            let today = (std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0)
+                / 86_400_000) as i32;
+            let used = proxy
+                .state
+                .client_tokens_on_day(&identity.label, today)
+                .await;
+            if used >= budget as i64 {
+                warn!(
+                    "client key '{}' exceeded its daily token budget ({} / {})",
+                    identity.label, used, budget
+                );
+                return reject(
+                    StatusCode::TOO_MANY_REQUESTS,
+                    "daily token budget for this client key is exhausted",
+                );
+            }
+        }
+        req.extensions_mut().insert(identity);
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tcp(ip: IpAddr) -> PeerAddr {
+        PeerAddr::Tcp(std::net::SocketAddr::new(ip, 40_000))
+    }
+
+    #[test]
+    fn ip_rules_match_cidrs_and_mapped_v4() {
+        let allowed = vec!["192.168.1.0/24".to_string(), "2001:db8::/32".to_string()];
+        assert!(ip_allowed(&allowed, "192.168.1.42".parse().unwrap()));
+        assert!(ip_allowed(&allowed, "::ffff:192.168.1.7".parse().unwrap()));
+        assert!(ip_allowed(&allowed, "2001:db8::5".parse().unwrap()));
+        assert!(ip_allowed(&allowed, "127.0.0.1".parse().unwrap()));
+        assert!(!ip_allowed(&allowed, "192.168.2.1".parse().unwrap()));
+        assert!(!ip_allowed(&allowed, "10.0.0.1".parse().unwrap()));
+        assert!(IpRule::parse("10.0.0.0/33").is_none());
+        assert!(IpRule::parse("not-an-ip").is_none());
+    }
+
+    #[test]
+    fn client_token_is_checked_and_stripped() {
+        let server = ServerConfig {
+            client_token: Some("team-secret".to_string()),
+            ..Default::default()
+        };
+        let lan: IpAddr = "192.168.1.10".parse().unwrap();
+
+        let mut headers = HeaderMap::new();
+        assert!(
+            check_request(
+                &server,
+                &ClientKeyRegistry::default(),
+                Some(tcp(lan)),
+                "/v1/responses",
+                &mut headers
+            )
+            .is_err()
+        );
+
+        headers.insert(CLIENT_TOKEN_HEADER, "team-secret".parse().unwrap());
+        assert!(
+            check_request(
+                &server,
+                &ClientKeyRegistry::default(),
+                Some(tcp(lan)),
+                "/v1/responses",
+                &mut headers
+            )
+            .is_ok()
+        );
+        assert!(headers.get(CLIENT_TOKEN_HEADER).is_none());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(header::AUTHORIZATION, "Bearer team-secret".parse().unwrap());
+        assert!(
+            check_request(
+                &server,
+                &ClientKeyRegistry::default(),
+                Some(tcp(lan)),
+                "/v1/responses",
+                &mut headers
+            )
+            .is_ok()
+        );
+        assert!(headers.get(header::AUTHORIZATION).is_none());
+    }
+
+    #[test]
+    fn admin_routes_need_admin_token_or_loopback() {
+        let lan: IpAddr = "192.168.1.10".parse().unwrap();
+        let local: IpAddr = "127.0.0.1".parse().unwrap();
+        let path = "/__codex_helper/config/reload";
+
+        let open = ServerConfig::default();
+        assert!(
+            check_request(
+                &open,
+                &ClientKeyRegistry::default(),
+                Some(tcp(local)),
+                path,
+                &mut HeaderMap::new()
+            )
+            .is_ok()
+        );
+        assert!(
+            check_request(
+                &open,
+                &ClientKeyRegistry::default(),
+                Some(tcp(lan)),
+                path,
+                &mut HeaderMap::new()
+            )
+            .is_err()
+        );
+
+        let guarded = ServerConfig {
+            client_token: Some("client".to_string()),
+            admin_token: Some("admin".to_string()),
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        headers.insert(CLIENT_TOKEN_HEADER, "client".parse().unwrap());
+        assert!(
+            check_request(
+                &guarded,
+                &ClientKeyRegistry::default(),
+                Some(tcp(local)),
+                path,
+                &mut headers
+            )
+            .is_err()
+        );
+        headers.insert(ADMIN_TOKEN_HEADER, "admin".parse().unwrap());
+        assert!(
+            check_request(
+                &guarded,
+                &ClientKeyRegistry::default(),
+                Some(tcp(lan)),
+                path,
+                &mut headers
+            )
+            .is_ok()
+        );
+    }
+
+    #[test]
+    fn unix_socket_clients_need_admin_token_unless_trusted() {
+        let path = "/__codex_helper/status/active";
+        let open = ServerConfig {
+            allowed_ips: vec!["10.0.0.0/8".to_string()],
+            ..Default::default()
+        };
+        let check = |server: &ServerConfig, headers: &mut HeaderMap| {
+            check_request(
+                server,
+                &ClientKeyRegistry::default(),
+                Some(PeerAddr::Unix),
+                path,
+                headers,
+            )
+        };
+        assert_eq!(
+            check(&open, &mut HeaderMap::new()).unwrap_err().0,
+            StatusCode::FORBIDDEN
+        );
+        // Proxy routes over the socket are not subject to the IP allowlist.
+        assert!(
+            check_request(
+                &open,
+                &ClientKeyRegistry::default(),
+                Some(PeerAddr::Unix),
+                "/v1/responses",
+                &mut HeaderMap::new()
+            )
+            .is_ok()
+        );
+
+        let trusted = ServerConfig {
+            trust_unix_socket: Some(true),
+            ..Default::default()
+        };
+        assert!(check(&trusted, &mut HeaderMap::new()).is_ok());
+
+        let guarded = ServerConfig {
+            admin_token: Some("admin".to_string()),
+            ..Default::default()
+        };
+        let mut headers = HeaderMap::new();
+        assert!(check(&guarded, &mut headers).is_err());
+        headers.insert(ADMIN_TOKEN_HEADER, "admin".parse().unwrap());
+        assert!(check(&guarded, &mut headers).is_ok());
+    }
+
+    #[test]
+    fn client_keys_enable_team_mode_and_carry_identity() {
+        let mut registry = ClientKeyRegistry::default();
+        let (_, plaintext) = registry
+            .create("bob", vec!["relay".to_string()], Some(500), 1)
+            .expect("create");
+        let server = ServerConfig::default();
+        let path = "/v1/responses";
+
+        // No shared token configured, but an active key still makes credentials mandatory.
+        assert!(check_request(&server, &registry, None, path, &mut HeaderMap::new()).is_err());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            format!("Bearer {plaintext}").parse().unwrap(),
+        );
+        let identity = check_request(&server, &registry, None, path, &mut headers)
+            .expect("valid key")
+            .expect("identity");
+        assert_eq!(identity.label, "bob");
+        assert_eq!(identity.allowed_configs, vec!["relay".to_string()]);
+        assert_eq!(identity.daily_token_budget, Some(500));
+        assert!(headers.get(header::AUTHORIZATION).is_none());
+    }
+}
@@ -0,0 +1,326 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock};
+
+use axum::body::{Body, Bytes};
+use futures_util::{Stream, StreamExt, TryStreamExt};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+type BoxError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Bodies whose declared length is at most this are always buffered: cheap, and keeps
+/// model/effort visible in logs even when nothing needs rewriting.
+pub(super) const BUFFER_THRESHOLD_BYTES: u64 = 1024 * 1024;
+
+const SPOOL_CHUNK_BYTES: usize = 64 * 1024;
+
+#[derive(Debug)]
+pub(super) enum BodyReadError {
+    TooLarge { limit: usize },
+    Read(String),
+}
+
+impl std::fmt::Display for BodyReadError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            BodyReadError::TooLarge { limit } => {
+                write!(f, "request body exceeds the {limit} byte limit")
+            }
+            BodyReadError::Read(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for BodyReadError {}
+
+/// The client body, held in whichever form the request needs.
+pub(super) enum ClientBody {
+    /// Read into memory; required whenever the body may be inspected or rewritten.
+    Buffered(Bytes),
+    /// Forwarded as it arrives, counting the bytes sent. It can be sent once, so this is only
+    /// used without retries.
+    Streaming(Option<Body>, Arc<AtomicUsize>),
+    /// Written to a temp file so every retry attempt can re-send it.
+    Spooled(SpooledBody),
+}
+
+impl ClientBody {
+    /// Body length; without `Content-Length` a streamed body only knows the bytes forwarded so far.
+    pub(super) fn len(&self, content_length: Option<u64>) -> usize {
+        match self {
+            ClientBody::Buffered(b) => b.len(),
+            ClientBody::Streaming(_, sent) => content_length
+                .map(|n| n as usize)
+                .unwrap_or_else(|| sent.load(Ordering::Relaxed)),
+            ClientBody::Spooled(s) => s.len as usize,
+        }
+    }
+
+    /// A body for one upstream attempt; fails if a streamed body was already sent.
+    pub(super) async fn upstream_body(&mut self, limit: usize) -> Result<reqwest::Body, String> {
+        match self {
+            ClientBody::Buffered(b) => Ok(reqwest::Body::from(b.clone())),
+            ClientBody::Streaming(slot, sent) => {
+                let body = slot
+                    .take()
+                    .ok_or_else(|| "streamed request body was already forwarded".to_string())?;
+                let sent = sent.clone();
+                Ok(reqwest::Body::wrap_stream(limited(body, limit).inspect(
+                    move |chunk| {
+                        if let Ok(chunk) = chunk {
+                            sent.fetch_add(chunk.len(), Ordering::Relaxed);
+                        }
+                    },
+                )))
+            }
+            ClientBody::Spooled(s) => s
+                .reader_body()
+                .await
+                .map_err(|e| format!("reopen spooled request body: {e}")),
+        }
+    }
+}
+
+/// Reads the whole body into memory, failing with `TooLarge` past `limit`.
+pub(super) async fn read_to_bytes(body: Body, limit: usize) -> Result<Bytes, BodyReadError> {
+    let mut buf = Vec::new();
+    let mut stream = std::pin::pin!(limited(body, limit));
+    while let Some(chunk) = stream.next().await {
+        buf.extend_from_slice(&chunk.map_err(into_read_error)?);
+    }
+    Ok(Bytes::from(buf))
+}
+
+fn into_read_error(e: BoxError) -> BodyReadError {
+    match e.downcast::<BodyReadError>() {
+        Ok(e) => *e,
+        Err(e) => BodyReadError::Read(e.to_string()),
+    }
+}
+
+/// Enforces the body limit on a stream, failing the transfer once it is exceeded.
+fn limited(body: Body, limit: usize) -> impl Stream<Item = Result<Bytes, BoxError>> + Send {
+    let mut seen = 0usize;
+    body.into_data_stream().map(move |chunk| {
+        let chunk = chunk.map_err(|e| Box::new(e) as BoxError)?;
+        seen += chunk.len();
+        if seen > limit {
+            return Err(Box::new(BodyReadError::TooLarge { limit }) as BoxError);
+        }
+        Ok(chunk)
+    })
+}
+
+fn spool_root() -> PathBuf {
+    crate::config::proxy_home_dir().join("spool")
+}
+
+/// This process's spool directory, `~/.codex-helper/spool/<pid>`. Its `.lock` file stays locked
+/// while the process runs, which is how [`clear_stale_spool_dirs`] tells live directories apart.
+struct SpoolDir {
+    path: PathBuf,
+    _lock: std::fs::File,
+}
+
+fn spool_dir() -> std::io::Result<&'static Path> {
+    static DIR: OnceLock<Result<SpoolDir, String>> = OnceLock::new();
+    DIR.get_or_init(|| {
+        open_spool_dir(spool_root().join(std::process::id().to_string())).map_err(|e| e.to_string())
+    })
+    .as_ref()
+    .map(|d| d.path.as_path())
+    .map_err(|e| std::io::Error::other(e.clone()))
+}
+
+fn open_spool_dir(path: PathBuf) -> std::io::Result<SpoolDir> {
+    let mut builder = std::fs::DirBuilder::new();
+    builder.recursive(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::DirBuilderExt;
+        builder.mode(0o700);
+    }
+    builder.create(&path)?;
+    let lock = std::fs::OpenOptions::new()
+        .create(true)
+        .truncate(false)
+        .write(true)
+        .open(path.join(".lock"))?;
+    lock.try_lock().map_err(std::io::Error::from)?;
+    Ok(SpoolDir { path, _lock: lock })
+}
+
+/// Removes spool directories left behind by processes that are gone (a forced quit skips `Drop`).
+pub fn clear_stale_spool_dirs() {
+    clear_stale_spool_dirs_in(&spool_root());
+}
+
+fn clear_stale_spool_dirs_in(root: &Path) {
+    let Ok(entries) = std::fs::read_dir(root) else {
+        return;
+    };
+    for entry in entries.flatten() {
+        let dir = entry.path();
+        if !dir.is_dir() {
+            continue;
+        }
+        // A live owner holds the lock; a missing lock file means a half-created, dead directory.
+        let owned = std::fs::OpenOptions::new()
+            .write(true)
+            .open(dir.join(".lock"))
+            .is_ok_and(|f| f.try_lock().is_err());
+        if !owned && let Err(e) = std::fs::remove_dir_all(&dir) {
+            tracing::warn!("failed to remove stale spool dir {:?}: {}", dir, e);
+        }
+    }
+}
+
+/// A request body on disk; the file is removed when this is dropped.
+pub(super) struct SpooledBody {
+    path: PathBuf,
+    len: u64,
+}
+
+impl SpooledBody {
+    pub(super) async fn spool(body: Body, limit: usize) -> Result<Self, BodyReadError> {
+        let io_err = |e: std::io::Error| BodyReadError::Read(format!("spool request body: {e}"));
+        // Request bodies hold prompts and file contents: private to this user, never overwritten.
+        let path = spool_dir()
+            .map_err(io_err)?
+            .join(format!("body-{}.tmp", uuid::Uuid::new_v4().simple()));
+        let mut options = tokio::fs::OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        options.mode(0o600);
+        let mut file = options.open(&path).await.map_err(io_err)?;
+        // From here on `spooled` owns the file, so early returns still clean it up.
+        let mut spooled = SpooledBody { path, len: 0 };
+        let mut stream = std::pin::pin!(limited(body, limit));
+        while let Some(chunk) = stream.next().await {
+            let chunk = chunk.map_err(into_read_error)?;
+            file.write_all(&chunk).await.map_err(io_err)?;
+            spooled.len += chunk.len() as u64;
+        }
+        file.flush().await.map_err(io_err)?;
+        Ok(spooled)
+    }
+
+    async fn reader_body(&self) -> std::io::Result<reqwest::Body> {
+        let file = tokio::fs::File::open(&self.path).await?;
+        let stream = futures_util::stream::try_unfold(file, |mut file| async move {
+            let mut buf = vec![0u8; SPOOL_CHUNK_BYTES];
+            let n = file.read(&mut buf).await?;
+            if n == 0 {
+                return Ok(None);
+            }
+            buf.truncate(n);
+            Ok::<_, std::io::Error>(Some((Bytes::from(buf), file)))
+        });
+        Ok(reqwest::Body::wrap_stream(stream.into_stream()))
+    }
+
+    /// Reads the top-level `model` / `reasoning.effort` without loading the whole body.
+    pub(super) async fn probe_model_and_effort(&self) -> (Option<String>, Option<String>) {
+        #[derive(serde::Deserialize)]
+        struct Reasoning {
+            effort: Option<String>,
+        }
+        #[derive(serde::Deserialize)]
+        struct Probe {
+            model: Option<String>,
+            reasoning: Option<Reasoning>,
+        }
+        let path = self.path.clone();
+        let probe = tokio::task::spawn_blocking(move || {
+            let file = std::fs::File::open(&path).ok()?;
+            serde_json::from_reader::<_, Probe>(std::io::BufReader::new(file)).ok()
+        });
+        match probe.await {
+            Ok(Some(p)) => (p.model, p.reasoning.and_then(|r| r.effort)),
+            _ => (None, None),
+        }
+    }
+}
+
+impl Drop for SpooledBody {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.path);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn spooled_body_replays_and_enforces_limit() {
+        let payload = br#"{"model":"gpt-5","reasoning":{"effort":"high"},"input":"hello"}"#;
+        let mut body = ClientBody::Spooled(
+            SpooledBody::spool(Body::from(payload.to_vec()), 1024)
+                .await
+                .expect("spool"),
+        );
+        if let ClientBody::Spooled(s) = &body {
+            assert_eq!(
+                s.probe_model_and_effort().await,
+                (Some("gpt-5".to_string()), Some("high".to_string()))
+            );
+        }
+        // Each attempt gets the full body again.
+        for _ in 0..2 {
+            let b = body.upstream_body(1024).await.expect("body");
+            let bytes = axum::body::to_bytes(Body::new(b), 4096)
+                .await
+                .expect("read");
+            assert_eq!(bytes.as_ref(), payload);
+        }
+
+        #[cfg(unix)]
+        if let ClientBody::Spooled(s) = &body {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&s.path).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+
+        let err = SpooledBody::spool(Body::from(payload.to_vec()), 10)
+            .await
+            .err()
+            .expect("too large");
+        assert!(matches!(err, BodyReadError::TooLarge { limit: 10 }));
+    }
+
+    #[tokio::test]
+    async fn streamed_body_counts_forwarded_bytes_without_content_length() {
+        let payload = br#"{"model":"gpt-5","input":"hello"}"#;
+        let mut body =
+            ClientBody::Streaming(Some(Body::from(payload.to_vec())), Default::default());
+        assert_eq!(body.len(None), 0);
+        assert_eq!(body.len(Some(7)), 7);
+
+        let b = body.upstream_body(1024).await.expect("body");
+        axum::body::to_bytes(Body::new(b), 4096)
+            .await
+            .expect("read");
+        assert_eq!(body.len(None), payload.len());
+        assert!(body.upstream_body(1024).await.is_err(), "sent only once");
+    }
+
+    #[test]
+    fn stale_spool_dirs_are_cleared_but_live_ones_kept() {
+        let root =
+            std::env::temp_dir().join(format!("codex-helper-spool-{}", uuid::Uuid::new_v4()));
+        let live = open_spool_dir(root.join("live")).expect("live dir");
+        std::fs::write(live.path.join("body-a.tmp"), b"x").unwrap();
+        let dead = open_spool_dir(root.join("dead")).expect("dead dir");
+        std::fs::write(dead.path.join("body-b.tmp"), b"x").unwrap();
+        drop(dead);
+        std::fs::create_dir_all(root.join("no-lock")).unwrap();
+
+        clear_stale_spool_dirs_in(&root);
+        assert!(live.path.join("body-a.tmp").exists());
+        assert!(!root.join("dead").exists());
+        assert!(!root.join("no-lock").exists());
+        drop(live);
+        let _ = std::fs::remove_dir_all(&root);
+    }
+}
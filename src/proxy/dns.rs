@@ -0,0 +1,171 @@
+use std::collections::{BTreeMap, HashMap};
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+
+use anyhow::{Result, anyhow};
+use reqwest::Client;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use tracing::warn;
+
+use crate::config::{IpPreference, UpstreamConfig};
+
+/// DNS resolver used for upstreams that configure `resolve` and/or `ip_preference`.
+///
+/// Only address lookup is affected: reqwest still uses the URL hostname for TLS SNI and the
+/// `Host` header, so pinning an edge IP behaves like curl `--resolve`.
+struct UpstreamResolver {
+    overrides: HashMap<String, Vec<SocketAddr>>,
+    preference: Option<IpPreference>,
+}
+
+impl Resolve for UpstreamResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_ascii_lowercase();
+        let pinned = self.overrides.get(&host).cloned();
+        let preference = self.preference;
+        Box::pin(async move {
+            let mut addrs: Vec<SocketAddr> = match pinned {
+                Some(addrs) => addrs,
+                None => tokio::net::lookup_host((host.as_str(), 0)).await?.collect(),
+            };
+            order_by_preference(&mut addrs, preference);
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Stable-sort addresses so the preferred family comes first.
+///
+/// hyper's connector treats the family of the first address as primary and only falls back to
+/// the other family after the happy-eyeballs delay, so ordering is enough to express a preference.
+fn order_by_preference(addrs: &mut [SocketAddr], preference: Option<IpPreference>) {
+    match preference {
+        Some(IpPreference::Ipv4) => addrs.sort_by_key(|a| !a.is_ipv4()),
+        Some(IpPreference::Ipv6) => addrs.sort_by_key(|a| !a.is_ipv6()),
+        None => {}
+    }
+}
+
+fn parse_resolve_overrides(
+    resolve: &HashMap<String, Vec<String>>,
+) -> Result<HashMap<String, Vec<SocketAddr>>> {
+    let mut out = HashMap::new();
+    for (host, ips) in resolve {
+        let host_key = host.trim().to_ascii_lowercase();
+        if host_key.is_empty() {
+            return Err(anyhow!("resolve: empty hostname"));
+        }
+        let mut addrs = Vec::with_capacity(ips.len());
+        for raw in ips {
+            let s = raw.trim();
+            let s = s
+                .strip_prefix('[')
+                .and_then(|x| x.strip_suffix(']'))
+                .unwrap_or(s);
+            let ip: IpAddr = s
+                .parse()
+                .map_err(|_| anyhow!("resolve: invalid IP '{raw}' for host '{host}'"))?;
+            // Port 0 lets reqwest use the port from the URL (or the scheme default).
+            addrs.push(SocketAddr::new(ip, 0));
+        }
+        if addrs.is_empty() {
+            return Err(anyhow!("resolve: no IPs configured for host '{host}'"));
+        }
+        out.insert(host_key, addrs);
+    }
+    Ok(out)
+}
+
+fn cache_key(upstream: &UpstreamConfig) -> String {
+    let sorted: BTreeMap<_, _> = upstream.resolve.iter().collect();
+    format!("{:?}|{:?}", sorted, upstream.ip_preference)
+}
+
+fn build_client(upstream: &UpstreamConfig) -> Result<Client> {
+    let resolver = UpstreamResolver {
+        overrides: parse_resolve_overrides(&upstream.resolve)?,
+        preference: upstream.ip_preference,
+    };
+    Ok(Client::builder().dns_resolver(Arc::new(resolver)).build()?)
+}
+
+/// Per-upstream HTTP clients, keyed by their DNS settings.
+///
+/// Upstreams without `resolve` / `ip_preference` share the proxy's default client.
+#[derive(Clone, Default)]
+pub(super) struct UpstreamClients {
+    inner: Arc<Mutex<HashMap<String, Client>>>,
+}
+
+impl UpstreamClients {
+    pub(super) fn client_for(&self, default: &Client, upstream: &UpstreamConfig) -> Client {
+        if upstream.resolve.is_empty() && upstream.ip_preference.is_none() {
+            return default.clone();
+        }
+        let key = cache_key(upstream);
+        let mut guard = match self.inner.lock() {
+            Ok(g) => g,
+            Err(e) => e.into_inner(),
+        };
+        if let Some(client) = guard.get(&key) {
+            return client.clone();
+        }
+        let client = match build_client(upstream) {
+            Ok(c) => c,
+            Err(e) => {
+                // Cache the fallback as well so a bad entry is only reported once per config.
+                warn!(
+                    "upstream {} DNS override ignored: {}; using system resolver",
+                    upstream.base_url, e
+                );
+                default.clone()
+            }
+        };
+        guard.insert(key, client.clone());
+        client
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_resolve_overrides_accepts_v4_and_bracketed_v6() {
+        let mut resolve = HashMap::new();
+        resolve.insert(
+            "API.Example.com".to_string(),
+            vec!["203.0.113.10".to_string(), "[2001:db8::1]".to_string()],
+        );
+        let parsed = parse_resolve_overrides(&resolve).expect("parse");
+        let addrs = parsed.get("api.example.com").expect("lowercased host");
+        assert_eq!(addrs.len(), 2);
+        assert!(addrs[0].is_ipv4());
+        assert!(addrs[1].is_ipv6());
+        assert!(addrs.iter().all(|a| a.port() == 0));
+    }
+
+    #[test]
+    fn parse_resolve_overrides_rejects_hostnames() {
+        let mut resolve = HashMap::new();
+        resolve.insert(
+            "api.example.com".to_string(),
+            vec!["edge.example.net".to_string()],
+        );
+        assert!(parse_resolve_overrides(&resolve).is_err());
+    }
+
+    #[test]
+    fn order_by_preference_is_stable() {
+        let v4a: SocketAddr = "203.0.113.1:0".parse().unwrap();
+        let v4b: SocketAddr = "203.0.113.2:0".parse().unwrap();
+        let v6: SocketAddr = "[2001:db8::1]:0".parse().unwrap();
+
+        let mut addrs = vec![v6, v4a, v4b];
+        order_by_preference(&mut addrs, Some(IpPreference::Ipv4));
+        assert_eq!(addrs, vec![v4a, v4b, v6]);
+
+        order_by_preference(&mut addrs, Some(IpPreference::Ipv6));
+        assert_eq!(addrs, vec![v6, v4a, v4b]);
+    }
+}
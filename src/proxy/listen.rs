@@ -0,0 +1,522 @@
+use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use axum::Router;
+use axum::extract::connect_info::Connected;
+use axum::serve::{IncomingStream, Listener};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::{mpsc, watch};
+use tokio_rustls::TlsAcceptor;
+use tokio_rustls::rustls;
+use tokio_rustls::rustls::pki_types::pem::PemObject;
+use tokio_rustls::rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use tokio_rustls::server::TlsStream;
+use tracing::{info, warn};
+
+use crate::config::ServerConfig;
+
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// One socket the proxy serves on.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenerSpec {
+    Tcp {
+        addr: SocketAddr,
+        tls: Option<(PathBuf, PathBuf)>,
+    },
+    Unix {
+        path: PathBuf,
+        /// Permission bits the socket is created with.
+        mode: u32,
+    },
+}
+
+impl std::fmt::Display for ListenerSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListenerSpec::Tcp { addr, tls: None } => write!(f, "http://{addr}"),
+            ListenerSpec::Tcp { addr, tls: Some(_) } => write!(f, "https://{addr}"),
+            ListenerSpec::Unix { path, .. } => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+/// The listeners described by `[server]`: always the TCP port, plus an optional Unix socket.
+pub fn listener_specs(server: &ServerConfig, port: u16) -> Result<Vec<ListenerSpec>> {
+    let ip = server.listen_ip()?;
+    // Bound to one LAN address, the proxy sees the TUI, `doctor` and `notify` on this host as
+    // coming from that address, not loopback, so admin endpoints would refuse them.
+    if !ip.is_loopback() && !ip.is_unspecified() && server.resolve_admin_token().is_none() {
+        anyhow::bail!(
+            "server.listen = {ip} is not a loopback or wildcard address, so local tools (TUI, doctor, notify) \
+             reach the proxy from {ip} and need server.admin_token; set admin_token (or admin_token_env), \
+             or listen on 0.0.0.0 / 127.0.0.1"
+        );
+    }
+    let mut specs = vec![ListenerSpec::Tcp {
+        addr: SocketAddr::new(ip, port),
+        tls: server.tls_files()?,
+    }];
+    if let Some(path) = server.unix_socket_path() {
+        if !cfg!(unix) {
+            anyhow::bail!("server.unix_socket is only supported on Unix platforms");
+        }
+        specs.push(ListenerSpec::Unix {
+            path,
+            mode: server.unix_socket_mode()?,
+        });
+    }
+    Ok(specs)
+}
+
+/// The other end of a connection, as seen by the access layer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PeerAddr {
+    Tcp(SocketAddr),
+    /// A Unix socket client; it has no IP and is not treated as loopback.
+    Unix,
+}
+
+impl Connected<IncomingStream<'_, TcpListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, TcpListener>) -> Self {
+        PeerAddr::Tcp(*stream.remote_addr())
+    }
+}
+
+impl Connected<IncomingStream<'_, TlsListener>> for PeerAddr {
+    fn connect_info(stream: IncomingStream<'_, TlsListener>) -> Self {
+        PeerAddr::Tcp(*stream.remote_addr())
+    }
+}
+
+#[cfg(unix)]
+impl Connected<IncomingStream<'_, tokio::net::UnixListener>> for PeerAddr {
+    fn connect_info(_stream: IncomingStream<'_, tokio::net::UnixListener>) -> Self {
+        PeerAddr::Unix
+    }
+}
+
+/// A TCP listener that yields connections after the TLS handshake.
+///
+/// Handshakes run on their own tasks so one slow client cannot stall `accept`.
+/// Dropping the listener (axum does so as soon as graceful shutdown starts) stops the accept
+/// task and releases the port, while in-flight connections keep draining.
+pub struct TlsListener {
+    local_addr: SocketAddr,
+    rx: mpsc::Receiver<(TlsStream<TcpStream>, SocketAddr)>,
+}
+
+impl TlsListener {
+    fn new(tcp: TcpListener, acceptor: TlsAcceptor) -> std::io::Result<Self> {
+        let local_addr = tcp.local_addr()?;
+        let (tx, rx) = mpsc::channel(64);
+        tokio::spawn(async move {
+            loop {
+                let accepted = tokio::select! {
+                    _ = tx.closed() => return,
+                    accepted = tcp.accept() => accepted,
+                };
+                let (stream, peer) = match accepted {
+                    Ok(v) => v,
+                    Err(e) => {
+                        warn!("TLS listener accept error: {}", e);
+                        tokio::time::sleep(Duration::from_millis(50)).await;
+                        continue;
+                    }
+                };
+                let acceptor = acceptor.clone();
+                let tx = tx.clone();
+                tokio::spawn(async move {
+                    match tokio::time::timeout(TLS_HANDSHAKE_TIMEOUT, acceptor.accept(stream)).await
+                    {
+                        Ok(Ok(tls)) => {
+                            let _ = tx.send((tls, peer)).await;
+                        }
+                        Ok(Err(e)) => warn!("TLS handshake with {} failed: {}", peer, e),
+                        Err(_) => warn!("TLS handshake with {} timed out", peer),
+                    }
+                });
+            }
+        });
+        Ok(Self { local_addr, rx })
+    }
+}
+
+impl Listener for TlsListener {
+    type Io = TlsStream<TcpStream>;
+    type Addr = SocketAddr;
+
+    async fn accept(&mut self) -> (Self::Io, Self::Addr) {
+        match self.rx.recv().await {
+            Some(conn) => conn,
+            // The accept task only exits once we are dropped, so this never resolves in practice.
+            None => std::future::pending().await,
+        }
+    }
+
+    fn local_addr(&self) -> std::io::Result<Self::Addr> {
+        Ok(self.local_addr)
+    }
+}
+
+fn load_tls_acceptor(cert: &Path, key: &Path) -> Result<TlsAcceptor> {
+    let certs = CertificateDer::pem_file_iter(cert)
+        .with_context(|| format!("read TLS certificate {:?}", cert))?
+        .collect::<Result<Vec<_>, _>>()
+        .with_context(|| format!("parse TLS certificate {:?}", cert))?;
+    if certs.is_empty() {
+        anyhow::bail!("no certificates found in {:?}", cert);
+    }
+    let key = PrivateKeyDer::from_pem_file(key)
+        .with_context(|| format!("read TLS private key {:?}", key))?;
+    let mut config = rustls::ServerConfig::builder_with_provider(Arc::new(
+        rustls::crypto::ring::default_provider(),
+    ))
+    .with_safe_default_protocol_versions()?
+    .with_no_client_auth()
+    .with_single_cert(certs, key)
+    .context("invalid TLS certificate / key pair")?;
+    config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+    Ok(TlsAcceptor::from(Arc::new(config)))
+}
+
+/// A bound socket, ready to serve.
+pub enum BoundListener {
+    Tcp(TcpListener),
+    Tls(TlsListener),
+    #[cfg(unix)]
+    Unix(tokio::net::UnixListener, PathBuf),
+}
+
+/// Binds every listener up front so configuration mistakes fail before anything is switched over.
+pub async fn bind_listeners(specs: &[ListenerSpec]) -> Result<Vec<BoundListener>> {
+    let mut bound = Vec::with_capacity(specs.len());
+    for spec in specs {
+        let listener = match spec {
+            ListenerSpec::Tcp { addr, tls } => {
+                let tcp = TcpListener::bind(addr)
+                    .await
+                    .with_context(|| format!("bind {addr}"))?;
+                match tls {
+                    None => BoundListener::Tcp(tcp),
+                    Some((cert, key)) => {
+                        BoundListener::Tls(TlsListener::new(tcp, load_tls_acceptor(cert, key)?)?)
+                    }
+                }
+            }
+            #[cfg(unix)]
+            ListenerSpec::Unix { path, mode } => {
+                if std::fs::symlink_metadata(path)
+                    .is_ok_and(|m| std::os::unix::fs::FileTypeExt::is_socket(&m.file_type()))
+                {
+                    // Never take the socket over from a proxy that is still serving on it.
+                    if std::os::unix::net::UnixStream::connect(path).is_ok() {
+                        anyhow::bail!(
+                            "unix socket {:?} is in use by another running process",
+                            path
+                        );
+                    }
+                    // Left behind by a previous run; bind would fail with EADDRINUSE.
+                    let _ = std::fs::remove_file(path);
+                }
+                if let Some(parent) = path.parent() {
+                    std::fs::create_dir_all(parent)
+                        .with_context(|| format!("create {:?}", parent))?;
+                }
+                BoundListener::Unix(bind_unix_socket(path, *mode)?, path.clone())
+            }
+            #[cfg(not(unix))]
+            ListenerSpec::Unix { .. } => {
+                anyhow::bail!("server.unix_socket is only supported on Unix platforms")
+            }
+        };
+        info!("codex-helper listening on {}", spec);
+        bound.push(listener);
+    }
+    Ok(bound)
+}
+
+/// Binds a Unix socket with permission bits `mode` (0600 unless configured otherwise).
+///
+/// The socket is bound under a temporary name, restricted to `mode` and then renamed into place,
+/// so it is never reachable at `path` with looser permissions.
+#[cfg(unix)]
+fn bind_unix_socket(path: &Path, mode: u32) -> Result<tokio::net::UnixListener> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .unwrap_or("proxy.sock");
+    let tmp = path.with_file_name(format!(".{file_name}.{}.tmp", std::process::id()));
+    let _ = std::fs::remove_file(&tmp);
+    let uds = tokio::net::UnixListener::bind(&tmp)
+        .with_context(|| format!("bind unix socket {:?}", tmp))?;
+    let placed = std::fs::set_permissions(&tmp, std::fs::Permissions::from_mode(mode))
+        .with_context(|| format!("chmod {mode:o} {:?}", tmp))
+        .and_then(|_| std::fs::rename(&tmp, path).with_context(|| format!("rename to {:?}", path)));
+    if let Err(e) = placed {
+        let _ = std::fs::remove_file(&tmp);
+        return Err(e);
+    }
+    Ok(uds)
+}
+
+/// Serves `app` on all listeners until `shutdown` flips to true; returns the first serve error.
+pub async fn serve_listeners(
+    listeners: Vec<BoundListener>,
+    app: Router,
+    shutdown: watch::Receiver<bool>,
+) -> Result<()> {
+    let mut tasks = tokio::task::JoinSet::new();
+    for listener in listeners {
+        let app = app.clone();
+        let mut rx = shutdown.clone();
+        let signal = async move {
+            let _ = rx.wait_for(|stop| *stop).await;
+        };
+        match listener {
+            BoundListener::Tcp(l) => {
+                let svc = app.into_make_service_with_connect_info::<PeerAddr>();
+                tasks.spawn(async move {
+                    axum::serve(l, svc).with_graceful_shutdown(signal).await?;
+                    Ok::<(), anyhow::Error>(())
+                });
+            }
+            BoundListener::Tls(l) => {
+                let svc = app.into_make_service_with_connect_info::<PeerAddr>();
+                tasks.spawn(async move {
+                    axum::serve(l, svc).with_graceful_shutdown(signal).await?;
+                    Ok(())
+                });
+            }
+            #[cfg(unix)]
+            BoundListener::Unix(l, path) => {
+                let svc = app.into_make_service_with_connect_info::<PeerAddr>();
+                tasks.spawn(async move {
+                    let res = axum::serve(l, svc).with_graceful_shutdown(signal).await;
+                    let _ = std::fs::remove_file(&path);
+                    res?;
+                    Ok(())
+                });
+            }
+        }
+    }
+    while let Some(res) = tasks.join_next().await {
+        res.map_err(|e| anyhow::anyhow!("listener task join error: {e}"))??;
+    }
+    Ok(())
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use futures_util::StreamExt;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    #[test]
+    fn specs_include_unix_socket_and_tls() {
+        let server = ServerConfig {
+            tls_cert: Some("/tmp/cert.pem".to_string()),
+            tls_key: Some("/tmp/key.pem".to_string()),
+            unix_socket: Some("/tmp/codex-helper.sock".to_string()),
+            ..Default::default()
+        };
+        let specs = listener_specs(&server, 3211).expect("specs");
+        assert_eq!(specs.len(), 2);
+        assert_eq!(specs[0].to_string(), "https://127.0.0.1:3211");
+        assert_eq!(specs[1].to_string(), "unix:/tmp/codex-helper.sock");
+        assert_eq!(
+            specs[1],
+            ListenerSpec::Unix {
+                path: PathBuf::from("/tmp/codex-helper.sock"),
+                mode: 0o600,
+            }
+        );
+
+        let shared = ServerConfig {
+            unix_socket_mode: Some("0660".to_string()),
+            ..server.clone()
+        };
+        assert!(matches!(
+            listener_specs(&shared, 3211).expect("specs")[1],
+            ListenerSpec::Unix { mode: 0o660, .. }
+        ));
+        let bad = ServerConfig {
+            unix_socket_mode: Some("rw-rw----".to_string()),
+            ..server
+        };
+        let err = listener_specs(&bad, 3211).expect_err("bad mode");
+        assert!(err.to_string().contains("unix_socket_mode"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn unix_socket_in_use_is_not_taken_over() {
+        let dir = std::env::temp_dir().join(format!("codex-helper-uds-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("proxy.sock");
+        let spec = ListenerSpec::Unix {
+            path: path.clone(),
+            mode: 0o660,
+        };
+        let live = bind_listeners(std::slice::from_ref(&spec))
+            .await
+            .expect("bind");
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)
+                .expect("socket")
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o660);
+        }
+
+        let err = match bind_listeners(std::slice::from_ref(&spec)).await {
+            Ok(_) => panic!("a live socket must not be replaced"),
+            Err(e) => e,
+        };
+        assert!(err.to_string().contains("in use"), "{err}");
+        assert!(path.exists(), "the live socket is left alone");
+
+        // Once nobody answers, the leftover socket file is stale and gets replaced.
+        let BoundListener::Unix(listener, _) = live.into_iter().next().expect("listener") else {
+            panic!("expected a unix listener");
+        };
+        drop(listener);
+        assert!(
+            path.exists(),
+            "dropping the listener leaves the file behind"
+        );
+        bind_listeners(&[spec])
+            .await
+            .expect("rebind over a stale socket");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn lan_listen_address_requires_admin_token() {
+        let mut server = ServerConfig {
+            listen: Some("192.168.1.5".to_string()),
+            ..Default::default()
+        };
+        let err = listener_specs(&server, 3211).expect_err("no admin token");
+        assert!(err.to_string().contains("admin_token"), "{err}");
+
+        server.admin_token = Some("admin".to_string());
+        assert_eq!(
+            listener_specs(&server, 3211).expect("specs")[0].to_string(),
+            "http://192.168.1.5:3211"
+        );
+        server.admin_token = None;
+        server.listen = Some("0.0.0.0".to_string());
+        assert!(listener_specs(&server, 3211).is_ok());
+    }
+
+    #[tokio::test]
+    async fn serves_requests_over_unix_socket() {
+        let dir = std::env::temp_dir().join(format!("codex-helper-uds-{}", uuid::Uuid::new_v4()));
+        let path = dir.join("proxy.sock");
+        let bound = bind_listeners(&[ListenerSpec::Unix {
+            path: path.clone(),
+            mode: 0o600,
+        }])
+        .await
+        .expect("bind");
+        let app = Router::new().route("/ping", axum::routing::get(|| async { "pong" }));
+        let (tx, rx) = watch::channel(false);
+        let server = tokio::spawn(serve_listeners(bound, app, rx));
+
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = std::fs::metadata(&path)
+                .expect("socket")
+                .permissions()
+                .mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        let mut stream = tokio::net::UnixStream::connect(&path)
+            .await
+            .expect("connect");
+        stream
+            .write_all(b"GET /ping HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n")
+            .await
+            .expect("write");
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.expect("read");
+        assert!(response.starts_with("HTTP/1.1 200"), "{response}");
+        assert!(response.ends_with("pong"), "{response}");
+
+        tx.send(true).expect("shutdown");
+        server.await.expect("join").expect("serve");
+        assert!(!path.exists(), "socket file is removed on shutdown");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[tokio::test]
+    async fn dropping_tls_listener_releases_the_port() {
+        let config = rustls::ServerConfig::builder_with_provider(Arc::new(
+            rustls::crypto::ring::default_provider(),
+        ))
+        .with_safe_default_protocol_versions()
+        .expect("protocols")
+        .with_no_client_auth()
+        .with_cert_resolver(Arc::new(rustls::server::ResolvesServerCertUsingSni::new()));
+        let tcp = TcpListener::bind("127.0.0.1:0").await.expect("bind");
+        let addr = tcp.local_addr().expect("addr");
+        let listener = TlsListener::new(tcp, TlsAcceptor::from(Arc::new(config))).expect("tls");
+        drop(listener);
+
+        let mut rebound = None;
+        for _ in 0..50 {
+            if let Ok(l) = TcpListener::bind(addr).await {
+                rebound = Some(l);
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        assert!(
+            rebound.is_some(),
+            "port is released once the listener is dropped"
+        );
+    }
+
+    #[tokio::test]
+    async fn shutdown_lets_in_flight_streams_finish() {
+        let bound = bind_listeners(&[ListenerSpec::Tcp {
+            addr: "127.0.0.1:0".parse().unwrap(),
+            tls: None,
+        }])
+        .await
+        .expect("bind");
+        let BoundListener::Tcp(tcp) = &bound[0] else {
+            panic!("expected a plain TCP listener");
+        };
+        let addr = tcp.local_addr().expect("addr");
+        let app = Router::new().route(
+            "/stream",
+            axum::routing::get(|| async {
+                let chunks = futures_util::stream::iter(0..3).then(|i| async move {
+                    tokio::time::sleep(Duration::from_millis(100)).await;
+                    Ok::<_, std::io::Error>(format!("data: {i}\n\n"))
+                });
+                axum::body::Body::from_stream(chunks)
+            }),
+        );
+        let (tx, rx) = watch::channel(false);
+        let server = tokio::spawn(serve_listeners(bound, app, rx));
+
+        let resp = reqwest::get(format!("http://{addr}/stream"))
+            .await
+            .expect("request");
+        tx.send(true).expect("shutdown");
+        let body = resp.text().await.expect("body");
+        assert_eq!(body, "data: 0\n\ndata: 1\n\ndata: 2\n\n");
+        server.await.expect("join").expect("serve");
+        assert!(
+            TcpStream::connect(addr).await.is_err(),
+            "no new connections after shutdown"
+        );
+    }
+}
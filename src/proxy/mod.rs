@@ -5,7 +5,7 @@ use std::time::Instant;
 use anyhow::{Result, anyhow};
 use axum::Json;
 use axum::Router;
-use axum::body::{Body, Bytes, to_bytes};
+use axum::body::{Body, Bytes};
 use axum::extract::Query;
 use axum::http::{HeaderMap, HeaderName, HeaderValue, Method, Request, Response, StatusCode, Uri};
 use axum::routing::{any, get};
@@ -13,15 +13,21 @@ use reqwest::Client;
 use std::sync::OnceLock;
 use tracing::{info, instrument, warn};
 
+mod access;
+mod body;
 mod classify;
+mod dns;
+mod listen;
 mod retry;
 mod runtime_config;
 mod stream;
 #[cfg(test)]
 mod tests;
 
+use crate::body_rules;
+use crate::client_keys::ClientIdentity;
 use crate::config::{ProxyConfig, ServiceConfigManager};
-use crate::filter::RequestFilter;
+use crate::filter::{BlockMatch, FilterReport, FilterScope, RequestFilter};
 use crate::lb::{LbState, LoadBalancer, SelectedUpstream};
 use crate::logging::{
     AuthResolutionLog, BodyPreview, HeaderEntry, HttpDebugLog, http_debug_options,
@@ -33,10 +39,15 @@ use crate::state::{ActiveRequest, FinishedRequest, ProxyState};
 use crate::usage::extract_usage_from_bytes;
 use crate::usage_providers;
 
+pub use self::access::{ADMIN_TOKEN_HEADER, CLIENT_TOKEN_HEADER};
+pub use self::body::clear_stale_spool_dirs;
+use self::body::{BUFFER_THRESHOLD_BYTES, BodyReadError, ClientBody, SpooledBody, read_to_bytes};
 use self::classify::classify_upstream_response;
+use self::dns::UpstreamClients;
+pub use self::listen::{bind_listeners, listener_specs, serve_listeners};
 use self::retry::{
-    backoff_sleep, retry_info_for_chain, retry_options, retry_sleep, should_retry_class,
-    should_retry_status,
+    backoff_sleep, key_cooldown_secs, retry_info_for_chain, retry_options, retry_sleep,
+    should_retry_class, should_retry_status,
 };
 use self::runtime_config::RuntimeConfig;
 use self::stream::{SseSuccessMeta, build_sse_success_response};
@@ -68,102 +79,156 @@ fn claude_settings_env_value(key: &str) -> Option<String> {
         .map(|s| s.to_string())
 }
 
-fn resolve_auth_token_with_source(
+/// Resolves an env-style secret: process env first, then the Codex `auth.json` / Claude `settings.json` env block.
+fn resolve_env_secret_with_source(service_name: &str, env_name: &str) -> Option<(String, String)> {
+    if let Ok(v) = std::env::var(env_name)
+        && !v.trim().is_empty()
+    {
+        return Some((v, format!("env:{env_name}")));
+    }
+
+    let file_value = match service_name {
+        "codex" => codex_auth_json_value(env_name),
+        "claude" => claude_settings_env_value(env_name),
+        _ => None,
+    };
+    let v = file_value.filter(|v| !v.trim().is_empty())?;
+    let src = match service_name {
+        "codex" => format!("codex_auth_json:{env_name}"),
+        "claude" => format!("claude_settings_env:{env_name}"),
+        _ => format!("file:{env_name}"),
+    };
+    Some((v, src))
+}
+
+/// Shared resolution order for a single upstream secret: inline -> env (incl. Codex/Claude files)
+/// -> `*_file` / `*_cmd` -> client passthrough. The returned label is what `AuthResolutionLog` records.
+async fn resolve_secret_with_source(
     service_name: &str,
-    auth: &crate::config::UpstreamAuth,
-    client_has_auth: bool,
+    inline: Option<&str>,
+    env_name: Option<&str>,
+    external: impl Future<Output = Option<(String, String)>>,
+    external_hint: Option<String>,
+    client_has_value: bool,
 ) -> (Option<String>, String) {
-    if let Some(token) = auth.auth_token.as_deref()
-        && !token.trim().is_empty()
+    if let Some(v) = inline
+        && !v.trim().is_empty()
     {
-        return (Some(token.to_string()), "inline".to_string());
+        return (Some(v.to_string()), "inline".to_string());
     }
 
-    if let Some(env_name) = auth.auth_token_env.as_deref()
-        && !env_name.trim().is_empty()
+    let env_name = env_name.filter(|n| !n.trim().is_empty());
+    if let Some(env_name) = env_name
+        && let Some((v, src)) = resolve_env_secret_with_source(service_name, env_name)
     {
-        if let Ok(v) = std::env::var(env_name)
-            && !v.trim().is_empty()
-        {
-            return (Some(v), format!("env:{env_name}"));
-        }
+        return (Some(v), src);
+    }
 
-        let file_value = match service_name {
-            "codex" => codex_auth_json_value(env_name),
-            "claude" => claude_settings_env_value(env_name),
-            _ => None,
-        };
-        if let Some(v) = file_value
-            && !v.trim().is_empty()
-        {
-            let src = match service_name {
-                "codex" => format!("codex_auth_json:{env_name}"),
-                "claude" => format!("claude_settings_env:{env_name}"),
-                _ => format!("file:{env_name}"),
-            };
-            return (Some(v), src);
-        }
+    if let Some((v, src)) = external.await {
+        return (Some(v), src);
+    }
 
-        if client_has_auth {
-            return (None, format!("client_passthrough (missing_env:{env_name})"));
-        }
-        return (None, format!("missing_env:{env_name}"));
+    let missing = match (env_name, external_hint) {
+        (Some(env_name), _) => Some(format!("missing_env:{env_name}")),
+        (None, Some(hint)) => Some(format!("missing_{hint}")),
+        (None, None) => None,
+    };
+    match (missing, client_has_value) {
+        (Some(m), true) => (None, format!("client_passthrough ({m})")),
+        (Some(m), false) => (None, m),
+        (None, true) => (None, "client_passthrough".to_string()),
+        (None, false) => (None, "none".to_string()),
     }
+}
 
-    if client_has_auth {
-        (None, "client_passthrough".to_string())
-    } else {
-        (None, "none".to_string())
+/// Label for a configured-but-unresolved `*_secret` / `*_file` / `*_cmd` source, e.g. `file:/run/secrets/key`.
+fn external_secret_hint(
+    secret: Option<&str>,
+    file: Option<&str>,
+    cmd: &[String],
+) -> Option<String> {
+    if let Some(name) = secret.filter(|n| !n.trim().is_empty()) {
+        return Some(format!("secret:{name}"));
     }
+    if let Some(path) = file.filter(|p| !p.trim().is_empty()) {
+        return Some(format!("file:{path}"));
+    }
+    cmd.first().map(|program| format!("cmd:{program}"))
 }
 
-fn resolve_api_key_with_source(
+async fn resolve_auth_token_with_source(
+    service_name: &str,
+    auth: &crate::config::UpstreamAuth,
+    client_has_auth: bool,
+) -> (Option<String>, String) {
+    resolve_secret_with_source(
+        service_name,
+        auth.auth_token.as_deref(),
+        auth.auth_token_env.as_deref(),
+        auth.resolve_external_auth_token(),
+        external_secret_hint(
+            auth.auth_token_secret.as_deref(),
+            auth.auth_token_file.as_deref(),
+            &auth.auth_token_cmd,
+        ),
+        client_has_auth,
+    )
+    .await
+}
+
+async fn resolve_api_key_with_source(
     service_name: &str,
     auth: &crate::config::UpstreamAuth,
     client_has_x_api_key: bool,
 ) -> (Option<String>, String) {
-    if let Some(key) = auth.api_key.as_deref()
-        && !key.trim().is_empty()
-    {
-        return (Some(key.to_string()), "inline".to_string());
-    }
+    resolve_secret_with_source(
+        service_name,
+        auth.api_key.as_deref(),
+        auth.api_key_env.as_deref(),
+        auth.resolve_external_api_key(),
+        external_secret_hint(
+            auth.api_key_secret.as_deref(),
+            auth.api_key_file.as_deref(),
+            &auth.api_key_cmd,
+        ),
+        client_has_x_api_key,
+    )
+    .await
+}
 
-    if let Some(env_name) = auth.api_key_env.as_deref()
-        && !env_name.trim().is_empty()
-    {
-        if let Ok(v) = std::env::var(env_name)
-            && !v.trim().is_empty()
-        {
-            return (Some(v), format!("env:{env_name}"));
-        }
+/// A resolvable key from an upstream's bearer token pool.
+struct PooledToken {
+    key_id: String,
+    value: String,
+    source: String,
+}
 
-        let file_value = match service_name {
-            "codex" => codex_auth_json_value(env_name),
-            "claude" => claude_settings_env_value(env_name),
-            _ => None,
-        };
-        if let Some(v) = file_value
-            && !v.trim().is_empty()
-        {
-            let src = match service_name {
-                "codex" => format!("codex_auth_json:{env_name}"),
-                "claude" => format!("claude_settings_env:{env_name}"),
-                _ => format!("file:{env_name}"),
-            };
-            return (Some(v), src);
-        }
+/// Resolves every pool entry that currently has a value; missing env vars, secrets, files or failing
+/// commands are skipped so rotation only ever lands on usable keys.
+async fn resolve_auth_token_pool(
+    service_name: &str,
+    auth: &crate::config::UpstreamAuth,
+) -> Vec<PooledToken> {
+    use crate::config::AuthKeySource;
 
-        if client_has_x_api_key {
-            return (None, format!("client_passthrough (missing_env:{env_name})"));
+    let mut out = Vec::new();
+    for (key_id, src) in auth.token_pool() {
+        let resolved = match &src {
+            AuthKeySource::Env(name) => resolve_env_secret_with_source(service_name, name),
+            _ => auth
+                .resolve_pool_key(&src)
+                .await
+                .map(|v| (v, key_id.clone())),
+        };
+        if let Some((value, source)) = resolved {
+            out.push(PooledToken {
+                key_id,
+                value,
+                source,
+            });
         }
-        return (None, format!("missing_env:{env_name}"));
-    }
-
-    if client_has_x_api_key {
-        (None, "client_passthrough".to_string())
-    } else {
-        (None, "none".to_string())
     }
+    out
 }
 
 fn is_hop_by_hop_header(name_lower: &str) -> bool {
@@ -278,6 +343,7 @@ struct HttpDebugBase {
     upstream_request_body_debug: Option<BodyPreview>,
     client_body_warn: Option<BodyPreview>,
     upstream_request_body_warn: Option<BodyPreview>,
+    body_rewrites: Vec<String>,
 }
 
 fn warn_http_debug(status_code: u16, http_debug: &HttpDebugLog) {
@@ -291,10 +357,31 @@ fn warn_http_debug(status_code: u16, http_debug: &HttpDebugLog) {
     warn!("upstream non-2xx http_debug={json} status_code={status_code}");
 }
 
+/// OpenAI-style error for a request rejected by a filter `block` rule.
+fn blocked_by_policy_response(hit: &BlockMatch) -> Response<Body> {
+    let detail = hit
+        .message
+        .as_deref()
+        .unwrap_or("request matches a blocked pattern");
+    let body = serde_json::json!({
+        "error": {
+            "message": format!(
+                "Request blocked by codex-helper filter rule '{}': {}",
+                hit.rule_id, detail
+            ),
+            "type": "invalid_request_error",
+            "param": null,
+            "code": "request_blocked_by_policy",
+        }
+    });
+    axum::response::IntoResponse::into_response((StatusCode::FORBIDDEN, Json(body)))
+}
+
 /// Generic proxy service; currently used by both Codex and Claude.
 #[derive(Clone)]
 pub struct ProxyService {
     pub client: Client,
+    upstream_clients: UpstreamClients,
     config: Arc<RuntimeConfig>,
     pub service_name: &'static str,
     lb_states: Arc<Mutex<HashMap<String, LbState>>>,
@@ -310,12 +397,11 @@ impl ProxyService {
         lb_states: Arc<Mutex<HashMap<String, LbState>>>,
     ) -> Self {
         let state = ProxyState::new_with_lb_states(Some(lb_states.clone()));
+        state.set_usage_rollup_keep_days(config.usage.rollup_keep_days);
         ProxyState::spawn_cleanup_task(state.clone());
         {
             let state = state.clone();
-            let log_path = crate::config::proxy_home_dir()
-                .join("logs")
-                .join("requests.jsonl");
+            let log_path = crate::logging::log_path();
             let mut base_url_to_provider_id = HashMap::new();
             let mgr = match service_name {
                 "claude" => &config.claude,
@@ -328,14 +414,17 @@ impl ProxyService {
                     }
                 }
             }
-            tokio::spawn(async move {
-                let _ = state
-                    .replay_usage_from_requests_log(service_name, log_path, base_url_to_provider_id)
-                    .await;
-            });
+            ProxyState::spawn_usage_rollup_persistence(
+                state,
+                service_name,
+                crate::state::usage_rollup_path(service_name),
+                log_path,
+                base_url_to_provider_id,
+            );
         }
         Self {
             client,
+            upstream_clients: UpstreamClients::default(),
             config: Arc::new(RuntimeConfig::new(config)),
             service_name,
             lb_states,
@@ -352,13 +441,81 @@ impl ProxyService {
         }
     }
 
-    async fn pinned_config_name(&self, session_id: Option<&str>) -> Option<String> {
+    /// Configs of this service whose budget is used up. The first time a config crosses a limit
+    /// in a period a warning is logged and a notification raised.
+    async fn over_budget_configs(
+        &self,
+        cfg: &ProxyConfig,
+        mgr: &ServiceConfigManager,
+    ) -> HashSet<String> {
+        let budgets = mgr
+            .configs
+            .iter()
+            .filter(|(_, svc)| !svc.budget.is_empty())
+            .map(|(name, svc)| (name.clone(), svc.budget.clone()))
+            .collect::<Vec<_>>();
+        let now_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let statuses = self
+            .state
+            .budget_statuses(self.service_name, &budgets, now_ms)
+            .await;
+        let mut out = HashSet::new();
+        for status in statuses {
+            let Some(limit) = status.exceeded.as_deref() else {
+                continue;
+            };
+            let today = crate::budget::day_index(now_ms);
+            let period = if limit == "daily_tokens" {
+                today
+            } else {
+                crate::budget::month_start_day(today)
+            };
+            let key = format!(
+                "{}/{}/{limit}/{period}",
+                self.service_name, status.config_name
+            );
+            if self.state.mark_budget_alert(key) {
+                warn!(
+                    "config '{}' is over its budget ({}); removed from automatic routing until the period rolls over",
+                    status.config_name,
+                    status.summary()
+                );
+                crate::notify::spawn_proxy_alert(
+                    &cfg.notify,
+                    "codex-helper: budget exceeded",
+                    &format!("{}: {}", status.config_name, status.summary()),
+                    serde_json::json!({
+                        "type": "codex-helper-budget-exceeded",
+                        "service": self.service_name,
+                        "config": status.config_name,
+                        "limit": limit,
+                        "status": status,
+                    }),
+                );
+            }
+            out.insert(status.config_name);
+        }
+        out
+    }
+
+    /// Config pinned for this request: session pin (TUI) > project rule > global pin.
+    async fn pinned_config_name(
+        &self,
+        session_id: Option<&str>,
+        project_config: Option<&str>,
+    ) -> Option<String> {
         if let Some(sid) = session_id
             && let Some(name) = self.state.get_session_config_override(sid).await
             && !name.trim().is_empty()
         {
             return Some(name);
         }
+        if let Some(name) = project_config {
+            return Some(name.to_string());
+        }
         if let Some(name) = self.state.get_global_config_override().await
             && !name.trim().is_empty()
         {
@@ -371,13 +528,39 @@ impl ProxyService {
         &self,
         cfg: &ProxyConfig,
         session_id: Option<&str>,
+        allowed_configs: &[String],
+        project: Option<&crate::projects::ProjectSettings>,
     ) -> Vec<LoadBalancer> {
-        let mgr = self.service_manager(cfg);
+        let mut mgr = self.service_manager(cfg);
+        // A client key limited to some configs sees a view with only those configs, so pinning,
+        // level routing and the active fallback all stay within its allowance.
+        let restricted;
+        if !allowed_configs.is_empty() {
+            let mut view = mgr.clone();
+            view.configs
+                .retain(|name, _| allowed_configs.contains(name));
+            if view
+                .active
+                .as_ref()
+                .is_some_and(|name| !view.configs.contains_key(name))
+            {
+                view.active = None;
+            }
+            restricted = view;
+            mgr = &restricted;
+        }
         let meta_overrides = self
             .state
             .get_config_meta_overrides(self.service_name)
             .await;
-        if let Some(name) = self.pinned_config_name(session_id).await {
+        let project_config = project.and_then(|p| p.config.as_deref()).filter(|name| {
+            let known = mgr.configs.contains_key(*name);
+            if !known {
+                warn!("project rule selects unknown or disallowed config '{name}'; ignoring it");
+            }
+            known
+        });
+        if let Some(name) = self.pinned_config_name(session_id, project_config).await {
             if let Some(svc) = mgr
                 .configs
                 .get(&name)
@@ -388,8 +571,26 @@ impl ProxyService {
             }
             return Vec::new();
         }
+        // Project levels take precedence over runtime (TUI) level overrides.
+        let level_of = |name: &str, svc: &crate::config::ServiceConfig| {
+            project
+                .and_then(|p| p.levels.get(name).copied())
+                .or_else(|| meta_overrides.get(name).and_then(|(_, l)| *l))
+                .unwrap_or(svc.level)
+                .clamp(1, 10)
+        };
 
-        let active_name = mgr.active.as_deref();
+        // Over-budget configs leave automatic routing entirely, even when active.
+        let over_budget = self.over_budget_configs(cfg, mgr).await;
+        let active_name = mgr
+            .active
+            .as_deref()
+            .filter(|name| !over_budget.contains(*name));
+        let active_config = || {
+            mgr.active_config()
+                .filter(|svc| !over_budget.contains(&svc.name))
+                .cloned()
+        };
         let mut configs = mgr
             .configs
             .iter()
@@ -400,6 +601,7 @@ impl ProxyService {
                     .unwrap_or((None, None));
                 let enabled = enabled_ovr.unwrap_or(svc.enabled);
                 !svc.upstreams.is_empty()
+                    && !over_budget.contains(name.as_str())
                     && (enabled || active_name.is_some_and(|n| n == name.as_str()))
             })
             .collect::<Vec<_>>();
@@ -407,13 +609,7 @@ impl ProxyService {
         let has_multi_level = {
             let mut levels = configs
                 .iter()
-                .map(|(name, svc)| {
-                    let (_, level_ovr) = meta_overrides
-                        .get(name.as_str())
-                        .copied()
-                        .unwrap_or((None, None));
-                    level_ovr.unwrap_or(svc.level).clamp(1, 10)
-                })
+                .map(|(name, svc)| level_of(name, svc))
                 .collect::<Vec<_>>();
             levels.sort_unstable();
             levels.dedup();
@@ -438,23 +634,15 @@ impl ProxyService {
                 )];
             }
 
-            if let Some(svc) = mgr.active_config().cloned() {
+            if let Some(svc) = active_config() {
                 return vec![LoadBalancer::new(Arc::new(svc), self.lb_states.clone())];
             }
             return Vec::new();
         }
 
         configs.sort_by(|(a_name, a), (b_name, b)| {
-            let a_level = meta_overrides
-                .get(a_name.as_str())
-                .and_then(|(_, l)| *l)
-                .unwrap_or(a.level)
-                .clamp(1, 10);
-            let b_level = meta_overrides
-                .get(b_name.as_str())
-                .and_then(|(_, l)| *l)
-                .unwrap_or(b.level)
-                .clamp(1, 10);
+            let a_level = level_of(a_name, a);
+            let b_level = level_of(b_name, b);
             let a_active = active_name.is_some_and(|n| n == a_name.as_str());
             let b_active = active_name.is_some_and(|n| n == b_name.as_str());
             a_level
@@ -471,7 +659,7 @@ impl ProxyService {
             return lbs;
         }
 
-        if let Some(svc) = mgr.active_config().cloned() {
+        if let Some(svc) = active_config() {
             return vec![LoadBalancer::new(Arc::new(svc), self.lb_states.clone())];
         }
         Vec::new()
@@ -595,17 +783,68 @@ pub async fn handle_proxy(
     let method = parts.method;
     let client_headers = parts.headers;
     let client_headers_entries_cache: OnceLock<Vec<HeaderEntry>> = OnceLock::new();
+    // Set by the access layer when the request authenticated with a proxy-issued client key.
+    let client_identity = parts.extensions.get::<ClientIdentity>().cloned();
+    let client_label = client_identity.as_ref().map(|c| c.label.clone());
+    let allowed_configs = client_identity
+        .as_ref()
+        .map(|c| c.allowed_configs.as_slice())
+        .unwrap_or_default();
 
     let session_id = extract_session_id(&client_headers);
 
     proxy.config.maybe_reload_from_disk().await;
     let cfg_snapshot = proxy.config.snapshot().await;
+    let cwd = if let Some(id) = session_id.as_deref() {
+        proxy.state.resolve_session_cwd(id).await
+    } else {
+        None
+    };
+    // Project rules (config / repo files) keyed on the session cwd.
+    let project = match cwd.as_deref() {
+        Some(dir) => crate::projects::resolve(&cfg_snapshot.projects, dir).await,
+        None => None,
+    };
     let lbs = proxy
-        .lbs_for_request(cfg_snapshot.as_ref(), session_id.as_deref())
+        .lbs_for_request(
+            cfg_snapshot.as_ref(),
+            session_id.as_deref(),
+            allowed_configs,
+            project.as_ref(),
+        )
         .await;
     if lbs.is_empty() {
         let dur = start.elapsed().as_millis() as u64;
-        let status = StatusCode::BAD_GATEWAY;
+        let over_budget = allowed_configs.is_empty()
+            && !proxy
+                .over_budget_configs(&cfg_snapshot, proxy.service_manager(&cfg_snapshot))
+                .await
+                .is_empty();
+        let (status, error_class, hint, message) = if !allowed_configs.is_empty() {
+            (
+                StatusCode::FORBIDDEN,
+                "client_key_config_denied",
+                "该 client key 允许的配置都不存在或不可用（检查 allowed_configs）。",
+                format!(
+                    "client key '{}' is not allowed to use any available config",
+                    client_label.as_deref().unwrap_or("-")
+                ),
+            )
+        } else if over_budget {
+            (
+                StatusCode::TOO_MANY_REQUESTS,
+                "config_budget_exhausted",
+                "所有可用配置都已超出预算（budget），周期滚动后自动恢复。",
+                "every available config is over its budget".to_string(),
+            )
+        } else {
+            (
+                StatusCode::BAD_GATEWAY,
+                "no_active_upstream_config",
+                "未找到任何可用的上游配置（active_config 为空或 upstreams 为空）。",
+                "no active upstream config".to_string(),
+            )
+        };
         let client_headers_entries = client_headers_entries_cache
             .get_or_init(|| header_map_to_entries(&client_headers))
             .clone();
@@ -616,11 +855,10 @@ pub async fn handle_proxy(
                 upstream_headers_ms: None,
                 upstream_first_chunk_ms: None,
                 upstream_body_read_ms: None,
-                upstream_error_class: Some("no_active_upstream_config".to_string()),
-                upstream_error_hint: Some(
-                    "未找到任何可用的上游配置（active_config 为空或 upstreams 为空）。".to_string(),
-                ),
+                upstream_error_class: Some(error_class.to_string()),
+                upstream_error_hint: Some(hint.to_string()),
                 upstream_cf_ray: None,
+                upstream_remote_addr: None,
                 client_uri: uri.to_string(),
                 target_url: "-".to_string(),
                 client_headers: client_headers_entries,
@@ -628,9 +866,10 @@ pub async fn handle_proxy(
                 auth_resolution: None,
                 client_body: None,
                 upstream_request_body: None,
+                body_rewrites: Vec::new(),
                 upstream_response_headers: None,
                 upstream_response_body: None,
-                upstream_error: Some("no active upstream config".to_string()),
+                upstream_error: Some(message.clone()),
             })
         } else {
             None
@@ -644,14 +883,19 @@ pub async fn handle_proxy(
             "-",
             None,
             "-",
+            None,
+            client_label.clone(),
             session_id.clone(),
+            cwd.clone(),
+            None,
+            None,
             None,
             None,
             None,
             None,
             http_debug,
         );
-        return Err((status, "no active upstream config".to_string()));
+        return Err((status, message));
     }
     let client_content_type = client_headers
         .get("content-type")
@@ -669,11 +913,6 @@ pub async fn handle_proxy(
     let is_user_turn = method == Method::POST && is_responses_path;
     let is_codex_service = proxy.service_name == "codex";
 
-    let cwd = if let Some(id) = session_id.as_deref() {
-        proxy.state.resolve_session_cwd(id).await
-    } else {
-        None
-    };
     if let Some(id) = session_id.as_deref() {
         proxy.state.touch_session_override(id, started_at_ms).await;
         proxy
@@ -682,12 +921,68 @@ pub async fn handle_proxy(
             .await;
     }
 
-    // Read request body and apply filters.
-    let raw_body = match to_bytes(body, 10 * 1024 * 1024).await {
+    let project_instructions = project
+        .as_ref()
+        .map(|p| &p.instructions)
+        .filter(|i| is_user_turn && !i.is_empty());
+    let project_model = project.as_ref().and_then(|p| p.model.clone());
+
+    let override_effort = if let Some(id) = session_id.as_deref() {
+        proxy.state.get_session_effort_override(id).await
+    } else {
+        None
+    }
+    .or_else(|| project.as_ref().and_then(|p| p.reasoning_effort.clone()));
+    let retry_opt = retry_options(&cfg_snapshot.retry);
+
+    // Read the request body. Anything that may inspect or rewrite it (filters, effort override,
+    // model mapping / support checks) needs it in memory; otherwise large bodies are streamed
+    // through, or spooled to disk when a retry may have to send them again.
+    let body_limit = cfg_snapshot.server.max_request_body_bytes();
+    let content_length = client_headers
+        .get(axum::http::header::CONTENT_LENGTH)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let needs_buffer = content_length.is_some_and(|n| n <= BUFFER_THRESHOLD_BYTES)
+        || override_effort.is_some()
+        || project_instructions.is_some()
+        || project_model.is_some()
+        || proxy.filter.has_rules()
+        || lbs.iter().any(|lb| {
+            !lb.service.body_rules.is_empty()
+                || lb.service.upstreams.iter().any(|u| {
+                    !u.model_mapping.is_empty()
+                        || !u.supported_models.is_empty()
+                        || !u.body_rules.is_empty()
+                })
+        });
+    let read_body = if content_length.is_some_and(|n| n > body_limit as u64) {
+        Err(BodyReadError::TooLarge { limit: body_limit })
+    } else if needs_buffer {
+        read_to_bytes(body, body_limit)
+            .await
+            .map(ClientBody::Buffered)
+    } else if retry_opt.max_attempts > 1
+        || lbs
+            .iter()
+            .any(|lb| lb.service.upstreams.iter().any(|u| u.auth.has_token_pool()))
+    {
+        // A key pool may re-send the body to another key even without upstream retries.
+        SpooledBody::spool(body, body_limit)
+            .await
+            .map(ClientBody::Spooled)
+    } else {
+        Ok(ClientBody::Streaming(Some(body), Default::default()))
+    };
+    let mut client_body = match read_body {
         Ok(b) => b,
         Err(e) => {
             let dur = start.elapsed().as_millis() as u64;
-            let status = StatusCode::BAD_REQUEST;
+            let status = if matches!(e, BodyReadError::TooLarge { .. }) {
+                StatusCode::PAYLOAD_TOO_LARGE
+            } else {
+                StatusCode::BAD_REQUEST
+            };
             let err_str = e.to_string();
             let client_headers_entries = client_headers_entries_cache
                 .get_or_init(|| header_map_to_entries(&client_headers))
@@ -699,11 +994,24 @@ pub async fn handle_proxy(
                     upstream_headers_ms: None,
                     upstream_first_chunk_ms: None,
                     upstream_body_read_ms: None,
-                    upstream_error_class: Some("client_body_read_error".to_string()),
+                    upstream_error_class: Some(
+                        if status == StatusCode::PAYLOAD_TOO_LARGE {
+                            "client_body_too_large"
+                        } else {
+                            "client_body_read_error"
+                        }
+                        .to_string(),
+                    ),
                     upstream_error_hint: Some(
-                        "读取客户端请求 body 失败（可能超过大小限制或连接中断）。".to_string(),
+                        if status == StatusCode::PAYLOAD_TOO_LARGE {
+                            "请求 body 超过 server.max_request_body_bytes 限制。"
+                        } else {
+                            "读取客户端请求 body 失败（连接中断或格式错误）。"
+                        }
+                        .to_string(),
                     ),
                     upstream_cf_ray: None,
+                    upstream_remote_addr: None,
                     client_uri: uri.to_string(),
                     target_url: "-".to_string(),
                     client_headers: client_headers_entries,
@@ -711,6 +1019,7 @@ pub async fn handle_proxy(
                     auth_resolution: None,
                     client_body: None,
                     upstream_request_body: None,
+                    body_rewrites: Vec::new(),
                     upstream_response_headers: None,
                     upstream_response_body: None,
                     upstream_error: Some(err_str.clone()),
@@ -727,34 +1036,34 @@ pub async fn handle_proxy(
                 "-",
                 None,
                 "-",
+                None,
+                client_label.clone(),
                 session_id.clone(),
                 cwd.clone(),
                 None,
                 None,
                 None,
+                None,
+                None,
+                None,
                 http_debug,
             );
             return Err((status, err_str));
         }
     };
-    let original_effort = extract_reasoning_effort_from_request_body(&raw_body);
-    let override_effort = if let Some(id) = session_id.as_deref() {
-        proxy.state.get_session_effort_override(id).await
-    } else {
-        None
+    let (original_effort, mut request_model) = match &client_body {
+        ClientBody::Buffered(raw) => (
+            extract_reasoning_effort_from_request_body(raw),
+            extract_model_from_request_body(raw),
+        ),
+        ClientBody::Spooled(spooled) => {
+            let (model, effort) = spooled.probe_model_and_effort().await;
+            (effort, model)
+        }
+        ClientBody::Streaming(..) => (None, None),
     };
     let effective_effort = override_effort.clone().or(original_effort.clone());
-
-    let body_for_upstream = if let Some(ref effort) = override_effort {
-        Bytes::from(
-            apply_reasoning_effort_override(&raw_body, effort)
-                .unwrap_or_else(|| raw_body.as_ref().to_vec()),
-        )
-    } else {
-        raw_body.clone()
-    };
-    let request_model = extract_model_from_request_body(body_for_upstream.as_ref());
-    let request_body_len = raw_body.len();
+    let mut request_body_len = client_body.len(content_length);
 
     let debug_opt = http_debug_options();
     let warn_opt = http_warn_options();
@@ -769,16 +1078,47 @@ pub async fn handle_proxy(
         0
     };
     let request_body_previews = should_log_request_body_preview();
-    let client_body_debug = if request_body_previews && debug_max > 0 {
-        Some(make_body_preview(&raw_body, client_content_type, debug_max))
-    } else {
-        None
-    };
-    let client_body_warn = if request_body_previews && warn_max > 0 {
-        Some(make_body_preview(&raw_body, client_content_type, warn_max))
-    } else {
-        None
+    let raw_body = match &client_body {
+        ClientBody::Buffered(raw) if request_body_previews => Some(raw),
+        _ => None,
     };
+    let client_body_debug = raw_body
+        .filter(|_| debug_max > 0)
+        .map(|raw| make_body_preview(raw, client_content_type, debug_max));
+    let client_body_warn = raw_body
+        .filter(|_| warn_max > 0)
+        .map(|raw| make_body_preview(raw, client_content_type, warn_max));
+
+    if let Some(ref effort) = override_effort
+        && let ClientBody::Buffered(raw) = &mut client_body
+        && let Some(modified) = apply_reasoning_effort_override(raw, effort)
+    {
+        *raw = Bytes::from(modified);
+    }
+    let mut project_notes = project
+        .iter()
+        .flat_map(|p| &p.repo_files)
+        .map(|f| format!("repo project file: {f}"))
+        .collect::<Vec<_>>();
+    if let Some(extra) = project_instructions
+        && let ClientBody::Buffered(raw) = &mut client_body
+        && let Some(modified) = crate::projects::inject_instructions(raw, extra)
+    {
+        *raw = Bytes::from(modified);
+        project_notes.push(format!("instructions from {}", extra.sources.join(", ")));
+    }
+    if let Some(model) = project_model
+        && request_model.as_deref() != Some(model.as_str())
+        && let ClientBody::Buffered(raw) = &mut client_body
+        && let Some(modified) = apply_model_override(raw, &model)
+    {
+        *raw = Bytes::from(modified);
+        project_notes.push(format!(
+            "project model: {} -> {model}",
+            request_model.as_deref().unwrap_or("-")
+        ));
+        request_model = Some(model);
+    }
 
     let request_id = proxy
         .state
@@ -790,19 +1130,28 @@ pub async fn handle_proxy(
             cwd.clone(),
             request_model.clone(),
             effective_effort.clone(),
+            client_label.clone(),
             started_at_ms,
         )
         .await;
 
-    let retry_opt = retry_options(&cfg_snapshot.retry);
     let total_upstreams = lbs
         .iter()
         .map(|lb| lb.service.upstreams.len())
         .sum::<usize>();
     let mut avoid: HashMap<String, HashSet<usize>> = HashMap::new();
     let mut upstream_chain: Vec<String> = Vec::new();
-
-    for attempt_index in 0..retry_opt.max_attempts {
+    let mut filter_report: Option<FilterReport> = None;
+    // Moving to another key of a pool is not an upstream retry: those attempts are counted here
+    // (bounded by each pool's size) and left out of `attempt_index`.
+    let mut key_rotations = 0u32;
+    let mut key_rotations_by_upstream: HashMap<(String, usize), usize> = HashMap::new();
+
+    for attempt in 0u32.. {
+        let attempt_index = attempt - key_rotations;
+        if attempt_index >= retry_opt.max_attempts {
+            break;
+        }
         let avoided_total = avoid.values().map(|s| s.len()).sum::<usize>();
         if total_upstreams > 0 && avoided_total >= total_upstreams {
             upstream_chain.push(format!("all_upstreams_avoided total={total_upstreams}"));
@@ -869,11 +1218,16 @@ pub async fn handle_proxy(
                 "-",
                 None,
                 "-",
+                None,
+                client_label.clone(),
                 session_id.clone(),
                 cwd.clone(),
+                request_model.clone(),
                 effective_effort.clone(),
                 None,
+                None,
                 retry_info_for_chain(&upstream_chain),
+                filter_report.clone(),
                 None,
             );
             let retry = retry_info_for_chain(&upstream_chain);
@@ -885,6 +1239,7 @@ pub async fn handle_proxy(
                     dur,
                     started_at_ms + dur,
                     None,
+                    None,
                     retry,
                 )
                 .await;
@@ -898,41 +1253,209 @@ pub async fn handle_proxy(
         };
 
         let mut model_note = "-".to_string();
-        let mut body_for_selected = body_for_upstream.clone();
-        if let Some(ref requested_model) = request_model {
-            let effective_model =
-                model_routing::effective_model(&selected.upstream.model_mapping, requested_model);
-            if effective_model != *requested_model {
-                if let Some(modified) =
-                    apply_model_override(body_for_upstream.as_ref(), effective_model.as_str())
-                {
-                    body_for_selected = Bytes::from(modified);
+        let mut upstream_request_body_len = request_body_len;
+        let mut upstream_request_body_debug = None;
+        let mut upstream_request_body_warn = None;
+        let mut body_rewrites = project_notes.clone();
+        // Record the route up front so requests rejected before the upstream call (filter `block`
+        // rules, unreadable bodies) still show which config they were routed to.
+        proxy
+            .state
+            .update_request_route(
+                request_id,
+                selected.config_name.clone(),
+                selected.upstream.tags.get("provider_id").cloned(),
+                selected.upstream.base_url.clone(),
+                None,
+            )
+            .await;
+        let filter_scope = FilterScope {
+            service: proxy.service_name.to_string(),
+            config: Some(selected.config_name.clone()),
+            path: uri.path().to_string(),
+            model: request_model
+                .as_deref()
+                .map(|m| model_routing::effective_model(&selected.upstream.model_mapping, m)),
+        };
+        let upstream_body = if let ClientBody::Buffered(body_for_upstream) = &client_body {
+            let mut body_for_selected = body_for_upstream.clone();
+            let mut model_for_rules = request_model.clone();
+            if let Some(ref requested_model) = request_model {
+                let effective_model = model_routing::effective_model(
+                    &selected.upstream.model_mapping,
+                    requested_model,
+                );
+                if effective_model != *requested_model {
+                    if let Some(modified) =
+                        apply_model_override(body_for_upstream.as_ref(), effective_model.as_str())
+                    {
+                        body_for_selected = Bytes::from(modified);
+                    }
+                    model_note = format!("{requested_model}->{effective_model}");
+                } else {
+                    model_note = requested_model.clone();
                 }
-                model_note = format!("{requested_model}->{effective_model}");
-            } else {
-                model_note = requested_model.clone();
+                model_for_rules = Some(effective_model);
             }
-        }
 
-        let filtered_body = proxy.filter.apply_bytes(body_for_selected);
-        let upstream_request_body_len = filtered_body.len();
-        let upstream_request_body_debug = if request_body_previews && debug_max > 0 {
-            Some(make_body_preview(
-                &filtered_body,
-                client_content_type,
-                debug_max,
-            ))
-        } else {
-            None
-        };
-        let upstream_request_body_warn = if request_body_previews && warn_max > 0 {
-            Some(make_body_preview(
-                &filtered_body,
-                client_content_type,
-                warn_max,
-            ))
+            if let Some((rewritten, changes)) = body_rules::apply_body_rules(
+                &body_for_selected,
+                lb.service
+                    .body_rules
+                    .iter()
+                    .chain(selected.upstream.body_rules.iter()),
+                model_for_rules.as_deref(),
+                uri.path(),
+            ) {
+                tracing::debug!(
+                    "body_rules for {} (idx={}): {}",
+                    selected.config_name,
+                    selected.index,
+                    changes.join("; ")
+                );
+                body_for_selected = Bytes::from(rewritten);
+                body_rewrites.extend(changes);
+            }
+
+            // DLP: `block` filter rules reject the request before anything is sent upstream.
+            if let Some(hit) = proxy.filter.check_block(&body_for_selected, &filter_scope) {
+                let dur = start.elapsed().as_millis() as u64;
+                let status = StatusCode::FORBIDDEN;
+                warn!(
+                    "request {} {} blocked by filter rule '{}' (config: {}, session: {}, client: {})",
+                    method,
+                    uri.path(),
+                    hit.rule_id,
+                    selected.config_name,
+                    session_id.as_deref().unwrap_or("-"),
+                    client_label.as_deref().unwrap_or("-")
+                );
+                if hit.notify {
+                    crate::notify::spawn_exec_event(
+                        &cfg_snapshot.notify,
+                        serde_json::json!({
+                            "type": "codex-helper-request-blocked",
+                            "service": proxy.service_name,
+                            "rule_id": hit.rule_id,
+                            "message": hit.message,
+                            "method": method.as_str(),
+                            "path": uri.path(),
+                            "config_name": selected.config_name,
+                            "session_id": session_id,
+                            "cwd": cwd,
+                            "client": client_label,
+                        }),
+                    );
+                }
+                log_request_with_debug(
+                    proxy.service_name,
+                    method.as_str(),
+                    uri.path(),
+                    status.as_u16(),
+                    dur,
+                    &selected.config_name,
+                    selected.upstream.tags.get("provider_id").cloned(),
+                    &selected.upstream.base_url,
+                    None,
+                    client_label.clone(),
+                    session_id.clone(),
+                    cwd.clone(),
+                    filter_scope.model.clone(),
+                    effective_effort.clone(),
+                    None,
+                    None,
+                    None,
+                    Some(FilterReport {
+                        redactions: Default::default(),
+                        blocked_by: Some(hit.rule_id.clone()),
+                    }),
+                    None,
+                );
+                proxy
+                    .state
+                    .finish_request(
+                        request_id,
+                        status.as_u16(),
+                        dur,
+                        started_at_ms + dur,
+                        None,
+                        None,
+                        None,
+                    )
+                    .await;
+                return Ok(blocked_by_policy_response(&hit));
+            }
+
+            let (filtered_body, counts) =
+                proxy
+                    .filter
+                    .apply_bytes(body_for_selected, session_id.as_deref(), &filter_scope);
+            filter_report = (!counts.is_empty()).then_some(FilterReport {
+                redactions: counts,
+                blocked_by: None,
+            });
+            upstream_request_body_len = filtered_body.len();
+            if request_body_previews && debug_max > 0 {
+                upstream_request_body_debug = Some(make_body_preview(
+                    &filtered_body,
+                    client_content_type,
+                    debug_max,
+                ));
+            }
+            if request_body_previews && warn_max > 0 {
+                upstream_request_body_warn = Some(make_body_preview(
+                    &filtered_body,
+                    client_content_type,
+                    warn_max,
+                ));
+            }
+            reqwest::Body::from(filtered_body)
         } else {
-            None
+            // Passthrough bodies are forwarded untouched, so there is nothing to preview or rewrite.
+            if let Some(ref requested_model) = request_model {
+                model_note = requested_model.clone();
+            }
+            match client_body.upstream_body(body_limit).await {
+                Ok(b) => b,
+                Err(err_str) => {
+                    let dur = start.elapsed().as_millis() as u64;
+                    let status = StatusCode::INTERNAL_SERVER_ERROR;
+                    log_request_with_debug(
+                        proxy.service_name,
+                        method.as_str(),
+                        uri.path(),
+                        status.as_u16(),
+                        dur,
+                        &selected.config_name,
+                        selected.upstream.tags.get("provider_id").cloned(),
+                        &selected.upstream.base_url,
+                        None,
+                        client_label.clone(),
+                        session_id.clone(),
+                        cwd.clone(),
+                        filter_scope.model.clone(),
+                        effective_effort.clone(),
+                        None,
+                        None,
+                        retry_info_for_chain(&upstream_chain),
+                        filter_report.clone(),
+                        None,
+                    );
+                    proxy
+                        .state
+                        .finish_request(
+                            request_id,
+                            status.as_u16(),
+                            dur,
+                            started_at_ms + dur,
+                            None,
+                            None,
+                            retry_info_for_chain(&upstream_chain),
+                        )
+                        .await;
+                    return Err((status, err_str));
+                }
+            }
         };
 
         let target_url = match proxy.build_target(&selected, &uri) {
@@ -976,6 +1499,7 @@ pub async fn handle_proxy(
                             "构造上游 target_url 失败（通常是 base_url 配置错误）。".to_string(),
                         ),
                         upstream_cf_ray: None,
+                        upstream_remote_addr: None,
                         client_uri: uri.to_string(),
                         target_url: "-".to_string(),
                         client_headers: client_headers_entries,
@@ -983,6 +1507,7 @@ pub async fn handle_proxy(
                         auth_resolution: None,
                         client_body: client_body_warn.clone(),
                         upstream_request_body: upstream_request_body_warn.clone(),
+                        body_rewrites: body_rewrites.clone(),
                         upstream_response_headers: None,
                         upstream_response_body: None,
                         upstream_error: Some(err_str.clone()),
@@ -999,11 +1524,16 @@ pub async fn handle_proxy(
                     &selected.config_name,
                     selected.upstream.tags.get("provider_id").cloned(),
                     &selected.upstream.base_url,
+                    None,
+                    client_label.clone(),
                     session_id.clone(),
                     cwd.clone(),
+                    filter_scope.model.clone(),
                     effective_effort.clone(),
                     None,
+                    None,
                     retry_info_for_chain(&upstream_chain),
+                    filter_report.clone(),
                     http_debug,
                 );
                 let retry = retry_info_for_chain(&upstream_chain);
@@ -1015,6 +1545,7 @@ pub async fn handle_proxy(
                         dur,
                         started_at_ms + dur,
                         None,
+                        None,
                         retry,
                     )
                     .await;
@@ -1028,11 +1559,40 @@ pub async fn handle_proxy(
         // - otherwise, preserve client Authorization / X-API-Key (required for requires_openai_auth=true providers).
         let mut headers = filter_request_headers(&client_headers);
         let client_has_auth = headers.contains_key("authorization");
-        let (token, token_src) = resolve_auth_token_with_source(
-            proxy.service_name,
-            &selected.upstream.auth,
-            client_has_auth,
-        );
+        let token_pool = if selected.upstream.auth.has_token_pool() {
+            resolve_auth_token_pool(proxy.service_name, &selected.upstream.auth).await
+        } else {
+            Vec::new()
+        };
+        let token_pool_ids = token_pool
+            .iter()
+            .map(|k| k.key_id.as_str())
+            .collect::<Vec<_>>();
+        let (token, token_src, auth_key) = match lb.select_key(
+            selected.index,
+            &token_pool_ids,
+            selected.upstream.auth.key_rotation.unwrap_or_default(),
+        ) {
+            Some(i) => {
+                let k = &token_pool[i];
+                // The pool wins over the client's own Authorization; say so in the log.
+                let src = if client_has_auth {
+                    format!("pool:{} (client_auth_overridden)", k.source)
+                } else {
+                    format!("pool:{}", k.source)
+                };
+                (Some(k.value.clone()), src, Some(k.key_id.clone()))
+            }
+            None => {
+                let (token, src) = resolve_auth_token_with_source(
+                    proxy.service_name,
+                    &selected.upstream.auth,
+                    client_has_auth,
+                )
+                .await;
+                (token, src, None)
+            }
+        };
         if let Some(token) = token
             && let Ok(v) = HeaderValue::from_str(&format!("Bearer {token}"))
         {
@@ -1044,7 +1604,8 @@ pub async fn handle_proxy(
             proxy.service_name,
             &selected.upstream.auth,
             client_has_x_api_key,
-        );
+        )
+        .await;
         if let Some(key) = api_key
             && let Ok(v) = HeaderValue::from_str(&key)
         {
@@ -1060,6 +1621,7 @@ pub async fn handle_proxy(
                 selected.config_name.clone(),
                 provider_id.clone(),
                 selected.upstream.base_url.clone(),
+                auth_key.clone(),
             )
             .await;
         let auth_resolution = AuthResolutionLog {
@@ -1067,7 +1629,7 @@ pub async fn handle_proxy(
             x_api_key: Some(api_key_src),
         };
 
-        let debug_base = if debug_max > 0 || warn_max > 0 {
+        let mut debug_base = if debug_max > 0 || warn_max > 0 {
             Some(HttpDebugBase {
                 debug_max_body_bytes: debug_max,
                 warn_max_body_bytes: warn_max,
@@ -1084,6 +1646,7 @@ pub async fn handle_proxy(
                 upstream_request_body_debug: upstream_request_body_debug.clone(),
                 client_body_warn: client_body_warn.clone(),
                 upstream_request_body_warn: upstream_request_body_warn.clone(),
+                body_rewrites: body_rewrites.clone(),
             })
         } else {
             None
@@ -1099,13 +1662,24 @@ pub async fn handle_proxy(
         );
 
         let builder = proxy
-            .client
+            .upstream_clients
+            .client_for(&proxy.client, &selected.upstream)
             .request(method.clone(), target_url.clone())
             .headers(headers)
-            .body(filtered_body.clone());
+            .body(upstream_body);
 
         let upstream_start = Instant::now();
-        let resp = match builder.send().await {
+        let sent = builder.send().await;
+        // Without Content-Length a streamed body's length is only known once it was forwarded.
+        if matches!(client_body, ClientBody::Streaming(..)) {
+            request_body_len = client_body.len(content_length);
+            upstream_request_body_len = request_body_len;
+            if let Some(b) = debug_base.as_mut() {
+                b.request_body_len = request_body_len;
+                b.upstream_request_body_len = request_body_len;
+            }
+        }
+        let resp = match sent {
             Ok(r) => r,
             Err(e) => {
                 lb.record_result(selected.index, false);
@@ -1153,6 +1727,7 @@ pub async fn handle_proxy(
                             "上游连接/发送请求失败（reqwest 错误）；请检查网络、DNS、TLS、代理设置或上游可用性。".to_string(),
                         ),
                         upstream_cf_ray: None,
+                        upstream_remote_addr: None,
                         client_uri: b.client_uri.clone(),
                         target_url: b.target_url.clone(),
                         client_headers: b.client_headers.clone(),
@@ -1160,6 +1735,7 @@ pub async fn handle_proxy(
                         auth_resolution: b.auth_resolution.clone(),
                         client_body: b.client_body_warn.clone(),
                         upstream_request_body: b.upstream_request_body_warn.clone(),
+                        body_rewrites: b.body_rewrites.clone(),
                         upstream_response_headers: None,
                         upstream_response_body: None,
                         upstream_error: Some(err_str.clone()),
@@ -1186,6 +1762,7 @@ pub async fn handle_proxy(
                                 "上游连接/发送请求失败（reqwest 错误）；请检查网络、DNS、TLS、代理设置或上游可用性。".to_string(),
                             ),
                             upstream_cf_ray: None,
+                            upstream_remote_addr: None,
                             client_uri: b.client_uri.clone(),
                             target_url: b.target_url.clone(),
                             client_headers: b.client_headers.clone(),
@@ -1193,6 +1770,7 @@ pub async fn handle_proxy(
                             auth_resolution: b.auth_resolution.clone(),
                             client_body: b.client_body_debug.clone(),
                             upstream_request_body: b.upstream_request_body_debug.clone(),
+                            body_rewrites: b.body_rewrites.clone(),
                             upstream_response_headers: None,
                             upstream_response_body: None,
                             upstream_error: Some(err_str.clone()),
@@ -1212,11 +1790,16 @@ pub async fn handle_proxy(
                     &selected.config_name,
                     selected.upstream.tags.get("provider_id").cloned(),
                     &selected.upstream.base_url,
+                    auth_key.clone(),
+                    client_label.clone(),
                     session_id.clone(),
                     cwd.clone(),
+                    filter_scope.model.clone(),
                     effective_effort.clone(),
                     None,
+                    None,
                     retry.clone(),
+                    filter_report.clone(),
                     http_debug,
                 );
                 proxy
@@ -1227,6 +1810,7 @@ pub async fn handle_proxy(
                         dur,
                         started_at_ms + dur,
                         None,
+                        None,
                         retry,
                     )
                     .await;
@@ -1235,6 +1819,7 @@ pub async fn handle_proxy(
         };
 
         let upstream_headers_ms = upstream_start.elapsed().as_millis() as u64;
+        let upstream_remote_addr = resp.remote_addr().map(|a| a.to_string());
         let status = resp.status();
         let success = status.is_success();
         let resp_headers = resp.headers().clone();
@@ -1244,6 +1829,9 @@ pub async fn handle_proxy(
 
         if is_stream && success {
             lb.record_result(selected.index, true);
+            if let Some(key_id) = auth_key.as_deref() {
+                lb.record_key_result(selected.index, key_id, true);
+            }
             upstream_chain.push(format!(
                 "{} (idx={}) status={} model={}",
                 selected.upstream.base_url,
@@ -1273,6 +1861,10 @@ pub async fn handle_proxy(
                     session_id: session_id.clone(),
                     cwd: cwd.clone(),
                     effective_effort: effective_effort.clone(),
+                    auth_key: auth_key.clone(),
+                    client: client_label.clone(),
+                    filter_report: filter_report.clone(),
+                    filter_scope,
                     request_id,
                     is_user_turn,
                     is_codex_service,
@@ -1329,6 +1921,7 @@ pub async fn handle_proxy(
                                     .to_string(),
                             ),
                             upstream_cf_ray: None,
+                            upstream_remote_addr: upstream_remote_addr.clone(),
                             client_uri: b.client_uri.clone(),
                             target_url: b.target_url.clone(),
                             client_headers: b.client_headers.clone(),
@@ -1336,6 +1929,7 @@ pub async fn handle_proxy(
                             auth_resolution: b.auth_resolution.clone(),
                             client_body: b.client_body_warn.clone(),
                             upstream_request_body: b.upstream_request_body_warn.clone(),
+                            body_rewrites: b.body_rewrites.clone(),
                             upstream_response_headers: Some(header_map_to_entries(&resp_headers)),
                             upstream_response_body: None,
                             upstream_error: Some(err_str.clone()),
@@ -1352,11 +1946,16 @@ pub async fn handle_proxy(
                         &selected.config_name,
                         selected.upstream.tags.get("provider_id").cloned(),
                         &selected.upstream.base_url,
+                        auth_key.clone(),
+                        client_label.clone(),
                         session_id.clone(),
                         cwd.clone(),
+                        filter_scope.model.clone(),
                         effective_effort.clone(),
                         None,
+                        None,
                         retry_info_for_chain(&upstream_chain),
+                        filter_report.clone(),
                         http_debug,
                     );
                     let retry = retry_info_for_chain(&upstream_chain);
@@ -1368,6 +1967,7 @@ pub async fn handle_proxy(
                             dur,
                             started_at_ms + dur,
                             None,
+                            None,
                             retry,
                         )
                         .await;
@@ -1377,6 +1977,18 @@ pub async fn handle_proxy(
             let upstream_body_read_ms = upstream_start.elapsed().as_millis() as u64;
             let dur = start.elapsed().as_millis() as u64;
             let usage = extract_usage_from_bytes(&bytes);
+            let cost = usage.as_ref().and_then(|u| {
+                crate::pricing::estimate(
+                    &cfg_snapshot.pricing,
+                    filter_scope.model.as_deref(),
+                    selected
+                        .upstream
+                        .tags
+                        .get("provider_id")
+                        .map(String::as_str),
+                    u,
+                )
+            });
             let status_code = status.as_u16();
             let (cls, hint, cf_ray) =
                 classify_upstream_response(status_code, &resp_headers, bytes.as_ref());
@@ -1390,6 +2002,43 @@ pub async fn handle_proxy(
                 model_note.as_str()
             ));
 
+            // Key pools: 429 / 402 are per-key limits, so try another key on the same upstream
+            // before failing over to a different upstream.
+            if let Some(key_id) = auth_key.as_deref() {
+                if success {
+                    lb.record_key_result(selected.index, key_id, true);
+                } else if matches!(status_code, 401 | 403) {
+                    lb.record_key_result(selected.index, key_id, false);
+                } else if matches!(status_code, 402 | 429) {
+                    lb.mark_key_exhausted(
+                        selected.index,
+                        key_id,
+                        key_cooldown_secs(&retry_opt, &resp_headers),
+                        &format!("status_{status_code}"),
+                    );
+                    let rotated = key_rotations_by_upstream
+                        .entry((selected.config_name.clone(), selected.index))
+                        .or_default();
+                    if *rotated + 1 < token_pool_ids.len()
+                        && lb.has_other_available_key(selected.index, &token_pool_ids, key_id)
+                    {
+                        *rotated += 1;
+                        key_rotations += 1;
+                        info!(
+                            "rotating key after status {} for {} {} (config: {}, upstream[{}], key: {})",
+                            status_code,
+                            method,
+                            uri.path(),
+                            selected.config_name,
+                            selected.index,
+                            key_id
+                        );
+                        // The limit is per key, so the next key goes out without a backoff.
+                        continue;
+                    }
+                }
+            }
+
             let retryable = !status.is_success()
                 && attempt_index + 1 < retry_opt.max_attempts
                 && (should_retry_status(&retry_opt, status_code)
@@ -1478,6 +2127,7 @@ pub async fn handle_proxy(
                     upstream_error_class: cls.clone(),
                     upstream_error_hint: hint.clone(),
                     upstream_cf_ray: cf_ray.clone(),
+                    upstream_remote_addr: upstream_remote_addr.clone(),
                     client_uri: b.client_uri.clone(),
                     target_url: b.target_url.clone(),
                     client_headers: b.client_headers.clone(),
@@ -1485,6 +2135,7 @@ pub async fn handle_proxy(
                     auth_resolution: b.auth_resolution.clone(),
                     client_body: b.client_body_warn.clone(),
                     upstream_request_body: b.upstream_request_body_warn.clone(),
+                    body_rewrites: b.body_rewrites.clone(),
                     upstream_response_headers: Some(header_map_to_entries(&resp_headers)),
                     upstream_response_body: Some(make_body_preview(bytes.as_ref(), resp_ct, max)),
                     upstream_error: None,
@@ -1526,6 +2177,7 @@ pub async fn handle_proxy(
                         upstream_error_class: cls,
                         upstream_error_hint: hint,
                         upstream_cf_ray: cf_ray,
+                        upstream_remote_addr,
                         client_uri: b.client_uri,
                         target_url: b.target_url,
                         client_headers: b.client_headers,
@@ -1533,6 +2185,7 @@ pub async fn handle_proxy(
                         auth_resolution: b.auth_resolution,
                         client_body: b.client_body_debug,
                         upstream_request_body: b.upstream_request_body_debug,
+                        body_rewrites: b.body_rewrites.clone(),
                         upstream_response_headers: Some(header_map_to_entries(&resp_headers)),
                         upstream_response_body: Some(make_body_preview(
                             bytes.as_ref(),
@@ -1557,11 +2210,16 @@ pub async fn handle_proxy(
                 &selected.config_name,
                 selected.upstream.tags.get("provider_id").cloned(),
                 &selected.upstream.base_url,
+                auth_key.clone(),
+                client_label.clone(),
                 session_id.clone(),
                 cwd.clone(),
+                filter_scope.model.clone(),
                 effective_effort.clone(),
                 usage.clone(),
+                cost.clone(),
                 retry.clone(),
+                filter_report.clone(),
                 http_debug,
             );
             proxy
@@ -1572,6 +2230,7 @@ pub async fn handle_proxy(
                     dur,
                     started_at_ms + dur,
                     usage.clone(),
+                    cost.clone(),
                     retry,
                 )
                 .await;
@@ -1591,6 +2250,10 @@ pub async fn handle_proxy(
             for (name, value) in resp_headers_filtered.iter() {
                 builder = builder.header(name, value);
             }
+            let bytes =
+                proxy
+                    .filter
+                    .apply_response_bytes(bytes, session_id.as_deref(), &filter_scope);
             return Ok(builder.body(Body::from(bytes)).unwrap());
         }
     }
@@ -1610,6 +2273,7 @@ pub async fn handle_proxy(
             upstream_error_class: Some("retry_exhausted".to_string()),
             upstream_error_hint: Some("所有重试尝试均未能返回可用响应。".to_string()),
             upstream_cf_ray: None,
+            upstream_remote_addr: None,
             client_uri: uri.to_string(),
             target_url: "-".to_string(),
             client_headers: client_headers_entries,
@@ -1617,6 +2281,7 @@ pub async fn handle_proxy(
             auth_resolution: None,
             client_body: client_body_warn.clone(),
             upstream_request_body: None,
+            body_rewrites: Vec::new(),
             upstream_response_headers: None,
             upstream_response_body: None,
             upstream_error: Some(format!(
@@ -1636,11 +2301,16 @@ pub async fn handle_proxy(
         "-",
         None,
         "-",
+        None,
+        client_label.clone(),
         session_id.clone(),
         cwd.clone(),
+        request_model.clone(),
         effective_effort.clone(),
         None,
+        None,
         retry_info_for_chain(&upstream_chain),
+        filter_report.clone(),
         http_debug,
     );
     let retry = retry_info_for_chain(&upstream_chain);
@@ -1652,6 +2322,7 @@ pub async fn handle_proxy(
             dur,
             started_at_ms + dur,
             None,
+            None,
             retry,
         )
         .await;
@@ -1672,6 +2343,8 @@ pub fn router(proxy: ProxyService) -> Router {
         loaded_at_ms: u64,
         source_mtime_ms: Option<u64>,
         retry: crate::config::RetryConfig,
+        /// filter.json 的解析/正则问题（为空表示正常）。
+        filter_problems: Vec<String>,
     }
 
     #[derive(serde::Serialize)]
@@ -1689,6 +2362,7 @@ pub fn router(proxy: ProxyService) -> Router {
             loaded_at_ms: proxy.config.last_loaded_at_ms(),
             source_mtime_ms: proxy.config.last_mtime_ms().await,
             retry: cfg.retry.clone(),
+            filter_problems: proxy.filter.problems(),
         }))
     }
 
@@ -1708,6 +2382,7 @@ pub fn router(proxy: ProxyService) -> Router {
                 loaded_at_ms: proxy.config.last_loaded_at_ms(),
                 source_mtime_ms: proxy.config.last_mtime_ms().await,
                 retry: cfg.retry.clone(),
+                filter_problems: proxy.filter.problems(),
             },
         }))
     }
@@ -1774,6 +2449,28 @@ pub fn router(proxy: ProxyService) -> Router {
         Ok(Json(vec))
     }
 
+    async fn list_budgets(
+        proxy: ProxyService,
+    ) -> Result<Json<Vec<crate::budget::BudgetStatus>>, (StatusCode, String)> {
+        let cfg = proxy.config.snapshot().await;
+        let budgets = proxy
+            .service_manager(cfg.as_ref())
+            .configs
+            .iter()
+            .filter(|(_, svc)| !svc.budget.is_empty())
+            .map(|(name, svc)| (name.clone(), svc.budget.clone()))
+            .collect::<Vec<_>>();
+        let now_ms = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        let statuses = proxy
+            .state
+            .budget_statuses(proxy.service_name, &budgets, now_ms)
+            .await;
+        Ok(Json(statuses))
+    }
+
     let p0 = proxy.clone();
     let p1 = proxy.clone();
     let p2 = proxy.clone();
@@ -1782,6 +2479,7 @@ pub fn router(proxy: ProxyService) -> Router {
     let p5 = proxy.clone();
     let p6 = proxy.clone();
     let p7 = proxy.clone();
+    let p8 = proxy.clone();
 
     Router::new()
         .route(
@@ -1806,5 +2504,13 @@ pub fn router(proxy: ProxyService) -> Router {
             "/__codex_helper/status/recent",
             get(move |q| list_recent_finished(p4.clone(), q)),
         )
+        .route(
+            "/__codex_helper/status/budgets",
+            get(move || list_budgets(p8.clone())),
+        )
         .route("/{*path}", any(move |req| handle_proxy(p2.clone(), req)))
+        .layer(axum::middleware::from_fn_with_state(
+            proxy,
+            access::enforce_access,
+        ))
 }
@@ -16,6 +16,7 @@ pub(super) struct RetryOptions {
     pub(super) cloudflare_challenge_cooldown_secs: u64,
     pub(super) cloudflare_timeout_cooldown_secs: u64,
     pub(super) transport_cooldown_secs: u64,
+    pub(super) key_cooldown_secs: u64,
 }
 
 pub(super) fn parse_status_ranges(spec: &str) -> Vec<(u16, u16)> {
@@ -82,6 +83,10 @@ pub(super) fn retry_options(cfg: &RetryConfig) -> RetryOptions {
         .ok()
         .and_then(|s| s.trim().parse::<u64>().ok())
         .unwrap_or(cfg.transport_cooldown_secs);
+    let key_cooldown_secs = std::env::var("CODEX_HELPER_RETRY_KEY_COOLDOWN_SECS")
+        .ok()
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .unwrap_or(cfg.key_cooldown_secs);
 
     RetryOptions {
         max_attempts,
@@ -93,6 +98,7 @@ pub(super) fn retry_options(cfg: &RetryConfig) -> RetryOptions {
         cloudflare_challenge_cooldown_secs,
         cloudflare_timeout_cooldown_secs,
         transport_cooldown_secs,
+        key_cooldown_secs,
     }
 }
 
@@ -138,6 +144,17 @@ fn retry_after_ms(headers: &HeaderMap, opt: &RetryOptions) -> Option<u64> {
     Some(ms.min(cap))
 }
 
+/// How long a pooled key stays out of rotation after a 429 / quota response.
+/// Unlike `retry_after_ms`, `Retry-After` is not capped by the backoff limit here (bounded to one hour).
+pub(super) fn key_cooldown_secs(opt: &RetryOptions, headers: &HeaderMap) -> u64 {
+    headers
+        .get("retry-after")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|s| s.trim().parse::<u64>().ok())
+        .map(|s| s.min(3600))
+        .unwrap_or(opt.key_cooldown_secs)
+}
+
 pub(super) async fn backoff_sleep(opt: &RetryOptions, attempt_index: u32) {
     if opt.base_backoff_ms == 0 {
         return;
@@ -200,8 +217,12 @@ mod tests {
             cloudflare_challenge_cooldown_secs: 0,
             cloudflare_timeout_cooldown_secs: 0,
             transport_cooldown_secs: 0,
+            key_cooldown_secs: 60,
         };
         assert_eq!(retry_after_ms(&headers, &opt), Some(2_000));
+        // Key cooldown uses the full Retry-After value rather than the backoff cap.
+        assert_eq!(key_cooldown_secs(&opt, &headers), 10);
+        assert_eq!(key_cooldown_secs(&opt, &HeaderMap::new()), 60);
     }
 
     #[test]
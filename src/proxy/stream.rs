@@ -1,17 +1,21 @@
 use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
-use axum::body::Body;
+use axum::body::{Body, Bytes};
 use axum::http::{HeaderMap, Method, Response, StatusCode};
-use futures_util::StreamExt;
+use futures_util::{Stream, StreamExt};
 use tracing::{info, warn};
 
+use crate::config::PricingConfig;
+use crate::filter::{FilterReport, FilterScope, ResponseStreamFilter};
 use crate::lb::LoadBalancer;
 use crate::logging::{
     HttpDebugLog, RetryInfo, log_request_with_debug, make_body_preview, should_include_http_debug,
     should_include_http_warn,
 };
+use crate::pricing::Cost;
 use crate::state::ProxyState;
+use crate::usage::UsageMetrics;
 use crate::usage_providers;
 
 use super::classify::classify_upstream_response;
@@ -27,7 +31,7 @@ struct StreamUsageState {
     stream_error: bool,
     warned_non_success: bool,
     first_chunk_ms: Option<u64>,
-    usage: Option<crate::usage::UsageMetrics>,
+    usage: Option<UsageMetrics>,
     usage_scan_pos: usize,
 }
 
@@ -40,15 +44,21 @@ struct StreamFinalize {
     started_at_ms: u64,
     upstream_start: Instant,
     upstream_headers_ms: u64,
+    upstream_remote_addr: Option<String>,
     request_body_len: usize,
     upstream_request_body_len: usize,
     config_name: String,
     provider_id: Option<String>,
     upstream_base_url: String,
+    auth_key: Option<String>,
+    client: Option<String>,
+    filter_report: Option<FilterReport>,
     retry: Option<RetryInfo>,
     session_id: Option<String>,
     cwd: Option<String>,
+    model: Option<String>,
     reasoning_effort: Option<String>,
+    pricing: PricingConfig,
     request_id: u64,
     state: Arc<ProxyState>,
     resp_headers: HeaderMap,
@@ -60,6 +70,15 @@ struct StreamFinalize {
 }
 
 impl StreamFinalize {
+    fn cost(&self, usage: &UsageMetrics) -> Option<Cost> {
+        crate::pricing::estimate(
+            &self.pricing,
+            self.model.as_deref(),
+            self.provider_id.as_deref(),
+            usage,
+        )
+    }
+
     fn build_http_debug(
         &self,
         body: &[u8],
@@ -101,6 +120,7 @@ impl StreamFinalize {
             upstream_error_class: cls,
             upstream_error_hint: hint,
             upstream_cf_ray: cf_ray,
+            upstream_remote_addr: self.upstream_remote_addr.clone(),
             client_uri: b.client_uri.clone(),
             target_url: b.target_url.clone(),
             client_headers: b.client_headers.clone(),
@@ -108,6 +128,7 @@ impl StreamFinalize {
             auth_resolution: b.auth_resolution.clone(),
             client_body,
             upstream_request_body,
+            body_rewrites: b.body_rewrites.clone(),
             upstream_response_headers: Some(header_map_to_entries(&self.resp_headers)),
             upstream_response_body: Some(make_body_preview(body, resp_ct, max)),
             upstream_error: None,
@@ -132,6 +153,7 @@ impl Drop for StreamFinalize {
         guard.finished = true;
         let already_logged = guard.logged;
         let usage_for_state = guard.usage.clone();
+        let cost_for_state = usage_for_state.as_ref().and_then(|u| self.cost(u));
         let retry_for_state = self.retry.clone();
         let stream_error = guard.stream_error;
 
@@ -162,11 +184,16 @@ impl Drop for StreamFinalize {
                 &self.config_name,
                 self.provider_id.clone(),
                 &self.upstream_base_url,
+                self.auth_key.clone(),
+                self.client.clone(),
                 self.session_id.clone(),
                 self.cwd.clone(),
+                self.model.clone(),
                 self.reasoning_effort.clone(),
                 usage,
+                cost_for_state.clone(),
                 self.retry.clone(),
+                self.filter_report.clone(),
                 http_debug,
             );
         }
@@ -190,6 +217,7 @@ impl Drop for StreamFinalize {
                     dur,
                     started_at_ms + dur,
                     usage_for_state,
+                    cost_for_state,
                     retry_for_state,
                 )
                 .await;
@@ -219,6 +247,10 @@ pub(super) async fn build_sse_success_response(
         session_id,
         cwd,
         effective_effort,
+        auth_key,
+        client,
+        filter_report,
+        filter_scope,
         request_id,
         is_user_turn,
         is_codex_service,
@@ -266,15 +298,21 @@ pub(super) async fn build_sse_success_response(
         started_at_ms,
         upstream_start,
         upstream_headers_ms,
+        upstream_remote_addr: resp.remote_addr().map(|a| a.to_string()),
         request_body_len,
         upstream_request_body_len,
         config_name: config_name.clone(),
         provider_id: provider_id.clone(),
         upstream_base_url: base_url.clone(),
+        auth_key: auth_key.clone(),
+        client: client.clone(),
+        filter_report: filter_report.clone(),
         retry: retry.clone(),
         session_id: session_id.clone(),
         cwd: cwd.clone(),
+        model: filter_scope.model.clone(),
         reasoning_effort: effective_effort.clone(),
+        pricing: proxy.config.snapshot().await.pricing.clone(),
         request_id,
         state: proxy.state.clone(),
         resp_headers: resp_headers.clone(),
@@ -304,6 +342,11 @@ pub(super) async fn build_sse_success_response(
         });
     }
 
+    let response_filter = proxy.filter.has_response_rules().then(|| {
+        proxy
+            .filter
+            .response_stream(session_id.as_deref(), &filter_scope)
+    });
     let stream = resp.bytes_stream().map(move |item| {
         let _finalize = &finalize;
 
@@ -339,7 +382,7 @@ pub(super) async fn build_sse_success_response(
                 if guard.logged {
                     return Ok(chunk);
                 }
-                {
+                let usage_complete = {
                     let StreamUsageState {
                         buffer,
                         usage_scan_pos,
@@ -350,10 +393,11 @@ pub(super) async fn build_sse_success_response(
                         buffer.as_slice(),
                         usage_scan_pos,
                         usage,
-                    );
-                }
-                if let Some(usage) = guard.usage.clone() {
+                    )
+                };
+                if usage_complete && let Some(usage) = guard.usage.clone() {
                     guard.logged = true;
+                    let cost = _finalize.cost(&usage);
                     let dur = start_time.elapsed().as_millis() as u64;
                     let http_debug = if should_include_http_debug(status_code) {
                         _finalize.build_http_debug(&guard.buffer, guard.first_chunk_ms, false)
@@ -369,12 +413,17 @@ pub(super) async fn build_sse_success_response(
                         &config_name,
                         provider_id.clone(),
                         &base_url,
+                        auth_key.clone(),
+                        client.clone(),
                         session_id.clone(),
                         cwd.clone(),
+                        _finalize.model.clone(),
                         effective_effort.clone(),
                         Some(usage),
+                        cost,
                         retry.clone(),
-                        http_debug,
+                        filter_report.clone(),
+                        http_debug
                     );
                 }
 
@@ -397,7 +446,10 @@ pub(super) async fn build_sse_success_response(
         }
     });
 
-    let body = Body::from_stream(stream);
+    let body = match response_filter {
+        Some(filter) => Body::from_stream(filter_response_stream(stream, filter)),
+        None => Body::from_stream(stream),
+    };
     let mut builder = Response::builder().status(status);
     for (name, value) in resp_headers_filtered.iter() {
         builder = builder.header(name, value);
@@ -408,6 +460,36 @@ pub(super) async fn build_sse_success_response(
     builder.body(body).unwrap()
 }
 
+/// Runs response filter rules over the client-bound stream, flushing held-back bytes at the end.
+fn filter_response_stream<S>(
+    stream: S,
+    filter: ResponseStreamFilter,
+) -> impl Stream<Item = Result<Bytes, reqwest::Error>> + Send
+where
+    S: Stream<Item = Result<Bytes, reqwest::Error>> + Send + 'static,
+{
+    futures_util::stream::unfold(
+        (Box::pin(stream), Some(filter)),
+        |(mut stream, mut filter)| async move {
+            loop {
+                let f = filter.as_mut()?;
+                match stream.next().await {
+                    Some(Ok(chunk)) => {
+                        if let Some(out) = f.push(&chunk) {
+                            return Some((Ok(out), (stream, filter)));
+                        }
+                    }
+                    Some(Err(e)) => return Some((Err(e), (stream, filter))),
+                    None => {
+                        let rest = filter.take().and_then(|mut f| f.finish())?;
+                        return Some((Ok(rest), (stream, None)));
+                    }
+                }
+            }
+        },
+    )
+}
+
 pub(super) struct SseSuccessMeta {
     pub(super) status: StatusCode,
     pub(super) resp_headers: HeaderMap,
@@ -423,6 +505,10 @@ pub(super) struct SseSuccessMeta {
     pub(super) session_id: Option<String>,
     pub(super) cwd: Option<String>,
     pub(super) effective_effort: Option<String>,
+    pub(super) auth_key: Option<String>,
+    pub(super) client: Option<String>,
+    pub(super) filter_report: Option<FilterReport>,
+    pub(super) filter_scope: FilterScope,
     pub(super) request_id: u64,
     pub(super) is_user_turn: bool,
     pub(super) is_codex_service: bool,
@@ -36,7 +36,9 @@ fn make_proxy_config(upstreams: Vec<UpstreamConfig>, retry: RetryConfig) -> Prox
             alias: None,
             enabled: true,
             level: 1,
+            budget: Default::default(),
             upstreams,
+            body_rules: Vec::new(),
         },
     );
 
@@ -48,6 +50,10 @@ fn make_proxy_config(upstreams: Vec<UpstreamConfig>, retry: RetryConfig) -> Prox
         notify: Default::default(),
         default_service: None,
         ui: UiConfig::default(),
+        server: Default::default(),
+        projects: Default::default(),
+        pricing: Default::default(),
+        usage: Default::default(),
     }
 }
 
@@ -93,6 +99,7 @@ async fn proxy_failover_retries_502_then_uses_second_upstream() {
         cloudflare_challenge_cooldown_secs: 0,
         cloudflare_timeout_cooldown_secs: 0,
         transport_cooldown_secs: 0,
+        key_cooldown_secs: 0,
     };
     let cfg = make_proxy_config(
         vec![
@@ -103,6 +110,17 @@ async fn proxy_failover_retries_502_then_uses_second_upstream() {
                     auth_token_env: None,
                     api_key: None,
                     api_key_env: None,
+                    auth_token_file: None,
+                    auth_token_cmd: Vec::new(),
+                    api_key_file: None,
+                    api_key_cmd: Vec::new(),
+                    cmd_cache_ttl_secs: None,
+                    auth_token_secret: None,
+                    api_key_secret: None,
+                    auth_tokens: Vec::new(),
+                    auth_token_envs: Vec::new(),
+                    auth_token_secrets: Vec::new(),
+                    key_rotation: None,
                 },
                 tags: {
                     let mut t = HashMap::new();
@@ -111,6 +129,9 @@ async fn proxy_failover_retries_502_then_uses_second_upstream() {
                 },
                 supported_models: HashMap::new(),
                 model_mapping: HashMap::new(),
+                resolve: HashMap::new(),
+                ip_preference: None,
+                body_rules: Vec::new(),
             },
             UpstreamConfig {
                 base_url: format!("http://{}/v1", u2_addr),
@@ -119,6 +140,17 @@ async fn proxy_failover_retries_502_then_uses_second_upstream() {
                     auth_token_env: None,
                     api_key: None,
                     api_key_env: None,
+                    auth_token_file: None,
+                    auth_token_cmd: Vec::new(),
+                    api_key_file: None,
+                    api_key_cmd: Vec::new(),
+                    cmd_cache_ttl_secs: None,
+                    auth_token_secret: None,
+                    api_key_secret: None,
+                    auth_tokens: Vec::new(),
+                    auth_token_envs: Vec::new(),
+                    auth_token_secrets: Vec::new(),
+                    key_rotation: None,
                 },
                 tags: {
                     let mut t = HashMap::new();
@@ -127,6 +159,9 @@ async fn proxy_failover_retries_502_then_uses_second_upstream() {
                 },
                 supported_models: HashMap::new(),
                 model_mapping: HashMap::new(),
+                resolve: HashMap::new(),
+                ip_preference: None,
+                body_rules: Vec::new(),
             },
         ],
         retry,
@@ -203,6 +238,7 @@ async fn proxy_does_not_retry_or_failover_on_400() {
         cloudflare_challenge_cooldown_secs: 0,
         cloudflare_timeout_cooldown_secs: 0,
         transport_cooldown_secs: 0,
+        key_cooldown_secs: 0,
     };
     let cfg = make_proxy_config(
         vec![
@@ -213,10 +249,24 @@ async fn proxy_does_not_retry_or_failover_on_400() {
                     auth_token_env: None,
                     api_key: None,
                     api_key_env: None,
+                    auth_token_file: None,
+                    auth_token_cmd: Vec::new(),
+                    api_key_file: None,
+                    api_key_cmd: Vec::new(),
+                    cmd_cache_ttl_secs: None,
+                    auth_token_secret: None,
+                    api_key_secret: None,
+                    auth_tokens: Vec::new(),
+                    auth_token_envs: Vec::new(),
+                    auth_token_secrets: Vec::new(),
+                    key_rotation: None,
                 },
                 tags: HashMap::new(),
                 supported_models: HashMap::new(),
                 model_mapping: HashMap::new(),
+                resolve: HashMap::new(),
+                ip_preference: None,
+                body_rules: Vec::new(),
             },
             UpstreamConfig {
                 base_url: format!("http://{}/v1", u2_addr),
@@ -225,10 +275,24 @@ async fn proxy_does_not_retry_or_failover_on_400() {
                     auth_token_env: None,
                     api_key: None,
                     api_key_env: None,
+                    auth_token_file: None,
+                    auth_token_cmd: Vec::new(),
+                    api_key_file: None,
+                    api_key_cmd: Vec::new(),
+                    cmd_cache_ttl_secs: None,
+                    auth_token_secret: None,
+                    api_key_secret: None,
+                    auth_tokens: Vec::new(),
+                    auth_token_envs: Vec::new(),
+                    auth_token_secrets: Vec::new(),
+                    key_rotation: None,
                 },
                 tags: HashMap::new(),
                 supported_models: HashMap::new(),
                 model_mapping: HashMap::new(),
+                resolve: HashMap::new(),
+                ip_preference: None,
+                body_rules: Vec::new(),
             },
         ],
         retry,
@@ -303,6 +367,7 @@ async fn proxy_skips_upstreams_that_do_not_support_model() {
         cloudflare_challenge_cooldown_secs: 0,
         cloudflare_timeout_cooldown_secs: 0,
         transport_cooldown_secs: 0,
+        key_cooldown_secs: 0,
     };
     let cfg = make_proxy_config(
         vec![
@@ -313,6 +378,17 @@ async fn proxy_skips_upstreams_that_do_not_support_model() {
                     auth_token_env: None,
                     api_key: None,
                     api_key_env: None,
+                    auth_token_file: None,
+                    auth_token_cmd: Vec::new(),
+                    api_key_file: None,
+                    api_key_cmd: Vec::new(),
+                    cmd_cache_ttl_secs: None,
+                    auth_token_secret: None,
+                    api_key_secret: None,
+                    auth_tokens: Vec::new(),
+                    auth_token_envs: Vec::new(),
+                    auth_token_secrets: Vec::new(),
+                    key_rotation: None,
                 },
                 tags: HashMap::new(),
                 supported_models: {
@@ -321,6 +397,9 @@ async fn proxy_skips_upstreams_that_do_not_support_model() {
                     m
                 },
                 model_mapping: HashMap::new(),
+                resolve: HashMap::new(),
+                ip_preference: None,
+                body_rules: Vec::new(),
             },
             UpstreamConfig {
                 base_url: format!("http://{}/v1", u2_addr),
@@ -329,6 +408,17 @@ async fn proxy_skips_upstreams_that_do_not_support_model() {
                     auth_token_env: None,
                     api_key: None,
                     api_key_env: None,
+                    auth_token_file: None,
+                    auth_token_cmd: Vec::new(),
+                    api_key_file: None,
+                    api_key_cmd: Vec::new(),
+                    cmd_cache_ttl_secs: None,
+                    auth_token_secret: None,
+                    api_key_secret: None,
+                    auth_tokens: Vec::new(),
+                    auth_token_envs: Vec::new(),
+                    auth_token_secrets: Vec::new(),
+                    key_rotation: None,
                 },
                 tags: HashMap::new(),
                 supported_models: {
@@ -337,6 +427,9 @@ async fn proxy_skips_upstreams_that_do_not_support_model() {
                     m
                 },
                 model_mapping: HashMap::new(),
+                resolve: HashMap::new(),
+                ip_preference: None,
+                body_rules: Vec::new(),
             },
         ],
         retry,
@@ -404,6 +497,7 @@ async fn proxy_applies_model_mapping_to_request_body() {
         cloudflare_challenge_cooldown_secs: 0,
         cloudflare_timeout_cooldown_secs: 0,
         transport_cooldown_secs: 0,
+        key_cooldown_secs: 0,
     };
     let cfg = make_proxy_config(
         vec![UpstreamConfig {
@@ -413,6 +507,17 @@ async fn proxy_applies_model_mapping_to_request_body() {
                 auth_token_env: None,
                 api_key: None,
                 api_key_env: None,
+                auth_token_file: None,
+                auth_token_cmd: Vec::new(),
+                api_key_file: None,
+                api_key_cmd: Vec::new(),
+                cmd_cache_ttl_secs: None,
+                auth_token_secret: None,
+                api_key_secret: None,
+                auth_tokens: Vec::new(),
+                auth_token_envs: Vec::new(),
+                auth_token_secrets: Vec::new(),
+                key_rotation: None,
             },
             tags: HashMap::new(),
             supported_models: {
@@ -425,6 +530,9 @@ async fn proxy_applies_model_mapping_to_request_body() {
                 m.insert("claude-*".to_string(), "anthropic/claude-*".to_string());
                 m
             },
+            resolve: HashMap::new(),
+            ip_preference: None,
+            body_rules: Vec::new(),
         }],
         retry,
     );
@@ -492,6 +600,7 @@ async fn proxy_falls_back_to_level_2_config_after_retryable_failure() {
         cloudflare_challenge_cooldown_secs: 0,
         cloudflare_timeout_cooldown_secs: 0,
         transport_cooldown_secs: 0,
+        key_cooldown_secs: 0,
     };
 
     let mut mgr = ServiceConfigManager {
@@ -505,6 +614,7 @@ async fn proxy_falls_back_to_level_2_config_after_retryable_failure() {
             alias: None,
             enabled: true,
             level: 1,
+            budget: Default::default(),
             upstreams: vec![UpstreamConfig {
                 base_url: format!("http://{}/v1", l1_addr),
                 auth: UpstreamAuth {
@@ -512,11 +622,26 @@ async fn proxy_falls_back_to_level_2_config_after_retryable_failure() {
                     auth_token_env: None,
                     api_key: None,
                     api_key_env: None,
+                    auth_token_file: None,
+                    auth_token_cmd: Vec::new(),
+                    api_key_file: None,
+                    api_key_cmd: Vec::new(),
+                    cmd_cache_ttl_secs: None,
+                    auth_token_secret: None,
+                    api_key_secret: None,
+                    auth_tokens: Vec::new(),
+                    auth_token_envs: Vec::new(),
+                    auth_token_secrets: Vec::new(),
+                    key_rotation: None,
                 },
                 tags: HashMap::new(),
                 supported_models: HashMap::new(),
                 model_mapping: HashMap::new(),
+                resolve: HashMap::new(),
+                ip_preference: None,
+                body_rules: Vec::new(),
             }],
+            body_rules: Vec::new(),
         },
     );
     mgr.configs.insert(
@@ -526,6 +651,7 @@ async fn proxy_falls_back_to_level_2_config_after_retryable_failure() {
             alias: None,
             enabled: true,
             level: 2,
+            budget: Default::default(),
             upstreams: vec![UpstreamConfig {
                 base_url: format!("http://{}/v1", l2_addr),
                 auth: UpstreamAuth {
@@ -533,11 +659,26 @@ async fn proxy_falls_back_to_level_2_config_after_retryable_failure() {
                     auth_token_env: None,
                     api_key: None,
                     api_key_env: None,
+                    auth_token_file: None,
+                    auth_token_cmd: Vec::new(),
+                    api_key_file: None,
+                    api_key_cmd: Vec::new(),
+                    cmd_cache_ttl_secs: None,
+                    auth_token_secret: None,
+                    api_key_secret: None,
+                    auth_tokens: Vec::new(),
+                    auth_token_envs: Vec::new(),
+                    auth_token_secrets: Vec::new(),
+                    key_rotation: None,
                 },
                 tags: HashMap::new(),
                 supported_models: HashMap::new(),
                 model_mapping: HashMap::new(),
+                resolve: HashMap::new(),
+                ip_preference: None,
+                body_rules: Vec::new(),
             }],
+            body_rules: Vec::new(),
         },
     );
 
@@ -549,6 +690,10 @@ async fn proxy_falls_back_to_level_2_config_after_retryable_failure() {
         notify: Default::default(),
         default_service: None,
         ui: UiConfig::default(),
+        server: Default::default(),
+        projects: Default::default(),
+        pricing: Default::default(),
+        usage: Default::default(),
     };
 
     let proxy = ProxyService::new(
@@ -576,3 +721,932 @@ async fn proxy_falls_back_to_level_2_config_after_retryable_failure() {
     l1_handle.abort();
     l2_handle.abort();
 }
+
+#[tokio::test]
+async fn proxy_uses_resolve_override_and_keeps_host_header() {
+    let upstream = axum::Router::new().route(
+        "/v1/responses",
+        post(move |headers: axum::http::HeaderMap| async move {
+            let host = headers
+                .get("host")
+                .and_then(|v| v.to_str().ok())
+                .unwrap_or_default()
+                .to_string();
+            (StatusCode::OK, Json(serde_json::json!({ "host": host })))
+        }),
+    );
+    let (u_addr, u_handle) = spawn_axum_server(upstream);
+
+    let retry = RetryConfig {
+        max_attempts: 1,
+        backoff_ms: 0,
+        backoff_max_ms: 0,
+        jitter_ms: 0,
+        on_status: "502".to_string(),
+        on_class: Vec::new(),
+        cloudflare_challenge_cooldown_secs: 0,
+        cloudflare_timeout_cooldown_secs: 0,
+        transport_cooldown_secs: 0,
+        key_cooldown_secs: 0,
+    };
+    // `pinned.invalid` never resolves via system DNS; the request only succeeds through the override.
+    let cfg = make_proxy_config(
+        vec![UpstreamConfig {
+            base_url: format!("http://pinned.invalid:{}/v1", u_addr.port()),
+            auth: UpstreamAuth::default(),
+            tags: HashMap::new(),
+            supported_models: HashMap::new(),
+            model_mapping: HashMap::new(),
+            resolve: {
+                let mut m = HashMap::new();
+                m.insert("pinned.invalid".to_string(), vec!["127.0.0.1".to_string()]);
+                m
+            },
+            ip_preference: Some(crate::config::IpPreference::Ipv4),
+            body_rules: Vec::new(),
+        }],
+        retry,
+    );
+
+    let proxy = ProxyService::new(
+        Client::new(),
+        Arc::new(cfg),
+        "codex",
+        Arc::new(std::sync::Mutex::new(HashMap::new())),
+    );
+    let app = crate::proxy::router(proxy);
+    let (proxy_addr, proxy_handle) = spawn_axum_server(app);
+
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!("http://{}/v1/responses", proxy_addr))
+        .header("content-type", "application/json")
+        .body(r#"{"model":"gpt-5","input":"hi"}"#)
+        .send()
+        .await
+        .expect("send");
+
+    assert_eq!(resp.status(), StatusCode::OK);
+    let body: serde_json::Value = resp.json().await.expect("json");
+    assert_eq!(
+        body["host"].as_str(),
+        Some(format!("pinned.invalid:{}", u_addr.port()).as_str())
+    );
+
+    proxy_handle.abort();
+    u_handle.abort();
+}
+
+#[tokio::test]
+async fn proxy_rotates_pooled_key_after_429() {
+    let seen_keys = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+
+    let seen = seen_keys.clone();
+    let upstream = axum::Router::new().route(
+        "/v1/responses",
+        post(move |headers: axum::http::HeaderMap| {
+            let seen = seen.clone();
+            async move {
+                let auth = headers
+                    .get("authorization")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                seen.lock().unwrap().push(auth.clone());
+                if auth == "Bearer sk-limited" {
+                    (
+                        StatusCode::TOO_MANY_REQUESTS,
+                        Json(serde_json::json!({ "error": "rate limited" })),
+                    )
+                } else {
+                    (StatusCode::OK, Json(serde_json::json!({ "ok": true })))
+                }
+            }
+        }),
+    );
+    let (u_addr, u_handle) = spawn_axum_server(upstream);
+
+    let retry = RetryConfig {
+        max_attempts: 2,
+        backoff_ms: 0,
+        backoff_max_ms: 0,
+        jitter_ms: 0,
+        on_status: "502".to_string(),
+        on_class: Vec::new(),
+        cloudflare_challenge_cooldown_secs: 0,
+        cloudflare_timeout_cooldown_secs: 0,
+        transport_cooldown_secs: 0,
+        key_cooldown_secs: 60,
+    };
+    let cfg = make_proxy_config(
+        vec![UpstreamConfig {
+            base_url: format!("http://{}/v1", u_addr),
+            auth: UpstreamAuth {
+                auth_tokens: vec!["sk-limited".to_string(), "sk-ok".to_string()],
+                ..Default::default()
+            },
+            tags: HashMap::new(),
+            supported_models: HashMap::new(),
+            model_mapping: HashMap::new(),
+            resolve: HashMap::new(),
+            ip_preference: None,
+            body_rules: Vec::new(),
+        }],
+        retry,
+    );
+
+    let proxy = ProxyService::new(
+        Client::new(),
+        Arc::new(cfg),
+        "codex",
+        Arc::new(std::sync::Mutex::new(HashMap::new())),
+    );
+    let app = crate::proxy::router(proxy);
+    let (proxy_addr, proxy_handle) = spawn_axum_server(app);
+
+    let client = reqwest::Client::new();
+    let send = || {
+        client
+            .post(format!("http://{}/v1/responses", proxy_addr))
+            .header("content-type", "application/json")
+            .body(r#"{"model":"gpt-5","input":"hi"}"#)
+            .send()
+    };
+
+    // First request: sk-limited gets 429, the proxy retries the same upstream with sk-ok.
+    let resp = send().await.expect("send");
+    assert_eq!(resp.status(), StatusCode::OK);
+    // Second request: sk-limited is cooling down, so it is skipped entirely.
+    let resp = send().await.expect("send");
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    assert_eq!(
+        *seen_keys.lock().unwrap(),
+        vec!["Bearer sk-limited", "Bearer sk-ok", "Bearer sk-ok"]
+    );
+
+    proxy_handle.abort();
+    u_handle.abort();
+}
+
+#[tokio::test]
+async fn proxy_rotates_pooled_keys_without_upstream_retries() {
+    let seen_keys = Arc::new(std::sync::Mutex::new(Vec::<String>::new()));
+
+    let seen = seen_keys.clone();
+    let upstream = axum::Router::new().route(
+        "/v1/responses",
+        post(move |headers: axum::http::HeaderMap| {
+            let seen = seen.clone();
+            async move {
+                let auth = headers
+                    .get("authorization")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                seen.lock().unwrap().push(auth.clone());
+                if auth.starts_with("Bearer sk-limited") {
+                    (
+                        StatusCode::TOO_MANY_REQUESTS,
+                        Json(serde_json::json!({ "error": "rate limited" })),
+                    )
+                } else {
+                    (StatusCode::OK, Json(serde_json::json!({ "ok": true })))
+                }
+            }
+        }),
+    );
+    let (u_addr, u_handle) = spawn_axum_server(upstream);
+
+    // No upstream retries at all: only the pool itself may move on to the next key.
+    let retry = RetryConfig {
+        max_attempts: 1,
+        backoff_ms: 0,
+        backoff_max_ms: 0,
+        jitter_ms: 0,
+        on_status: "502".to_string(),
+        on_class: Vec::new(),
+        cloudflare_challenge_cooldown_secs: 0,
+        cloudflare_timeout_cooldown_secs: 0,
+        transport_cooldown_secs: 0,
+        key_cooldown_secs: 60,
+    };
+    let cfg = make_proxy_config(
+        vec![UpstreamConfig {
+            base_url: format!("http://{}/v1", u_addr),
+            auth: UpstreamAuth {
+                auth_tokens: vec![
+                    "sk-limited-1".to_string(),
+                    "sk-limited-2".to_string(),
+                    "sk-ok".to_string(),
+                ],
+                ..Default::default()
+            },
+            tags: HashMap::new(),
+            supported_models: HashMap::new(),
+            model_mapping: HashMap::new(),
+            resolve: HashMap::new(),
+            ip_preference: None,
+            body_rules: Vec::new(),
+        }],
+        retry,
+    );
+
+    let proxy = ProxyService::new(
+        Client::new(),
+        Arc::new(cfg),
+        "codex",
+        Arc::new(std::sync::Mutex::new(HashMap::new())),
+    );
+    let app = crate::proxy::router(proxy);
+    let (proxy_addr, proxy_handle) = spawn_axum_server(app);
+
+    let resp = reqwest::Client::new()
+        .post(format!("http://{}/v1/responses", proxy_addr))
+        .header("content-type", "application/json")
+        .body(r#"{"model":"gpt-5","input":"hi"}"#)
+        .send()
+        .await
+        .expect("send");
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        *seen_keys.lock().unwrap(),
+        vec!["Bearer sk-limited-1", "Bearer sk-limited-2", "Bearer sk-ok"]
+    );
+
+    proxy_handle.abort();
+    u_handle.abort();
+}
+
+#[tokio::test]
+async fn proxy_records_route_of_requests_blocked_by_filter() {
+    let upstream_hits = Arc::new(AtomicUsize::new(0));
+    let hits = upstream_hits.clone();
+    let upstream = axum::Router::new().route(
+        "/v1/responses",
+        post(move || {
+            let hits = hits.clone();
+            async move {
+                hits.fetch_add(1, Ordering::SeqCst);
+                (StatusCode::OK, Json(serde_json::json!({ "ok": true })))
+            }
+        }),
+    );
+    let (u_addr, u_handle) = spawn_axum_server(upstream);
+
+    let dir = std::env::temp_dir().join(format!("codex-helper-block-{}", uuid::Uuid::new_v4()));
+    std::fs::create_dir_all(&dir).expect("dir");
+    let filter_path = dir.join("filter.json");
+    std::fs::write(
+        &filter_path,
+        r#"[{ "op": "block", "id": "prod-db", "source": "prod-db.internal" }]"#,
+    )
+    .expect("write filter");
+
+    let retry = RetryConfig {
+        max_attempts: 1,
+        backoff_ms: 0,
+        backoff_max_ms: 0,
+        jitter_ms: 0,
+        on_status: "502".to_string(),
+        on_class: Vec::new(),
+        cloudflare_challenge_cooldown_secs: 0,
+        cloudflare_timeout_cooldown_secs: 0,
+        transport_cooldown_secs: 0,
+        key_cooldown_secs: 0,
+    };
+    let cfg = make_proxy_config(
+        vec![UpstreamConfig {
+            base_url: format!("http://{}/v1", u_addr),
+            auth: UpstreamAuth::default(),
+            tags: HashMap::from([("provider_id".to_string(), "relay".to_string())]),
+            supported_models: HashMap::new(),
+            model_mapping: HashMap::new(),
+            resolve: HashMap::new(),
+            ip_preference: None,
+            body_rules: Vec::new(),
+        }],
+        retry,
+    );
+
+    let mut proxy = ProxyService::new(
+        Client::new(),
+        Arc::new(cfg),
+        "codex",
+        Arc::new(std::sync::Mutex::new(HashMap::new())),
+    );
+    proxy.filter = crate::filter::RequestFilter::from_path(filter_path);
+    let state = proxy.state_handle();
+    let app = crate::proxy::router(proxy);
+    let (proxy_addr, proxy_handle) = spawn_axum_server(app);
+
+    let resp = reqwest::Client::new()
+        .post(format!("http://{}/v1/responses", proxy_addr))
+        .header("content-type", "application/json")
+        .body(r#"{"model":"gpt-5","input":"connect to prod-db.internal"}"#)
+        .send()
+        .await
+        .expect("send");
+    assert_eq!(resp.status(), StatusCode::FORBIDDEN);
+    assert_eq!(upstream_hits.load(Ordering::SeqCst), 0);
+
+    let finished = state.list_recent_finished(10).await;
+    assert_eq!(finished.len(), 1);
+    assert_eq!(finished[0].config_name.as_deref(), Some("test"));
+    assert_eq!(finished[0].provider_id.as_deref(), Some("relay"));
+
+    proxy_handle.abort();
+    u_handle.abort();
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn auth_resolution_reports_file_and_missing_sources() {
+    let dir = std::env::temp_dir().join(format!("codex-helper-auth-src-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).expect("mkdir");
+    let token_path = dir.join("token");
+    std::fs::write(&token_path, "sk-from-file\n").expect("write token");
+    let token_path = token_path.to_string_lossy().to_string();
+
+    let auth = UpstreamAuth {
+        auth_token_file: Some(token_path.clone()),
+        ..Default::default()
+    };
+    let (token, src) = super::resolve_auth_token_with_source("codex", &auth, false).await;
+    assert_eq!(token.as_deref(), Some("sk-from-file"));
+    assert_eq!(src, format!("file:{token_path}"));
+
+    let missing = dir.join("missing").to_string_lossy().to_string();
+    let auth = UpstreamAuth {
+        api_key_file: Some(missing.clone()),
+        ..Default::default()
+    };
+    let (key, src) = super::resolve_api_key_with_source("codex", &auth, true).await;
+    assert!(key.is_none());
+    assert_eq!(src, format!("client_passthrough (missing_file:{missing})"));
+
+    let _ = std::fs::remove_dir_all(&dir);
+}
+
+#[tokio::test]
+async fn proxy_requires_client_token_and_does_not_forward_it() {
+    let seen_headers = Arc::new(std::sync::Mutex::new(Vec::<(String, bool)>::new()));
+
+    let seen = seen_headers.clone();
+    let upstream = axum::Router::new().route(
+        "/v1/responses",
+        post(move |headers: axum::http::HeaderMap| {
+            let seen = seen.clone();
+            async move {
+                let auth = headers
+                    .get("authorization")
+                    .and_then(|v| v.to_str().ok())
+                    .unwrap_or_default()
+                    .to_string();
+                let has_client_token = headers.contains_key(crate::proxy::CLIENT_TOKEN_HEADER);
+                seen.lock().unwrap().push((auth, has_client_token));
+                (StatusCode::OK, Json(serde_json::json!({ "ok": true })))
+            }
+        }),
+    );
+    let (u_addr, u_handle) = spawn_axum_server(upstream);
+
+    let mut cfg = make_proxy_config(
+        vec![UpstreamConfig {
+            base_url: format!("http://{}/v1", u_addr),
+            auth: UpstreamAuth {
+                auth_token: Some("sk-upstream".to_string()),
+                ..Default::default()
+            },
+            tags: HashMap::new(),
+            supported_models: HashMap::new(),
+            model_mapping: HashMap::new(),
+            resolve: HashMap::new(),
+            ip_preference: None,
+            body_rules: Vec::new(),
+        }],
+        RetryConfig::default(),
+    );
+    cfg.server.client_token = Some("team-token".to_string());
+
+    let proxy = ProxyService::new(
+        Client::new(),
+        Arc::new(cfg),
+        "codex",
+        Arc::new(std::sync::Mutex::new(HashMap::new())),
+    );
+    let app = crate::proxy::router(proxy);
+    let (proxy_addr, proxy_handle) = spawn_axum_server(app);
+
+    let client = reqwest::Client::new();
+    let url = format!("http://{}/v1/responses", proxy_addr);
+    let body = r#"{"model":"gpt-5","input":"hi"}"#;
+
+    let resp = client
+        .post(&url)
+        .header("content-type", "application/json")
+        .body(body)
+        .send()
+        .await
+        .expect("send");
+    assert_eq!(resp.status(), StatusCode::UNAUTHORIZED);
+
+    let resp = client
+        .post(&url)
+        .header("content-type", "application/json")
+        .header(crate::proxy::CLIENT_TOKEN_HEADER, "team-token")
+        .body(body)
+        .send()
+        .await
+        .expect("send");
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let resp = client
+        .post(&url)
+        .header("content-type", "application/json")
+        .bearer_auth("team-token")
+        .body(body)
+        .send()
+        .await
+        .expect("send");
+    assert_eq!(resp.status(), StatusCode::OK);
+
+    let seen = seen_headers.lock().unwrap().clone();
+    assert_eq!(
+        seen,
+        vec![
+            ("Bearer sk-upstream".to_string(), false),
+            ("Bearer sk-upstream".to_string(), false),
+        ]
+    );
+
+    proxy_handle.abort();
+    u_handle.abort();
+}
+
+#[tokio::test]
+async fn proxy_logs_client_key_limited_to_missing_config() {
+    let hits = Arc::new(AtomicUsize::new(0));
+    let h = hits.clone();
+    let upstream = axum::Router::new().route(
+        "/v1/responses",
+        post(move || {
+            let h = h.clone();
+            async move {
+                h.fetch_add(1, Ordering::SeqCst);
+                (StatusCode::OK, Json(serde_json::json!({ "ok": true })))
+            }
+        }),
+    );
+    let (u_addr, u_handle) = spawn_axum_server(upstream);
+
+    let cfg = make_proxy_config(
+        vec![UpstreamConfig {
+            base_url: format!("http://{}/v1", u_addr),
+            auth: UpstreamAuth::default(),
+            tags: HashMap::new(),
+            supported_models: HashMap::new(),
+            model_mapping: HashMap::new(),
+            resolve: HashMap::new(),
+            ip_preference: None,
+            body_rules: Vec::new(),
+        }],
+        RetryConfig::default(),
+    );
+    let proxy = ProxyService::new(
+        Client::new(),
+        Arc::new(cfg),
+        "codex",
+        Arc::new(std::sync::Mutex::new(HashMap::new())),
+    );
+
+    let label = format!("key-{}", uuid::Uuid::new_v4().simple());
+    let since_ms = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0);
+    let mut req = axum::http::Request::builder()
+        .method("POST")
+        .uri("/v1/responses")
+        .header("content-type", "application/json")
+        .body(axum::body::Body::from(r#"{"model":"gpt-5","input":"hi"}"#))
+        .expect("request");
+    req.extensions_mut()
+        .insert(crate::client_keys::ClientIdentity {
+            label: label.clone(),
+            allowed_configs: vec!["does-not-exist".to_string()],
+            daily_token_budget: None,
+        });
+
+    let (status, message) = crate::proxy::handle_proxy(proxy.clone(), req)
+        .await
+        .expect_err("denied");
+    assert_eq!(status, StatusCode::FORBIDDEN);
+    assert!(message.contains(&label), "{message}");
+    assert_eq!(hits.load(Ordering::SeqCst), 0);
+
+    let logged = crate::logging::request_log_files(Some(since_ms))
+        .into_iter()
+        .filter_map(|p| std::fs::read_to_string(p).ok())
+        .flat_map(|text| text.lines().map(str::to_string).collect::<Vec<_>>())
+        .filter_map(|line| serde_json::from_str::<serde_json::Value>(&line).ok())
+        .find(|v| v["client"] == label.as_str())
+        .expect("denied request is logged");
+    assert_eq!(logged["status_code"], 403);
+    assert!(proxy.state.list_active_requests().await.is_empty());
+
+    u_handle.abort();
+}
+
+#[tokio::test]
+async fn proxy_spools_large_body_for_retry_and_enforces_limit() {
+    let seen_lens = Arc::new(std::sync::Mutex::new(Vec::<usize>::new()));
+
+    let make_upstream = |status: StatusCode| {
+        let seen = seen_lens.clone();
+        axum::Router::new().route(
+            "/v1/responses",
+            post(move |body: axum::body::Body| {
+                let seen = seen.clone();
+                async move {
+                    let bytes = axum::body::to_bytes(body, usize::MAX)
+                        .await
+                        .expect("upstream body");
+                    seen.lock().unwrap().push(bytes.len());
+                    (
+                        status,
+                        Json(serde_json::json!({ "ok": status.is_success() })),
+                    )
+                }
+            }),
+        )
+    };
+    let (u1_addr, u1_handle) = spawn_axum_server(make_upstream(StatusCode::BAD_GATEWAY));
+    let (u2_addr, u2_handle) = spawn_axum_server(make_upstream(StatusCode::OK));
+
+    let upstream = |addr: std::net::SocketAddr| UpstreamConfig {
+        base_url: format!("http://{}/v1", addr),
+        auth: UpstreamAuth::default(),
+        tags: HashMap::new(),
+        supported_models: HashMap::new(),
+        model_mapping: HashMap::new(),
+        resolve: HashMap::new(),
+        ip_preference: None,
+        body_rules: Vec::new(),
+    };
+    let retry = RetryConfig {
+        max_attempts: 2,
+        backoff_ms: 0,
+        backoff_max_ms: 0,
+        jitter_ms: 0,
+        on_status: "502".to_string(),
+        ..Default::default()
+    };
+    let cfg = make_proxy_config(vec![upstream(u1_addr), upstream(u2_addr)], retry);
+    let mut limited_cfg = cfg.clone();
+    limited_cfg.server.max_request_body_bytes = Some(2 * 1024 * 1024);
+
+    let spawn_proxy = |cfg: ProxyConfig| {
+        let proxy = ProxyService::new(
+            Client::new(),
+            Arc::new(cfg),
+            "codex",
+            Arc::new(std::sync::Mutex::new(HashMap::new())),
+        );
+        spawn_axum_server(crate::proxy::router(proxy))
+    };
+    let (proxy_addr, proxy_handle) = spawn_proxy(cfg);
+    let (limited_addr, limited_handle) = spawn_proxy(limited_cfg);
+
+    // Larger than the in-memory threshold, so the proxy spools it and re-sends it on failover.
+    let body = format!(
+        r#"{{"model":"gpt-5","input":"{}"}}"#,
+        "x".repeat(3 * 1024 * 1024)
+    );
+    let client = reqwest::Client::new();
+    let resp = client
+        .post(format!("http://{}/v1/responses", proxy_addr))
+        .header("content-type", "application/json")
+        .body(body.clone())
+        .send()
+        .await
+        .expect("send");
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(*seen_lens.lock().unwrap(), vec![body.len(), body.len()]);
+
+    let resp = client
+        .post(format!("http://{}/v1/responses", limited_addr))
+        .header("content-type", "application/json")
+        .body(body)
+        .send()
+        .await
+        .expect("send");
+    assert_eq!(resp.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    assert_eq!(
+        seen_lens.lock().unwrap().len(),
+        2,
+        "oversized body never reaches upstream"
+    );
+
+    proxy_handle.abort();
+    limited_handle.abort();
+    u1_handle.abort();
+    u2_handle.abort();
+}
+
+#[tokio::test]
+async fn proxy_applies_config_and_upstream_body_rules() {
+    let seen_bodies = Arc::new(std::sync::Mutex::new(Vec::<serde_json::Value>::new()));
+
+    let seen = seen_bodies.clone();
+    let upstream = axum::Router::new().route(
+        "/v1/responses",
+        post(move |Json(body): Json<serde_json::Value>| {
+            let seen = seen.clone();
+            async move {
+                seen.lock().unwrap().push(body);
+                (StatusCode::OK, Json(serde_json::json!({ "ok": true })))
+            }
+        }),
+    );
+    let (u_addr, u_handle) = spawn_axum_server(upstream);
+
+    let rules = |src: &str| -> Vec<crate::body_rules::BodyRule> {
+        serde_json::from_str(src).expect("rules")
+    };
+    let mut cfg = make_proxy_config(
+        vec![UpstreamConfig {
+            base_url: format!("http://{}/v1", u_addr),
+            auth: UpstreamAuth::default(),
+            tags: HashMap::new(),
+            supported_models: HashMap::new(),
+            model_mapping: HashMap::new(),
+            resolve: HashMap::new(),
+            ip_preference: None,
+            body_rules: rules(
+                r#"[{"op":"remove","path":"/parallel_tool_calls"},
+                    {"op":"set","path":"/store","value":false}]"#,
+            ),
+        }],
+        RetryConfig::default(),
+    );
+    if let Some(svc) = cfg.codex.configs.get_mut("test") {
+        svc.body_rules = rules(r#"[{"op":"default","path":"/service_tier","value":"flex"}]"#);
+    }
+
+    let proxy = ProxyService::new(
+        Client::new(),
+        Arc::new(cfg),
+        "codex",
+        Arc::new(std::sync::Mutex::new(HashMap::new())),
+    );
+    let (proxy_addr, proxy_handle) = spawn_axum_server(crate::proxy::router(proxy));
+
+    let resp = reqwest::Client::new()
+        .post(format!("http://{}/v1/responses", proxy_addr))
+        .header("content-type", "application/json")
+        .body(r#"{"model":"gpt-5","input":"hi","store":true,"parallel_tool_calls":true}"#)
+        .send()
+        .await
+        .expect("send");
+    assert_eq!(resp.status(), StatusCode::OK);
+    assert_eq!(
+        *seen_bodies.lock().unwrap(),
+        vec![serde_json::json!({
+            "model": "gpt-5",
+            "input": "hi",
+            "store": false,
+            "service_tier": "flex"
+        })]
+    );
+
+    proxy_handle.abort();
+    u_handle.abort();
+}
+
+#[tokio::test]
+async fn proxy_skips_configs_over_their_daily_token_budget() {
+    let spawn_upstream = |hits: Arc<AtomicUsize>| {
+        spawn_axum_server(axum::Router::new().route(
+            "/v1/responses",
+            post(move || async move {
+                hits.fetch_add(1, Ordering::SeqCst);
+                (
+                    StatusCode::OK,
+                    Json(serde_json::json!({
+                        "ok": true,
+                        "usage": { "input_tokens": 40, "output_tokens": 10, "total_tokens": 50 }
+                    })),
+                )
+            }),
+        ))
+    };
+    let paid_hits = Arc::new(AtomicUsize::new(0));
+    let fallback_hits = Arc::new(AtomicUsize::new(0));
+    let (paid_addr, paid_handle) = spawn_upstream(paid_hits.clone());
+    let (fallback_addr, fallback_handle) = spawn_upstream(fallback_hits.clone());
+
+    let upstream = |addr: std::net::SocketAddr| UpstreamConfig {
+        base_url: format!("http://{}/v1", addr),
+        auth: UpstreamAuth::default(),
+        tags: HashMap::new(),
+        supported_models: HashMap::new(),
+        model_mapping: HashMap::new(),
+        resolve: HashMap::new(),
+        ip_preference: None,
+        body_rules: Vec::new(),
+    };
+    let budget = crate::config::BudgetConfig {
+        daily_tokens: Some(10),
+        monthly_cost_usd: None,
+    };
+    // Unique names: the proxy replays usage from the shared request log on startup.
+    let suffix = uuid::Uuid::new_v4().simple().to_string();
+    let mut cfg = make_proxy_config(Vec::new(), RetryConfig::default());
+    cfg.codex.configs.clear();
+    for (name, level, addr) in [
+        (format!("paid-{suffix}"), 1, paid_addr),
+        (format!("fallback-{suffix}"), 2, fallback_addr),
+    ] {
+        cfg.codex.configs.insert(
+            name.clone(),
+            ServiceConfig {
+                name,
+                alias: None,
+                enabled: true,
+                level,
+                budget: budget.clone(),
+                upstreams: vec![upstream(addr)],
+                body_rules: Vec::new(),
+            },
+        );
+    }
+    cfg.codex.active = Some(format!("paid-{suffix}"));
+
+    let proxy = ProxyService::new(
+        Client::new(),
+        Arc::new(cfg),
+        "codex",
+        Arc::new(std::sync::Mutex::new(HashMap::new())),
+    );
+    let (proxy_addr, proxy_handle) = spawn_axum_server(crate::proxy::router(proxy));
+
+    let client = reqwest::Client::new();
+    let send = || async {
+        client
+            .post(format!("http://{}/v1/responses", proxy_addr))
+            .header("content-type", "application/json")
+            .body(r#"{"model":"gpt","input":"hi"}"#)
+            .send()
+            .await
+            .expect("send")
+    };
+
+    // The active config spends its budget, then automatic routing moves on to level 2.
+    assert_eq!(send().await.status(), StatusCode::OK);
+    assert_eq!(send().await.status(), StatusCode::OK);
+    assert_eq!(paid_hits.load(Ordering::SeqCst), 1);
+    assert_eq!(fallback_hits.load(Ordering::SeqCst), 1);
+
+    let resp = send().await;
+    assert_eq!(resp.status(), StatusCode::TOO_MANY_REQUESTS);
+    assert_eq!(paid_hits.load(Ordering::SeqCst), 1);
+    assert_eq!(fallback_hits.load(Ordering::SeqCst), 1);
+
+    let budgets = client
+        .get(format!(
+            "http://{}/__codex_helper/status/budgets",
+            proxy_addr
+        ))
+        .send()
+        .await
+        .expect("send")
+        .json::<Vec<crate::budget::BudgetStatus>>()
+        .await
+        .expect("json");
+    assert_eq!(budgets.len(), 2);
+    assert!(
+        budgets
+            .iter()
+            .all(|b| b.exceeded.as_deref() == Some("daily_tokens"))
+    );
+
+    proxy_handle.abort();
+    paid_handle.abort();
+    fallback_handle.abort();
+}
+
+/// Three configs (`route-a` active, level 1; `route-b` level 2; `route-c` level 3) on a dummy upstream.
+fn make_routing_proxy() -> (ProxyService, Arc<ProxyConfig>) {
+    let mut cfg = make_proxy_config(Vec::new(), RetryConfig::default());
+    cfg.codex = ServiceConfigManager {
+        active: Some("route-a".to_string()),
+        ..Default::default()
+    };
+    for (name, level) in [("route-a", 1), ("route-b", 2), ("route-c", 3)] {
+        cfg.codex.configs.insert(
+            name.to_string(),
+            ServiceConfig {
+                name: name.to_string(),
+                alias: None,
+                enabled: true,
+                level,
+                budget: Default::default(),
+                upstreams: vec![UpstreamConfig {
+                    base_url: "http://127.0.0.1:9/v1".to_string(),
+                    auth: UpstreamAuth::default(),
+                    tags: HashMap::new(),
+                    supported_models: HashMap::new(),
+                    model_mapping: HashMap::new(),
+                    resolve: HashMap::new(),
+                    ip_preference: None,
+                    body_rules: Vec::new(),
+                }],
+                body_rules: Vec::new(),
+            },
+        );
+    }
+    let cfg = Arc::new(cfg);
+    let proxy = ProxyService::new(
+        Client::new(),
+        cfg.clone(),
+        "codex",
+        Arc::new(std::sync::Mutex::new(HashMap::new())),
+    );
+    (proxy, cfg)
+}
+
+fn lb_names(lbs: &[crate::lb::LoadBalancer]) -> Vec<String> {
+    lbs.iter().map(|lb| lb.service.name.clone()).collect()
+}
+
+#[tokio::test]
+async fn proxy_pins_session_over_project_over_global() {
+    let (proxy, cfg) = make_routing_proxy();
+    let project = crate::projects::ProjectSettings {
+        config: Some("route-b".to_string()),
+        ..Default::default()
+    };
+    proxy
+        .state
+        .set_global_config_override("route-c".to_string())
+        .await;
+    proxy
+        .state
+        .set_session_config_override("s-pinned".to_string(), "route-a".to_string(), 0)
+        .await;
+
+    let lbs = proxy
+        .lbs_for_request(&cfg, Some("s-pinned"), &[], Some(&project))
+        .await;
+    assert_eq!(lb_names(&lbs), vec!["route-a"]);
+    let lbs = proxy
+        .lbs_for_request(&cfg, Some("s-other"), &[], Some(&project))
+        .await;
+    assert_eq!(lb_names(&lbs), vec!["route-b"]);
+    let lbs = proxy
+        .lbs_for_request(&cfg, Some("s-other"), &[], None)
+        .await;
+    assert_eq!(lb_names(&lbs), vec!["route-c"]);
+}
+
+#[tokio::test]
+async fn proxy_prefers_project_levels_over_tui_levels() {
+    let (proxy, cfg) = make_routing_proxy();
+    // The TUI demotes route-a below route-b ...
+    proxy
+        .state
+        .set_config_level_override("codex", "route-a".to_string(), 3, 0)
+        .await;
+    let lbs = proxy.lbs_for_request(&cfg, None, &[], None).await;
+    assert_eq!(lb_names(&lbs), vec!["route-b", "route-a", "route-c"]);
+
+    // ... but a project rule that puts route-c first and route-a back on level 1 wins.
+    let project = crate::projects::ProjectSettings {
+        levels: [("route-a".to_string(), 1), ("route-c".to_string(), 1)]
+            .into_iter()
+            .collect(),
+        ..Default::default()
+    };
+    let lbs = proxy.lbs_for_request(&cfg, None, &[], Some(&project)).await;
+    assert_eq!(lb_names(&lbs), vec!["route-a", "route-c", "route-b"]);
+}
+
+#[tokio::test]
+async fn proxy_ignores_project_config_outside_client_key_allowlist() {
+    let (proxy, cfg) = make_routing_proxy();
+    let project = crate::projects::ProjectSettings {
+        config: Some("route-b".to_string()),
+        ..Default::default()
+    };
+    let allowed = vec!["route-a".to_string(), "route-c".to_string()];
+
+    let lbs = proxy
+        .lbs_for_request(&cfg, None, &allowed, Some(&project))
+        .await;
+    assert_eq!(lb_names(&lbs), vec!["route-a", "route-c"]);
+
+    // Without the allowlist the same project rule pins route-b.
+    let lbs = proxy.lbs_for_request(&cfg, None, &[], Some(&project)).await;
+    assert_eq!(lb_names(&lbs), vec!["route-b"]);
+}
@@ -0,0 +1,378 @@
+use std::collections::BTreeMap;
+use std::num::NonZeroU32;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::SystemTime;
+
+use anyhow::{Context, Result, anyhow, bail};
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD as B64;
+use ring::aead::{Aad, CHACHA20_POLY1305, LessSafeKey, NONCE_LEN, Nonce, UnboundKey};
+use ring::pbkdf2;
+use ring::rand::{SecureRandom, SystemRandom};
+use serde::{Deserialize, Serialize};
+use tracing::warn;
+
+use crate::config::proxy_home_dir;
+
+pub const PASSPHRASE_ENV: &str = "CODEX_HELPER_SECRETS_PASSPHRASE";
+
+const STORE_FILE: &str = "secrets.enc";
+const MACHINE_KEY_FILE: &str = "secrets.key";
+const STORE_VERSION: u32 = 1;
+const KEY_LEN: usize = 32;
+const SALT_LEN: usize = 16;
+const PBKDF2_ITERATIONS: u32 = 200_000;
+/// Binds the ciphertext to this file format so it cannot be replayed elsewhere.
+const AAD: &[u8] = b"codex-helper-secrets-v1";
+
+/// 加密密钥的来源：用户口令（PBKDF2 派生）或本机随机生成的 key 文件。
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum KeySource {
+    Passphrase,
+    Machine,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Envelope {
+    version: u32,
+    key_source: KeySource,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    salt: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    iterations: Option<u32>,
+    nonce: String,
+    ciphertext: String,
+}
+
+/// Encrypted name → value store at `~/.codex-helper/secrets.enc`.
+///
+/// Without a passphrase the key lives next to the store in `secrets.key` (mode 0600), which keeps
+/// secrets out of config files and backups of them, but not away from someone who can read the
+/// whole directory. Set `CODEX_HELPER_SECRETS_PASSPHRASE` when creating the store for the stronger mode.
+pub struct SecretStore {
+    dir: PathBuf,
+}
+
+impl SecretStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn default_location() -> Self {
+        Self::new(proxy_home_dir())
+    }
+
+    pub fn path(&self) -> PathBuf {
+        self.dir.join(STORE_FILE)
+    }
+
+    fn machine_key_path(&self) -> PathBuf {
+        self.dir.join(MACHINE_KEY_FILE)
+    }
+
+    fn read_envelope(&self) -> Result<Option<Envelope>> {
+        let path = self.path();
+        if !path.exists() {
+            return Ok(None);
+        }
+        let bytes = std::fs::read(&path).with_context(|| format!("read {:?}", path))?;
+        let env: Envelope =
+            serde_json::from_slice(&bytes).with_context(|| format!("parse {:?}", path))?;
+        if env.version != STORE_VERSION {
+            bail!(
+                "unsupported secret store version {} in {:?}",
+                env.version,
+                path
+            );
+        }
+        Ok(Some(env))
+    }
+
+    /// Which key source the existing store uses, if any.
+    pub fn key_source(&self) -> Result<Option<KeySource>> {
+        Ok(self.read_envelope()?.map(|e| e.key_source))
+    }
+
+    /// Decrypts all entries; a missing store is an empty map.
+    pub fn load(&self, passphrase: Option<&str>) -> Result<BTreeMap<String, String>> {
+        let Some(env) = self.read_envelope()? else {
+            return Ok(BTreeMap::new());
+        };
+        let key = match env.key_source {
+            KeySource::Machine => self.read_machine_key()?,
+            KeySource::Passphrase => {
+                let passphrase = passphrase.ok_or_else(|| {
+                    anyhow!("secret store is passphrase-protected; set {PASSPHRASE_ENV}")
+                })?;
+                let salt = decode_field(env.salt.as_deref(), "salt")?;
+                let iterations = env.iterations.unwrap_or(PBKDF2_ITERATIONS);
+                derive_key(passphrase, &salt, iterations)?
+            }
+        };
+        let nonce = decode_field(Some(&env.nonce), "nonce")?;
+        let mut buf = decode_field(Some(&env.ciphertext), "ciphertext")?;
+        let plain = open(&key, &nonce, &mut buf)?;
+        serde_json::from_slice(plain).context("decode secret store contents")
+    }
+
+    /// Encrypts and writes all entries, keeping the key source of an existing store.
+    ///
+    /// A new store uses passphrase mode when `passphrase` is set and a machine key otherwise.
+    pub fn save(&self, entries: &BTreeMap<String, String>, passphrase: Option<&str>) -> Result<()> {
+        let rng = SystemRandom::new();
+        let source = match self.key_source()? {
+            Some(source) => source,
+            None if passphrase.is_some() => KeySource::Passphrase,
+            None => KeySource::Machine,
+        };
+        let (key, salt, iterations) = match source {
+            KeySource::Machine => (self.load_or_create_machine_key(&rng)?, None, None),
+            KeySource::Passphrase => {
+                let passphrase = passphrase.ok_or_else(|| {
+                    anyhow!("secret store is passphrase-protected; set {PASSPHRASE_ENV}")
+                })?;
+                let salt = random_bytes::<SALT_LEN>(&rng)?;
+                let key = derive_key(passphrase, &salt, PBKDF2_ITERATIONS)?;
+                (key, Some(B64.encode(salt)), Some(PBKDF2_ITERATIONS))
+            }
+        };
+        let nonce = random_bytes::<NONCE_LEN>(&rng)?;
+        let mut buf = serde_json::to_vec(entries)?;
+        seal(&key, &nonce, &mut buf)?;
+
+        let env = Envelope {
+            version: STORE_VERSION,
+            key_source: source,
+            salt,
+            iterations,
+            nonce: B64.encode(nonce),
+            ciphertext: B64.encode(&buf),
+        };
+        write_private(&self.path(), &serde_json::to_vec_pretty(&env)?)
+    }
+
+    fn read_machine_key(&self) -> Result<[u8; KEY_LEN]> {
+        let path = self.machine_key_path();
+        let text = std::fs::read_to_string(&path)
+            .with_context(|| format!("read machine key {:?}", path))?;
+        let bytes = B64
+            .decode(text.trim())
+            .with_context(|| format!("decode machine key {:?}", path))?;
+        bytes
+            .try_into()
+            .map_err(|_| anyhow!("machine key {:?} has the wrong length", path))
+    }
+
+    fn load_or_create_machine_key(&self, rng: &SystemRandom) -> Result<[u8; KEY_LEN]> {
+        if self.machine_key_path().exists() {
+            return self.read_machine_key();
+        }
+        let key = random_bytes::<KEY_LEN>(rng)?;
+        write_private(&self.machine_key_path(), B64.encode(key).as_bytes())?;
+        Ok(key)
+    }
+}
+
+pub fn passphrase_from_env() -> Option<String> {
+    std::env::var(PASSPHRASE_ENV).ok().filter(|v| !v.is_empty())
+}
+
+fn decode_field(value: Option<&str>, name: &str) -> Result<Vec<u8>> {
+    let value = value.ok_or_else(|| anyhow!("secret store is missing '{name}'"))?;
+    B64.decode(value)
+        .with_context(|| format!("secret store has an invalid '{name}'"))
+}
+
+fn random_bytes<const N: usize>(rng: &SystemRandom) -> Result<[u8; N]> {
+    let mut out = [0u8; N];
+    rng.fill(&mut out)
+        .map_err(|_| anyhow!("system random generator failed"))?;
+    Ok(out)
+}
+
+fn derive_key(passphrase: &str, salt: &[u8], iterations: u32) -> Result<[u8; KEY_LEN]> {
+    let iterations =
+        NonZeroU32::new(iterations).ok_or_else(|| anyhow!("invalid PBKDF2 iteration count"))?;
+    let mut key = [0u8; KEY_LEN];
+    pbkdf2::derive(
+        pbkdf2::PBKDF2_HMAC_SHA256,
+        iterations,
+        salt,
+        passphrase.as_bytes(),
+        &mut key,
+    );
+    Ok(key)
+}
+
+fn aead_key(key: &[u8; KEY_LEN]) -> Result<LessSafeKey> {
+    let unbound =
+        UnboundKey::new(&CHACHA20_POLY1305, key).map_err(|_| anyhow!("invalid secret key"))?;
+    Ok(LessSafeKey::new(unbound))
+}
+
+fn seal(key: &[u8; KEY_LEN], nonce: &[u8; NONCE_LEN], buf: &mut Vec<u8>) -> Result<()> {
+    aead_key(key)?
+        .seal_in_place_append_tag(Nonce::assume_unique_for_key(*nonce), Aad::from(AAD), buf)
+        .map_err(|_| anyhow!("failed to encrypt secret store"))
+}
+
+fn open<'a>(key: &[u8; KEY_LEN], nonce: &[u8], buf: &'a mut [u8]) -> Result<&'a [u8]> {
+    let nonce = Nonce::try_assume_unique_for_key(nonce)
+        .map_err(|_| anyhow!("secret store has an invalid nonce"))?;
+    let plain = aead_key(key)?
+        .open_in_place(nonce, Aad::from(AAD), buf)
+        .map_err(|_| anyhow!("failed to decrypt secret store (wrong passphrase or key?)"))?;
+    Ok(plain)
+}
+
+/// Atomically writes `data`, readable only by the current user on unix.
+///
+/// The temp file is created with mode 0600 under a name unique to `path` and this write, so the
+/// data is never readable by others and concurrent writes of different files cannot collide.
+pub fn write_private(path: &Path, data: &[u8]) -> Result<()> {
+    use std::io::Write;
+
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)?;
+    }
+    let file_name = path
+        .file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| anyhow!("invalid secret file path {:?}", path))?;
+    let tmp = path.with_file_name(format!(
+        ".{file_name}.{}.tmp",
+        uuid::Uuid::new_v4().simple()
+    ));
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    let written = options
+        .open(&tmp)
+        .and_then(|mut file| {
+            file.write_all(data)?;
+            file.sync_all()
+        })
+        .with_context(|| format!("write {:?}", tmp))
+        .and_then(|_| {
+            std::fs::rename(&tmp, path).with_context(|| format!("rename {:?} to {:?}", tmp, path))
+        });
+    if written.is_err() {
+        let _ = std::fs::remove_file(&tmp);
+    }
+    written
+}
+
+struct CachedStore {
+    modified: Option<SystemTime>,
+    entries: Option<BTreeMap<String, String>>,
+}
+
+fn store_cache() -> &'static Mutex<Option<CachedStore>> {
+    static CACHE: OnceLock<Mutex<Option<CachedStore>>> = OnceLock::new();
+    CACHE.get_or_init(|| Mutex::new(None))
+}
+
+/// Looks up `name` in the default store for request-time auth resolution.
+///
+/// The decrypted store is cached until the file's mtime changes, so `ch secrets set` takes effect
+/// without restarting the proxy and a store that fails to decrypt is only reported once.
+pub fn get_secret(name: &str) -> Option<String> {
+    let store = SecretStore::default_location();
+    let modified = std::fs::metadata(store.path())
+        .and_then(|m| m.modified())
+        .ok();
+    let mut guard = match store_cache().lock() {
+        Ok(g) => g,
+        Err(e) => e.into_inner(),
+    };
+    let stale = guard.as_ref().is_none_or(|c| c.modified != modified);
+    if stale {
+        let entries = match store.load(passphrase_from_env().as_deref()) {
+            Ok(entries) => Some(entries),
+            Err(e) => {
+                warn!("failed to load secret store {:?}: {:#}", store.path(), e);
+                None
+            }
+        };
+        *guard = Some(CachedStore { modified, entries });
+    }
+    guard
+        .as_ref()
+        .and_then(|c| c.entries.as_ref())
+        .and_then(|entries| entries.get(name))
+        .filter(|v| !v.trim().is_empty())
+        .cloned()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_store(tag: &str) -> (SecretStore, PathBuf) {
+        let dir = std::env::temp_dir().join(format!(
+            "codex-helper-secrets-{}-{}",
+            tag,
+            std::process::id()
+        ));
+        let _ = std::fs::remove_dir_all(&dir);
+        (SecretStore::new(&dir), dir)
+    }
+
+    #[test]
+    fn machine_key_store_round_trips_without_plaintext_on_disk() {
+        let (store, dir) = temp_store("machine");
+        let mut entries = BTreeMap::new();
+        entries.insert("relay".to_string(), "sk-very-secret".to_string());
+        store.save(&entries, None).expect("save");
+
+        assert_eq!(store.key_source().unwrap(), Some(KeySource::Machine));
+        let raw = std::fs::read_to_string(store.path()).unwrap();
+        assert!(!raw.contains("sk-very-secret"));
+        assert_eq!(store.load(None).expect("load"), entries);
+
+        // Re-saving reuses the existing machine key.
+        entries.insert("other".to_string(), "sk-2".to_string());
+        store.save(&entries, None).expect("save again");
+        assert_eq!(store.load(None).expect("load again"), entries);
+
+        // Store and key are private and no temp files are left behind.
+        let mut names = Vec::new();
+        for entry in std::fs::read_dir(&dir).unwrap() {
+            let entry = entry.unwrap();
+            #[cfg(unix)]
+            {
+                use std::os::unix::fs::PermissionsExt;
+                assert_eq!(
+                    entry.metadata().unwrap().permissions().mode() & 0o777,
+                    0o600
+                );
+            }
+            names.push(entry.file_name().to_string_lossy().into_owned());
+        }
+        assert!(names.iter().all(|n| !n.ends_with(".tmp")), "{names:?}");
+        assert_eq!(names.len(), 2, "{names:?}");
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+
+    #[test]
+    fn passphrase_store_rejects_missing_or_wrong_passphrase() {
+        let (store, dir) = temp_store("passphrase");
+        let mut entries = BTreeMap::new();
+        entries.insert("relay".to_string(), "sk-pass".to_string());
+        store.save(&entries, Some("correct horse")).expect("save");
+
+        assert_eq!(store.key_source().unwrap(), Some(KeySource::Passphrase));
+        assert!(!store.machine_key_path().exists());
+        assert_eq!(store.load(Some("correct horse")).expect("load"), entries);
+        assert!(store.load(Some("wrong")).is_err());
+        assert!(store.load(None).is_err());
+        assert!(store.save(&entries, None).is_err());
+        let _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -508,6 +508,7 @@ async fn select_and_expand_headers(
     let mut chosen = if !matched.is_empty() { matched } else { others };
     // Use file mtime for cheap recency ordering; this correctly surfaces sessions that were resumed
     // (older filename timestamp but recently appended to).
+    #[allow(clippy::unnecessary_sort_by)]
     chosen.sort_by(|a, b| b.mtime_ms.cmp(&a.mtime_ms));
     if chosen.len() > limit {
         chosen.truncate(limit);
@@ -1,18 +1,21 @@
-use std::collections::{HashMap, VecDeque};
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::io::{Read, Seek, SeekFrom};
 use std::path::PathBuf;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::OnceLock;
-use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
 
-use serde::Serialize;
+use serde::{Deserialize, Serialize};
 use serde_json::Value as JsonValue;
 use tokio::sync::RwLock;
 use tokio::time::{Duration, interval};
 
+use crate::budget::{self, BudgetStatus};
+use crate::config::BudgetConfig;
 use crate::lb::LbState;
 use crate::logging::RetryInfo;
+use crate::pricing::{Cost, CostTotals};
 use crate::sessions;
 use crate::usage::UsageMetrics;
 
@@ -28,16 +31,25 @@ fn recent_finished_max() -> usize {
     })
 }
 
-#[derive(Debug, Clone, Serialize, Default, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, Deserialize, Default, PartialEq)]
+#[serde(default)]
 pub struct UsageBucket {
     pub requests_total: u64,
     pub requests_error: u64,
     pub duration_ms_total: u64,
     pub usage: UsageMetrics,
+    /// Estimated cost of the priced requests in this bucket.
+    pub cost: CostTotals,
 }
 
 impl UsageBucket {
-    fn record(&mut self, status_code: u16, duration_ms: u64, usage: Option<&UsageMetrics>) {
+    fn record(
+        &mut self,
+        status_code: u16,
+        duration_ms: u64,
+        usage: Option<&UsageMetrics>,
+        cost: Option<&Cost>,
+    ) {
         self.requests_total = self.requests_total.saturating_add(1);
         if status_code >= 400 {
             self.requests_error = self.requests_error.saturating_add(1);
@@ -46,20 +58,68 @@ impl UsageBucket {
         if let Some(u) = usage {
             self.usage.add_assign(u);
         }
+        if let Some(c) = cost {
+            self.cost.add(c);
+        }
+    }
+
+    fn merge(&mut self, other: &UsageBucket) {
+        self.requests_total = self.requests_total.saturating_add(other.requests_total);
+        self.requests_error = self.requests_error.saturating_add(other.requests_error);
+        self.duration_ms_total = self
+            .duration_ms_total
+            .saturating_add(other.duration_ms_total);
+        self.usage.add_assign(&other.usage);
+        self.cost.merge(&other.cost);
     }
 }
 
-#[derive(Debug, Clone, Serialize, Default, PartialEq, Eq)]
+fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+/// Monthly cost budgets sum the daily buckets of the current month, so fewer days are never kept.
+const MIN_USAGE_ROLLUP_KEEP_DAYS: i32 = 31;
+
+/// Days of per-day rollup buckets to keep: `CODEX_HELPER_USAGE_ROLLUP_KEEP_DAYS`, then
+/// `usage.rollup_keep_days`, default 60; never below [`MIN_USAGE_ROLLUP_KEEP_DAYS`].
+fn usage_rollup_keep_days(configured: Option<u32>) -> i32 {
+    std::env::var("CODEX_HELPER_USAGE_ROLLUP_KEEP_DAYS")
+        .ok()
+        .and_then(|s| s.trim().parse::<i32>().ok())
+        .filter(|&n| n > 0)
+        .or_else(|| configured.map(|n| n.min(i32::MAX as u32) as i32))
+        .unwrap_or(60)
+        .max(MIN_USAGE_ROLLUP_KEEP_DAYS)
+}
+
+/// Where a service's usage rollup is persisted between restarts.
+pub fn usage_rollup_path(service_name: &str) -> PathBuf {
+    crate::config::proxy_home_dir()
+        .join("usage")
+        .join(format!("{service_name}_rollup.json"))
+}
+
+#[derive(Debug, Clone, Serialize, Default, PartialEq)]
 pub struct UsageRollupView {
+    /// All-time totals (kept across restarts, never pruned); the name predates persistence.
     pub since_start: UsageBucket,
     pub by_day: Vec<(i32, UsageBucket)>,
     pub by_config: Vec<(String, UsageBucket)>,
     pub by_config_day: HashMap<String, Vec<(i32, UsageBucket)>>,
     pub by_provider: Vec<(String, UsageBucket)>,
     pub by_provider_day: HashMap<String, Vec<(i32, UsageBucket)>>,
+    /// Usage per pooled upstream key, keyed by `(config, key_id)` (never the key itself).
+    pub by_auth_key: Vec<((String, String), UsageBucket)>,
+    /// Usage per client key label (team mode).
+    pub by_client: Vec<(String, UsageBucket)>,
 }
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
 struct UsageRollup {
     since_start: UsageBucket,
     by_day: HashMap<i32, UsageBucket>,
@@ -67,6 +127,73 @@ struct UsageRollup {
     by_config_day: HashMap<String, HashMap<i32, UsageBucket>>,
     by_provider: HashMap<String, UsageBucket>,
     by_provider_day: HashMap<String, HashMap<i32, UsageBucket>>,
+    /// config -> key_id -> usage. Nested rather than `<config>/<key_id>`, which is ambiguous
+    /// once either name contains a `/`.
+    by_config_auth_key: HashMap<String, HashMap<String, UsageBucket>>,
+    by_client: HashMap<String, UsageBucket>,
+    by_client_day: HashMap<String, HashMap<i32, UsageBucket>>,
+}
+
+impl UsageRollup {
+    fn merge(&mut self, other: &UsageRollup) {
+        fn merge_keyed(dst: &mut HashMap<String, UsageBucket>, src: &HashMap<String, UsageBucket>) {
+            for (k, b) in src {
+                dst.entry(k.clone()).or_default().merge(b);
+            }
+        }
+        fn merge_days(dst: &mut HashMap<i32, UsageBucket>, src: &HashMap<i32, UsageBucket>) {
+            for (day, b) in src {
+                dst.entry(*day).or_default().merge(b);
+            }
+        }
+        fn merge_keyed_days(
+            dst: &mut HashMap<String, HashMap<i32, UsageBucket>>,
+            src: &HashMap<String, HashMap<i32, UsageBucket>>,
+        ) {
+            for (k, days) in src {
+                merge_days(dst.entry(k.clone()).or_default(), days);
+            }
+        }
+
+        self.since_start.merge(&other.since_start);
+        merge_days(&mut self.by_day, &other.by_day);
+        merge_keyed(&mut self.by_config, &other.by_config);
+        merge_keyed_days(&mut self.by_config_day, &other.by_config_day);
+        merge_keyed(&mut self.by_provider, &other.by_provider);
+        merge_keyed_days(&mut self.by_provider_day, &other.by_provider_day);
+        for (cfg, keys) in &other.by_config_auth_key {
+            merge_keyed(
+                self.by_config_auth_key.entry(cfg.clone()).or_default(),
+                keys,
+            );
+        }
+        merge_keyed(&mut self.by_client, &other.by_client);
+        merge_keyed_days(&mut self.by_client_day, &other.by_client_day);
+    }
+
+    /// Drops daily buckets older than `cutoff_day` (days since the Unix epoch).
+    fn retain_days_since(&mut self, cutoff_day: i32) {
+        self.by_day.retain(|day, _| *day >= cutoff_day);
+        for days in [
+            &mut self.by_config_day,
+            &mut self.by_provider_day,
+            &mut self.by_client_day,
+        ] {
+            days.retain(|_, m| {
+                m.retain(|day, _| *day >= cutoff_day);
+                !m.is_empty()
+            });
+        }
+    }
+}
+
+/// On-disk form of one service's rollup (see [`usage_rollup_path`]).
+#[derive(Debug, Serialize, Deserialize)]
+struct PersistedUsageRollup {
+    version: u32,
+    service: String,
+    saved_at_ms: u64,
+    rollup: UsageRollup,
 }
 
 #[derive(Debug, Clone, Serialize, Default, PartialEq, Eq)]
@@ -134,13 +261,19 @@ pub struct ActiveRequest {
     pub provider_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub upstream_base_url: Option<String>,
+    /// Key id (`inline:HASH` / `env:NAME` / ...) when the upstream uses a key pool.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_key: Option<String>,
+    /// Label of the proxy-issued client key that sent the request (team mode).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
     pub service: String,
     pub method: String,
     pub path: String,
     pub started_at_ms: u64,
 }
 
-#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Serialize, PartialEq)]
 pub struct FinishedRequest {
     pub id: u64,
     #[serde(skip_serializing_if = "Option::is_none")]
@@ -157,9 +290,17 @@ pub struct FinishedRequest {
     pub provider_id: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub upstream_base_url: Option<String>,
+    /// Key id (`inline:HASH` / `env:NAME` / ...) when the upstream uses a key pool.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub auth_key: Option<String>,
+    /// Label of the proxy-issued client key that sent the request (team mode).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client: Option<String>,
     #[serde(skip_serializing_if = "Option::is_none")]
     pub usage: Option<UsageMetrics>,
     #[serde(skip_serializing_if = "Option::is_none")]
+    pub cost: Option<Cost>,
+    #[serde(skip_serializing_if = "Option::is_none")]
     pub retry: Option<RetryInfo>,
     pub service: String,
     pub method: String,
@@ -223,9 +364,22 @@ struct ConfigMetaOverride {
     updated_at_ms: u64,
 }
 
-/// Runtime-only state for the proxy process.
+/// Graceful-shutdown progress, shown by the dashboard while in-flight requests finish.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum DrainStatus {
+    #[default]
+    Running,
+    /// Shutdown requested; requests still running are abandoned at `deadline_ms` (unix ms).
+    Draining {
+        deadline_ms: u64,
+    },
+    Done,
+}
+
+/// Runtime state for the proxy process.
 ///
-/// This state is intentionally not persisted across restarts.
+/// Only usage rollups survive restarts (see [`ProxyState::flush_usage_rollup`]); everything else
+/// is intentionally in-memory.
 #[derive(Debug)]
 pub struct ProxyState {
     next_request_id: AtomicU64,
@@ -241,9 +395,17 @@ pub struct ProxyState {
     active_requests: RwLock<HashMap<u64, ActiveRequest>>,
     recent_finished: RwLock<VecDeque<FinishedRequest>>,
     usage_rollups: RwLock<HashMap<String, UsageRollup>>,
+    /// Set when rollups changed since the last flush to disk.
+    usage_rollups_dirty: AtomicBool,
+    /// Held while writing the rollup file, so the periodic and shutdown flushes never interleave.
+    usage_rollup_flush: tokio::sync::Mutex<()>,
+    usage_rollup_keep_days: AtomicI32,
     config_health: RwLock<HashMap<String, HashMap<String, ConfigHealth>>>,
     health_checks: RwLock<HashMap<String, HashMap<String, HealthCheckStatus>>>,
     lb_states: Option<Arc<Mutex<HashMap<String, LbState>>>>,
+    /// Budget alerts already raised, keyed by service/config/limit/period.
+    budget_alerts: Mutex<HashSet<String>>,
+    drain_status: Mutex<DrainStatus>,
 }
 
 impl ProxyState {
@@ -286,9 +448,14 @@ impl ProxyState {
             active_requests: RwLock::new(HashMap::new()),
             recent_finished: RwLock::new(VecDeque::new()),
             usage_rollups: RwLock::new(HashMap::new()),
+            usage_rollups_dirty: AtomicBool::new(false),
+            usage_rollup_flush: tokio::sync::Mutex::new(()),
+            usage_rollup_keep_days: AtomicI32::new(usage_rollup_keep_days(None)),
             config_health: RwLock::new(HashMap::new()),
             health_checks: RwLock::new(HashMap::new()),
             lb_states,
+            budget_alerts: Mutex::new(HashSet::new()),
+            drain_status: Mutex::new(DrainStatus::default()),
         })
     }
 
@@ -681,6 +848,25 @@ impl ProxyState {
         by_provider.sort_by_key(|(_, v)| std::cmp::Reverse(v.usage.total_tokens));
         by_provider.truncate(top_n);
 
+        let mut by_auth_key = rollup
+            .by_config_auth_key
+            .iter()
+            .flat_map(|(cfg, keys)| {
+                keys.iter()
+                    .map(|(k, v)| ((cfg.clone(), k.clone()), v.clone()))
+            })
+            .collect::<Vec<_>>();
+        by_auth_key.sort_by_key(|(_, v)| std::cmp::Reverse(v.usage.total_tokens));
+        by_auth_key.truncate(top_n);
+
+        let mut by_client = rollup
+            .by_client
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect::<Vec<_>>();
+        by_client.sort_by_key(|(_, v)| std::cmp::Reverse(v.usage.total_tokens));
+        by_client.truncate(top_n);
+
         let mut by_config_day = HashMap::new();
         for (name, _) in &by_config {
             if let Some(m) = rollup.by_config_day.get(name) {
@@ -706,9 +892,68 @@ impl ProxyState {
             by_config_day,
             by_provider,
             by_provider_day,
+            by_auth_key,
+            by_client,
+        }
+    }
+
+    /// Tokens used by a client key label on `day` (days since the Unix epoch, UTC), across services.
+    pub async fn client_tokens_on_day(&self, label: &str, day: i32) -> i64 {
+        let guard = self.usage_rollups.read().await;
+        guard
+            .values()
+            .filter_map(|r| r.by_client_day.get(label)?.get(&day))
+            .map(|b| b.usage.total_tokens)
+            .sum()
+    }
+
+    /// Budget usage for `configs` (name, budget) of a service: tokens on the current UTC day and
+    /// estimated cost in the current UTC month.
+    pub async fn budget_statuses(
+        &self,
+        service_name: &str,
+        configs: &[(String, BudgetConfig)],
+        now_ms: u64,
+    ) -> Vec<BudgetStatus> {
+        if configs.is_empty() {
+            return Vec::new();
         }
+        let today = budget::day_index(now_ms);
+        let month_start = budget::month_start_day(today);
+        let guard = self.usage_rollups.read().await;
+        let days = guard.get(service_name).map(|r| &r.by_config_day);
+        configs
+            .iter()
+            .map(|(name, cfg)| {
+                let by_day = days.and_then(|d| d.get(name));
+                let tokens_today = by_day
+                    .and_then(|m| m.get(&today))
+                    .map(|b| b.usage.total_tokens)
+                    .unwrap_or(0);
+                // Only USD-priced requests count towards the (USD) monthly limit.
+                let month_usd = by_day
+                    .into_iter()
+                    .flatten()
+                    .filter(|(day, _)| **day >= month_start && **day <= today)
+                    .filter_map(|(_, b)| b.cost.get(crate::pricing::DEFAULT_CURRENCY))
+                    .collect::<Vec<_>>();
+                let cost_this_month = (!month_usd.is_empty()).then(|| month_usd.iter().sum());
+                BudgetStatus::evaluate(name, cfg, tokens_today, cost_this_month)
+            })
+            .collect()
     }
 
+    /// Records that an alert for `key` was raised; false if it already was.
+    pub fn mark_budget_alert(&self, key: String) -> bool {
+        let mut guard = match self.budget_alerts.lock() {
+            Ok(g) => g,
+            Err(e) => e.into_inner(),
+        };
+        guard.insert(key)
+    }
+
+    /// Seeds the rollup from the tail of `requests.jsonl`. Only used on the first start without
+    /// a persisted rollup; afterwards [`ProxyState::load_usage_rollup`] takes over.
     pub async fn replay_usage_from_requests_log(
         &self,
         service_name: &str,
@@ -819,6 +1064,17 @@ impl ProxyState {
             let usage = v
                 .get("usage")
                 .and_then(|u| serde_json::from_value::<UsageMetrics>(u.clone()).ok());
+            let auth_key = v
+                .get("auth_key")
+                .and_then(|x| x.as_str())
+                .map(|s| s.to_string());
+            let client = v
+                .get("client")
+                .and_then(|x| x.as_str())
+                .map(|s| s.to_string());
+            let cost = v
+                .get("cost")
+                .and_then(|c| serde_json::from_value::<Cost>(c.clone()).ok());
 
             events.push((
                 ended_at_ms,
@@ -826,7 +1082,10 @@ impl ProxyState {
                 duration_ms,
                 config_name,
                 provider_id,
+                auth_key,
+                client,
                 usage,
+                cost,
             ));
         }
 
@@ -836,20 +1095,33 @@ impl ProxyState {
 
         let mut guard = self.usage_rollups.write().await;
         let rollup = guard.entry(service_name.to_string()).or_default();
-        for (ended_at_ms, status_code, duration_ms, cfg_key, provider_key, usage) in events.iter() {
+        for (
+            ended_at_ms,
+            status_code,
+            duration_ms,
+            cfg_key,
+            provider_key,
+            auth_key,
+            client,
+            usage,
+            cost,
+        ) in events.iter()
+        {
             let day = (*ended_at_ms / 86_400_000) as i32;
             rollup
                 .since_start
-                .record(*status_code, *duration_ms, usage.as_ref());
+                .record(*status_code, *duration_ms, usage.as_ref(), cost.as_ref());
             rollup.by_day.entry(day).or_default().record(
                 *status_code,
                 *duration_ms,
                 usage.as_ref(),
+                cost.as_ref(),
             );
             rollup.by_config.entry(cfg_key.clone()).or_default().record(
                 *status_code,
                 *duration_ms,
                 usage.as_ref(),
+                cost.as_ref(),
             );
             rollup
                 .by_config_day
@@ -857,24 +1129,164 @@ impl ProxyState {
                 .or_default()
                 .entry(day)
                 .or_default()
-                .record(*status_code, *duration_ms, usage.as_ref());
+                .record(*status_code, *duration_ms, usage.as_ref(), cost.as_ref());
+            if let Some(key_id) = auth_key.as_deref() {
+                rollup
+                    .by_config_auth_key
+                    .entry(cfg_key.clone())
+                    .or_default()
+                    .entry(key_id.to_string())
+                    .or_default()
+                    .record(*status_code, *duration_ms, usage.as_ref(), cost.as_ref());
+            }
+            if let Some(label) = client.as_deref() {
+                rollup
+                    .by_client
+                    .entry(label.to_string())
+                    .or_default()
+                    .record(*status_code, *duration_ms, usage.as_ref(), cost.as_ref());
+                rollup
+                    .by_client_day
+                    .entry(label.to_string())
+                    .or_default()
+                    .entry(day)
+                    .or_default()
+                    .record(*status_code, *duration_ms, usage.as_ref(), cost.as_ref());
+            }
             rollup
                 .by_provider
                 .entry(provider_key.clone())
                 .or_default()
-                .record(*status_code, *duration_ms, usage.as_ref());
+                .record(*status_code, *duration_ms, usage.as_ref(), cost.as_ref());
             rollup
                 .by_provider_day
                 .entry(provider_key.clone())
                 .or_default()
                 .entry(day)
                 .or_default()
-                .record(*status_code, *duration_ms, usage.as_ref());
+                .record(*status_code, *duration_ms, usage.as_ref(), cost.as_ref());
         }
+        self.usage_rollups_dirty.store(true, Ordering::Relaxed);
 
         events.len()
     }
 
+    pub fn drain_status(&self) -> DrainStatus {
+        match self.drain_status.lock() {
+            Ok(g) => *g,
+            Err(e) => *e.into_inner(),
+        }
+    }
+
+    pub fn set_drain_status(&self, status: DrainStatus) {
+        let mut guard = match self.drain_status.lock() {
+            Ok(g) => g,
+            Err(e) => e.into_inner(),
+        };
+        *guard = status;
+    }
+
+    /// Applies `usage.rollup_keep_days` from the config (the env var still wins).
+    pub fn set_usage_rollup_keep_days(&self, configured: Option<u32>) {
+        self.usage_rollup_keep_days
+            .store(usage_rollup_keep_days(configured), Ordering::Relaxed);
+    }
+
+    /// Merges the persisted rollup for `service_name` into memory. Returns false if there is no
+    /// readable rollup file yet.
+    pub async fn load_usage_rollup(&self, service_name: &str, path: &std::path::Path) -> bool {
+        let bytes = match tokio::fs::read(path).await {
+            Ok(b) => b,
+            Err(_) => return false,
+        };
+        let persisted = match serde_json::from_slice::<PersistedUsageRollup>(&bytes) {
+            Ok(p) if p.service == service_name => p,
+            Ok(_) => return false,
+            Err(e) => {
+                tracing::warn!("ignoring unreadable usage rollup {:?}: {}", path, e);
+                return false;
+            }
+        };
+        let mut loaded = persisted.rollup;
+        let now_day = (now_ms() / 86_400_000) as i32;
+        loaded.retain_days_since(
+            now_day.saturating_sub(self.usage_rollup_keep_days.load(Ordering::Relaxed)),
+        );
+
+        let mut guard = self.usage_rollups.write().await;
+        guard
+            .entry(service_name.to_string())
+            .or_default()
+            .merge(&loaded);
+        true
+    }
+
+    /// Writes the rollup for `service_name` to `path` if anything changed since the last flush.
+    pub async fn flush_usage_rollup(
+        &self,
+        service_name: &str,
+        path: &std::path::Path,
+    ) -> anyhow::Result<()> {
+        let _flush = self.usage_rollup_flush.lock().await;
+        if !self.usage_rollups_dirty.swap(false, Ordering::Relaxed) {
+            return Ok(());
+        }
+        let rollup = {
+            let guard = self.usage_rollups.read().await;
+            guard.get(service_name).cloned().unwrap_or_default()
+        };
+        let persisted = PersistedUsageRollup {
+            version: 1,
+            service: service_name.to_string(),
+            saved_at_ms: now_ms(),
+            rollup,
+        };
+        let res = async {
+            if let Some(parent) = path.parent() {
+                tokio::fs::create_dir_all(parent).await?;
+            }
+            let tmp = path.with_extension("json.tmp");
+            tokio::fs::write(&tmp, serde_json::to_vec(&persisted)?).await?;
+            tokio::fs::rename(&tmp, path).await?;
+            anyhow::Ok(())
+        }
+        .await;
+        if res.is_err() {
+            self.usage_rollups_dirty.store(true, Ordering::Relaxed);
+        }
+        res
+    }
+
+    /// Loads the persisted rollup (seeding it from the request log on first run), then flushes
+    /// changes every `CODEX_HELPER_USAGE_ROLLUP_FLUSH_SECS` (default 10s).
+    pub fn spawn_usage_rollup_persistence(
+        state: Arc<Self>,
+        service_name: &'static str,
+        path: PathBuf,
+        log_path: PathBuf,
+        base_url_to_provider_id: HashMap<String, String>,
+    ) {
+        let flush_secs = std::env::var("CODEX_HELPER_USAGE_ROLLUP_FLUSH_SECS")
+            .ok()
+            .and_then(|s| s.trim().parse::<u64>().ok())
+            .filter(|&n| n > 0)
+            .unwrap_or(10);
+        tokio::spawn(async move {
+            if !state.load_usage_rollup(service_name, &path).await {
+                let _ = state
+                    .replay_usage_from_requests_log(service_name, log_path, base_url_to_provider_id)
+                    .await;
+            }
+            let mut tick = interval(Duration::from_secs(flush_secs));
+            loop {
+                tick.tick().await;
+                if let Err(e) = state.flush_usage_rollup(service_name, &path).await {
+                    tracing::warn!("failed to persist usage rollup to {:?}: {}", path, e);
+                }
+            }
+        });
+    }
+
     pub async fn resolve_session_cwd(&self, session_id: &str) -> Option<String> {
         if self.session_cwd_cache_max_entries == 0 {
             return sessions::find_codex_session_cwd_by_id(session_id)
@@ -929,6 +1341,7 @@ impl ProxyState {
         cwd: Option<String>,
         model: Option<String>,
         reasoning_effort: Option<String>,
+        client: Option<String>,
         started_at_ms: u64,
     ) -> u64 {
         let id = self.next_request_id.fetch_add(1, Ordering::Relaxed);
@@ -941,6 +1354,8 @@ impl ProxyState {
             config_name: None,
             provider_id: None,
             upstream_base_url: None,
+            auth_key: None,
+            client,
             service: service.to_string(),
             method: method.to_string(),
             path: path.to_string(),
@@ -957,6 +1372,7 @@ impl ProxyState {
         config_name: String,
         provider_id: Option<String>,
         upstream_base_url: String,
+        auth_key: Option<String>,
     ) {
         let mut guard = self.active_requests.write().await;
         let Some(req) = guard.get_mut(&request_id) else {
@@ -965,8 +1381,10 @@ impl ProxyState {
         req.config_name = Some(config_name);
         req.provider_id = provider_id;
         req.upstream_base_url = Some(upstream_base_url);
+        req.auth_key = auth_key;
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub async fn finish_request(
         &self,
         id: u64,
@@ -974,6 +1392,7 @@ impl ProxyState {
         duration_ms: u64,
         ended_at_ms: u64,
         usage: Option<UsageMetrics>,
+        cost: Option<Cost>,
         retry: Option<RetryInfo>,
     ) {
         let mut active = self.active_requests.write().await;
@@ -990,7 +1409,10 @@ impl ProxyState {
             config_name: req.config_name,
             provider_id: req.provider_id,
             upstream_base_url: req.upstream_base_url,
+            auth_key: req.auth_key,
+            client: req.client,
             usage: usage.clone(),
+            cost: cost.clone(),
             retry,
             service: req.service,
             method: req.method,
@@ -1015,37 +1437,63 @@ impl ProxyState {
             let rollup = rollups.entry(finished.service.clone()).or_default();
             rollup
                 .since_start
-                .record(status_code, duration_ms, usage.as_ref());
-            rollup
-                .by_day
-                .entry(day)
-                .or_default()
-                .record(status_code, duration_ms, usage.as_ref());
+                .record(status_code, duration_ms, usage.as_ref(), cost.as_ref());
+            rollup.by_day.entry(day).or_default().record(
+                status_code,
+                duration_ms,
+                usage.as_ref(),
+                cost.as_ref(),
+            );
             rollup.by_config.entry(cfg_key.clone()).or_default().record(
                 status_code,
                 duration_ms,
                 usage.as_ref(),
+                cost.as_ref(),
             );
+            if let Some(key_id) = finished.auth_key.as_deref() {
+                rollup
+                    .by_config_auth_key
+                    .entry(cfg_key.clone())
+                    .or_default()
+                    .entry(key_id.to_string())
+                    .or_default()
+                    .record(status_code, duration_ms, usage.as_ref(), cost.as_ref());
+            }
+            if let Some(label) = finished.client.as_deref() {
+                rollup
+                    .by_client
+                    .entry(label.to_string())
+                    .or_default()
+                    .record(status_code, duration_ms, usage.as_ref(), cost.as_ref());
+                rollup
+                    .by_client_day
+                    .entry(label.to_string())
+                    .or_default()
+                    .entry(day)
+                    .or_default()
+                    .record(status_code, duration_ms, usage.as_ref(), cost.as_ref());
+            }
             rollup
                 .by_config_day
                 .entry(cfg_key)
                 .or_default()
                 .entry(day)
                 .or_default()
-                .record(status_code, duration_ms, usage.as_ref());
+                .record(status_code, duration_ms, usage.as_ref(), cost.as_ref());
 
             rollup
                 .by_provider
                 .entry(provider_key.clone())
                 .or_default()
-                .record(status_code, duration_ms, usage.as_ref());
+                .record(status_code, duration_ms, usage.as_ref(), cost.as_ref());
             rollup
                 .by_provider_day
                 .entry(provider_key)
                 .or_default()
                 .entry(day)
                 .or_default()
-                .record(status_code, duration_ms, usage.as_ref());
+                .record(status_code, duration_ms, usage.as_ref(), cost.as_ref());
+            self.usage_rollups_dirty.store(true, Ordering::Relaxed);
         }
 
         if let Some(sid) = finished.session_id.as_deref() {
@@ -1149,25 +1597,14 @@ impl ProxyState {
         }
 
         // Keep a bounded number of days of rollup data to avoid unbounded growth.
-        let keep_days: i32 = std::env::var("CODEX_HELPER_USAGE_ROLLUP_KEEP_DAYS")
-            .ok()
-            .and_then(|s| s.trim().parse::<i32>().ok())
-            .filter(|&n| n > 0)
-            .unwrap_or(60);
         let now_day = (now_ms / 86_400_000) as i32;
-        let cutoff_day = now_day.saturating_sub(keep_days);
+        let cutoff_day =
+            now_day.saturating_sub(self.usage_rollup_keep_days.load(Ordering::Relaxed));
         let mut rollups = self.usage_rollups.write().await;
         for rollup in rollups.values_mut() {
-            rollup.by_day.retain(|day, _| *day >= cutoff_day);
-            rollup.by_config_day.retain(|_, m| {
-                m.retain(|day, _| *day >= cutoff_day);
-                !m.is_empty()
-            });
-            rollup.by_provider_day.retain(|_, m| {
-                m.retain(|day, _| *day >= cutoff_day);
-                !m.is_empty()
-            });
+            rollup.retain_days_since(cutoff_day);
         }
+        drop(rollups);
 
         let cutoff_cwd =
             if self.session_cwd_cache_ttl_ms == 0 || now_ms < self.session_cwd_cache_ttl_ms {
@@ -1219,3 +1656,131 @@ impl ProxyState {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn usage_rollup_keep_days_covers_a_month() {
+        if std::env::var_os("CODEX_HELPER_USAGE_ROLLUP_KEEP_DAYS").is_some() {
+            return;
+        }
+        assert_eq!(usage_rollup_keep_days(None), 60);
+        assert_eq!(usage_rollup_keep_days(Some(90)), 90);
+        assert_eq!(usage_rollup_keep_days(Some(7)), MIN_USAGE_ROLLUP_KEEP_DAYS);
+    }
+
+    #[tokio::test]
+    async fn usage_rollup_survives_restart_and_drops_expired_days() {
+        let path = std::env::temp_dir()
+            .join(format!("codex-helper-rollup-{}", uuid::Uuid::new_v4()))
+            .join("codex_rollup.json");
+        let today = (now_ms() / 86_400_000) as i32;
+        let usage = UsageMetrics {
+            input_tokens: 100,
+            output_tokens: 20,
+            total_tokens: 120,
+            ..Default::default()
+        };
+
+        let before = ProxyState::new();
+        {
+            let mut guard = before.usage_rollups.write().await;
+            let rollup = guard.entry("codex".to_string()).or_default();
+            for day in [today, today - 365] {
+                rollup.since_start.record(200, 10, Some(&usage), None);
+                rollup
+                    .by_day
+                    .entry(day)
+                    .or_default()
+                    .record(200, 10, Some(&usage), None);
+                rollup
+                    .by_config_day
+                    .entry("right".to_string())
+                    .or_default()
+                    .entry(day)
+                    .or_default()
+                    .record(200, 10, Some(&usage), None);
+            }
+        }
+        // Nothing changed yet as far as persistence is concerned.
+        before.flush_usage_rollup("codex", &path).await.unwrap();
+        assert!(!path.exists());
+        before.usage_rollups_dirty.store(true, Ordering::Relaxed);
+        before.flush_usage_rollup("codex", &path).await.unwrap();
+
+        let after = ProxyState::new();
+        {
+            let mut guard = after.usage_rollups.write().await;
+            guard
+                .entry("codex".to_string())
+                .or_default()
+                .since_start
+                .record(500, 5, None, None);
+        }
+        assert!(!after.load_usage_rollup("claude", &path).await);
+        assert!(after.load_usage_rollup("codex", &path).await);
+
+        let guard = after.usage_rollups.read().await;
+        let rollup = &guard["codex"];
+        assert_eq!(rollup.since_start.requests_total, 3);
+        assert_eq!(rollup.since_start.requests_error, 1);
+        assert_eq!(rollup.since_start.usage.total_tokens, 240);
+        assert_eq!(
+            rollup.by_day.keys().copied().collect::<Vec<_>>(),
+            vec![today]
+        );
+        assert_eq!(rollup.by_config_day["right"].len(), 1);
+
+        let _ = std::fs::remove_dir_all(path.parent().unwrap());
+    }
+
+    #[tokio::test]
+    async fn usage_rollup_keeps_pool_keys_of_similar_configs_apart() {
+        let state = ProxyState::new();
+        for (cfg, key_id) in [("a/b", "c"), ("a", "b/c"), ("a", "b/c")] {
+            let id = state
+                .begin_request(
+                    "codex",
+                    "POST",
+                    "/v1/responses",
+                    None,
+                    None,
+                    None,
+                    None,
+                    None,
+                    0,
+                )
+                .await;
+            state
+                .update_request_route(
+                    id,
+                    cfg.to_string(),
+                    None,
+                    "https://example.com".to_string(),
+                    Some(key_id.to_string()),
+                )
+                .await;
+            state
+                .finish_request(id, 200, 10, 10, None, None, None)
+                .await;
+        }
+
+        let mut keys = state
+            .get_usage_rollup_view("codex", 10, 7)
+            .await
+            .by_auth_key
+            .into_iter()
+            .map(|((cfg, key_id), b)| (cfg, key_id, b.requests_total))
+            .collect::<Vec<_>>();
+        keys.sort();
+        assert_eq!(
+            keys,
+            vec![
+                ("a".to_string(), "b/c".to_string(), 2),
+                ("a/b".to_string(), "c".to_string(), 1),
+            ]
+        );
+    }
+}
@@ -303,9 +303,9 @@ async fn probe_upstream(client: &reqwest::Client, upstream: &UpstreamConfig) ->
 
     let start = Instant::now();
     let mut req = client.get(url).header("Accept", "application/json");
-    if let Some(token) = upstream.auth.resolve_auth_token() {
+    if let Some(token) = upstream.auth.resolve_auth_token().await {
         req = req.header("Authorization", format!("Bearer {}", token));
-    } else if let Some(key) = upstream.auth.resolve_api_key() {
+    } else if let Some(key) = upstream.auth.resolve_api_key().await {
         req = req.header("X-API-Key", key);
     }
 
@@ -479,6 +479,8 @@ async fn handle_key_normal(
     key: KeyEvent,
 ) -> bool {
     if key.modifiers.contains(KeyModifiers::CONTROL) && matches!(key.code, KeyCode::Char('c')) {
+        // Raw mode swallows SIGINT, so a second Ctrl+C while draining is the forced quit.
+        ui.force_quit = ui.draining;
         ui.should_exit = true;
         return true;
     }
@@ -554,11 +556,8 @@ async fn handle_key_normal(
         }
         KeyCode::Char('R') if ui.page == Page::Settings => {
             let now = Instant::now();
-            let url = format!("http://127.0.0.1:{}/__codex_helper/config/reload", ui.port);
             let res = async {
-                let client = reqwest::Client::new();
-                client
-                    .post(&url)
+                ui.admin_request(reqwest::Method::POST, "/__codex_helper/config/reload")
                     .send()
                     .await?
                     .error_for_status()?
@@ -578,6 +577,10 @@ async fn handle_key_normal(
                     ui.last_runtime_retry = st
                         .and_then(|x| x.get("retry"))
                         .and_then(|x| serde_json::from_value(x.clone()).ok());
+                    ui.last_filter_problems = st
+                        .and_then(|x| x.get("filter_problems"))
+                        .and_then(|x| serde_json::from_value(x.clone()).ok())
+                        .unwrap_or_default();
                     ui.last_runtime_config_refresh_at = Some(now);
 
                     let changed = v.get("reloaded").and_then(|x| x.as_bool()).unwrap_or(false);
@@ -11,6 +11,7 @@ pub(crate) use i18n::Language;
 pub(crate) use i18n::{detect_system_language, parse_language};
 #[allow(unused_imports)]
 pub use model::{ProviderOption, UpstreamSummary, build_provider_options};
+pub(crate) use terminal::restore_terminal;
 
 use std::io;
 use std::sync::Arc;
@@ -25,12 +26,14 @@ use ratatui::Terminal;
 use ratatui::backend::CrosstermBackend;
 use tokio::sync::watch;
 
-use crate::state::ProxyState;
+use crate::state::{DrainStatus, ProxyState};
 
 use self::model::{Palette, refresh_snapshot};
-use self::state::UiState;
+use self::state::{UiState, admin_client};
 use self::terminal::TerminalGuard;
 
+/// Runs the dashboard until it is closed or the proxy finished draining. Returns `true` when the
+/// user asked to abandon in-flight requests (Ctrl+C while draining).
 pub async fn run_dashboard(
     state: Arc<ProxyState>,
     service_name: &'static str,
@@ -39,7 +42,7 @@ pub async fn run_dashboard(
     language: Language,
     shutdown: watch::Sender<bool>,
     mut shutdown_rx: watch::Receiver<bool>,
-) -> anyhow::Result<()> {
+) -> anyhow::Result<bool> {
     let refresh_ms = std::env::var("CODEX_HELPER_TUI_REFRESH_MS")
         .ok()
         .and_then(|s| s.trim().parse::<u64>().ok())
@@ -54,9 +57,15 @@ pub async fn run_dashboard(
     let mut terminal = Terminal::new(backend)?;
     terminal.hide_cursor()?;
 
+    let server_cfg = crate::config::load_config()
+        .await
+        .map(|cfg| cfg.server)
+        .unwrap_or_default();
     let mut ui = UiState {
         service_name,
-        port,
+        admin_base_url: server_cfg.local_base_url(port),
+        admin_token: server_cfg.resolve_admin_token(),
+        admin_client: admin_client(&server_cfg),
         language,
         refresh_ms,
         ..Default::default()
@@ -67,8 +76,8 @@ pub async fn run_dashboard(
     let mut ticker = tokio::time::interval(Duration::from_millis(refresh_ms));
     ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
 
-    let mut snapshot = refresh_snapshot(&state, service_name, ui.stats_days).await;
     let mut providers = providers;
+    let mut snapshot = refresh_snapshot(&state, service_name, ui.stats_days, &providers).await;
     ui.clamp_selection(&snapshot, providers.len());
 
     let mut should_redraw = true;
@@ -88,26 +97,30 @@ pub async fn run_dashboard(
             should_redraw = false;
         }
 
-        if ui.should_exit || *shutdown_rx.borrow() {
-            let _ = shutdown.send(true);
+        if snapshot.drain == DrainStatus::Done || (ui.draining && ui.should_exit) {
             break;
         }
+        if !ui.draining && (ui.should_exit || *shutdown_rx.borrow()) {
+            // Keep the dashboard up while in-flight requests drain; a second q closes it.
+            let _ = shutdown.send(true);
+            ui.draining = true;
+            ui.should_exit = false;
+            snapshot.drain = state.drain_status();
+            should_redraw = true;
+            continue;
+        }
 
         tokio::select! {
             _ = ticker.tick() => {
-                snapshot = refresh_snapshot(&state, service_name, ui.stats_days).await;
+                snapshot = refresh_snapshot(&state, service_name, ui.stats_days, &providers).await;
                 ui.clamp_selection(&snapshot, providers.len());
                 if ui.page == crate::tui::types::Page::Settings
                     && ui
                         .last_runtime_config_refresh_at
                         .is_none_or(|t| t.elapsed() > Duration::from_secs(1))
                 {
-                    let url =
-                        format!("http://127.0.0.1:{}/__codex_helper/config/runtime", ui.port);
                     let fetch = async {
-                        let client = reqwest::Client::new();
-                        client
-                            .get(&url)
+                        ui.admin_request(reqwest::Method::GET, "/__codex_helper/config/runtime")
                             .send()
                             .await?
                             .error_for_status()?
@@ -122,23 +135,29 @@ pub async fn run_dashboard(
                         ui.last_runtime_retry = v
                             .get("retry")
                             .and_then(|x| serde_json::from_value(x.clone()).ok());
+                        ui.last_filter_problems = v
+                            .get("filter_problems")
+                            .and_then(|x| serde_json::from_value(x.clone()).ok())
+                            .unwrap_or_default();
                     }
                     ui.last_runtime_config_refresh_at = Some(Instant::now());
                 }
                 should_redraw = true;
             }
-            changed = shutdown_rx.changed() => {
-                let _ = changed;
-                ui.should_exit = true;
+            changed = shutdown_rx.changed(), if !ui.draining => {
+                if changed.is_err() {
+                    ui.should_exit = true;
+                }
                 should_redraw = true;
             }
             maybe_event = events.next() => {
                 let Some(Ok(event)) = maybe_event else { continue; };
+                #[allow(clippy::collapsible_match)]
                 match event {
                     Event::Key(key) if input::should_accept_key_event(&key) => {
                         if input::handle_key_event(state.clone(), &mut providers, &mut ui, &snapshot, key).await {
                             if ui.needs_snapshot_refresh {
-                                snapshot = refresh_snapshot(&state, service_name, ui.stats_days).await;
+                                snapshot = refresh_snapshot(&state, service_name, ui.stats_days, &providers).await;
                                 ui.clamp_selection(&snapshot, providers.len());
                                 ui.needs_snapshot_refresh = false;
                             }
@@ -158,5 +177,5 @@ pub async fn run_dashboard(
     crossterm::terminal::disable_raw_mode()?;
     terminal.backend_mut().execute(LeaveAlternateScreen)?;
     term_guard.disarm();
-    Ok(())
+    Ok(ui.force_quit)
 }
@@ -3,9 +3,10 @@ use std::time::Instant;
 
 use ratatui::prelude::{Color, Style};
 
+use crate::budget::BudgetStatus;
 use crate::state::{
-    ActiveRequest, ConfigHealth, FinishedRequest, HealthCheckStatus, LbConfigView, ProxyState,
-    SessionStats, UsageRollupView,
+    ActiveRequest, ConfigHealth, DrainStatus, FinishedRequest, HealthCheckStatus, LbConfigView,
+    ProxyState, SessionStats, UsageRollupView,
 };
 use crate::usage::UsageMetrics;
 
@@ -114,13 +115,14 @@ pub struct UpstreamSummary {
     pub model_mapping: Vec<(String, String)>,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Default)]
+#[derive(Debug, Clone, PartialEq, Default)]
 pub struct ProviderOption {
     pub name: String,
     pub alias: Option<String>,
     pub enabled: bool,
     pub level: u8,
     pub active: bool,
+    pub budget: crate::config::BudgetConfig,
     pub upstreams: Vec<UpstreamSummary>,
 }
 
@@ -159,8 +161,10 @@ pub(in crate::tui) struct Snapshot {
     pub(in crate::tui) config_health: HashMap<String, ConfigHealth>,
     pub(in crate::tui) health_checks: HashMap<String, HealthCheckStatus>,
     pub(in crate::tui) lb_view: HashMap<String, LbConfigView>,
+    pub(in crate::tui) budgets: HashMap<String, BudgetStatus>,
     pub(in crate::tui) stats_5m: WindowStats,
     pub(in crate::tui) stats_1h: WindowStats,
+    pub(in crate::tui) drain: DrainStatus,
     pub(in crate::tui) refreshed_at: Instant,
 }
 
@@ -242,6 +246,14 @@ pub fn build_provider_options(
             .is_some_and(|s| !s.trim().is_empty())
         {
             "bearer inline".to_string()
+        } else if u.auth.has_token_pool() {
+            format!("bearer pool ({})", u.auth.token_pool().len())
+        } else if let Some(name) = u.auth.auth_token_secret.as_deref() {
+            format!("bearer secret {name}")
+        } else if let Some(path) = u.auth.auth_token_file.as_deref() {
+            format!("bearer file {path}")
+        } else if let Some(program) = u.auth.auth_token_cmd.first() {
+            format!("bearer cmd {program}")
         } else if let Some(env) = u.auth.api_key_env.as_deref()
             && !env.trim().is_empty()
         {
@@ -253,6 +265,12 @@ pub fn build_provider_options(
             .is_some_and(|s| !s.trim().is_empty())
         {
             "x-api-key inline".to_string()
+        } else if let Some(name) = u.auth.api_key_secret.as_deref() {
+            format!("x-api-key secret {name}")
+        } else if let Some(path) = u.auth.api_key_file.as_deref() {
+            format!("x-api-key file {path}")
+        } else if let Some(program) = u.auth.api_key_cmd.first() {
+            format!("x-api-key cmd {program}")
         } else {
             "-".to_string()
         };
@@ -295,6 +313,7 @@ pub fn build_provider_options(
                 enabled: svc.enabled,
                 level: svc.level.clamp(1, 10),
                 active: cfg.claude.active.as_deref() == Some(name.as_str()),
+                budget: svc.budget.clone(),
                 upstreams: svc.upstreams.iter().map(upstream_summary).collect(),
             })
             .collect(),
@@ -308,6 +327,7 @@ pub fn build_provider_options(
                 enabled: svc.enabled,
                 level: svc.level.clamp(1, 10),
                 active: cfg.codex.active.as_deref() == Some(name.as_str()),
+                budget: svc.budget.clone(),
                 upstreams: svc.upstreams.iter().map(upstream_summary).collect(),
             })
             .collect(),
@@ -358,14 +378,30 @@ pub(in crate::tui) fn tokens_short(n: i64) -> String {
     }
 }
 
+pub(in crate::tui) fn cache_hit_pct(usage: &UsageMetrics) -> String {
+    usage
+        .cache_hit_ratio()
+        .map(|r| format!("{:.1}%", r * 100.0))
+        .unwrap_or_else(|| "-".to_string())
+}
+
 pub(in crate::tui) fn usage_line(usage: &UsageMetrics) -> String {
-    format!(
+    let mut line = format!(
         "tok in/out/rsn/ttl: {}/{}/{}/{}",
         tokens_short(usage.input_tokens),
         tokens_short(usage.output_tokens),
         tokens_short(usage.reasoning_tokens),
         tokens_short(usage.total_tokens)
-    )
+    );
+    if usage.cached_input_tokens > 0 || usage.cache_write_tokens > 0 {
+        line.push_str(&format!(
+            "  cache rd/wr: {}/{} ({})",
+            tokens_short(usage.cached_input_tokens),
+            tokens_short(usage.cache_write_tokens),
+            cache_hit_pct(usage)
+        ));
+    }
+    line
 }
 
 pub(in crate::tui) fn status_style(p: Palette, status: Option<u16>) -> Style {
@@ -600,8 +636,14 @@ pub(in crate::tui) async fn refresh_snapshot(
     state: &ProxyState,
     service_name: &str,
     stats_days: usize,
+    providers: &[ProviderOption],
 ) -> Snapshot {
     let now = now_ms();
+    let budget_configs = providers
+        .iter()
+        .filter(|p| !p.budget.is_empty())
+        .map(|p| (p.name.clone(), p.budget.clone()))
+        .collect::<Vec<_>>();
     let (
         active,
         recent,
@@ -614,6 +656,7 @@ pub(in crate::tui) async fn refresh_snapshot(
         health,
         health_checks,
         lb_view,
+        budgets,
     ) = tokio::join!(
         state.list_active_requests(),
         state.list_recent_finished(2_000),
@@ -626,6 +669,7 @@ pub(in crate::tui) async fn refresh_snapshot(
         state.get_config_health(service_name),
         state.list_health_checks(service_name),
         state.get_lb_view(),
+        state.budget_statuses(service_name, &budget_configs, now),
     );
 
     let rows = build_session_rows(active, &recent, &overrides, &config_overrides, &stats);
@@ -642,8 +686,13 @@ pub(in crate::tui) async fn refresh_snapshot(
         config_health: health,
         health_checks,
         lb_view,
+        budgets: budgets
+            .into_iter()
+            .map(|b| (b.config_name.clone(), b))
+            .collect(),
         stats_5m,
         stats_1h,
+        drain: state.drain_status(),
         refreshed_at: Instant::now(),
     }
 }
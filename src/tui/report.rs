@@ -1,6 +1,6 @@
 use std::collections::HashMap;
 
-use super::model::tokens_short;
+use super::model::{cache_hit_pct, tokens_short};
 use super::state::UiState;
 use super::types::StatsFocus;
 use crate::state::UsageBucket;
@@ -19,6 +19,7 @@ fn sum_buckets(buckets: &[(i32, UsageBucket)]) -> UsageBucket {
         out.requests_error = out.requests_error.saturating_add(b.requests_error);
         out.duration_ms_total = out.duration_ms_total.saturating_add(b.duration_ms_total);
         out.usage.add_assign(&b.usage);
+        out.cost.merge(&b.cost);
     }
     out
 }
@@ -126,11 +127,21 @@ fn fmt_avg_ms(total_ms: u64, n: u64) -> String {
 
 fn fmt_usage_line(u: &UsageMetrics) -> String {
     format!(
-        "tokens in/out/rsn/ttl: {}/{}/{}/{}",
+        "tokens in/out/rsn/ttl: {}/{}/{}/{}\ncache read/write: {}/{}  hit {}",
         tokens_short(u.input_tokens),
         tokens_short(u.output_tokens),
         tokens_short(u.reasoning_tokens),
-        tokens_short(u.total_tokens)
+        tokens_short(u.total_tokens),
+        tokens_short(u.cached_input_tokens),
+        tokens_short(u.cache_write_tokens),
+        cache_hit_pct(u)
+    )
+}
+
+fn fmt_cost_line(bucket: &UsageBucket) -> String {
+    format!(
+        "cost (estimated): {}",
+        bucket.cost.display().unwrap_or_else(|| "-".to_string())
     )
 }
 
@@ -226,9 +237,10 @@ pub(in crate::tui) fn build_stats_report(
         ),
     ));
     out.push_str(&format!("{}\n", fmt_usage_line(&window_bucket.usage)));
+    out.push_str(&format!("{}\n", fmt_cost_line(&window_bucket)));
     out.push('\n');
 
-    out.push_str("[since start rollup]\n");
+    out.push_str("[all-time rollup]\n");
     out.push_str(&format!(
         "requests: {} (errors {} / {})  avg {}\n",
         since_start_bucket.requests_total,
@@ -243,8 +255,32 @@ pub(in crate::tui) fn build_stats_report(
         ),
     ));
     out.push_str(&format!("{}\n", fmt_usage_line(&since_start_bucket.usage)));
+    out.push_str(&format!("{}\n", fmt_cost_line(&since_start_bucket)));
     out.push('\n');
 
+    if let StatsTarget::Config(cfg) = &target {
+        let keys = snapshot
+            .usage_rollup
+            .by_auth_key
+            .iter()
+            .filter(|((c, _), _)| c == cfg)
+            .map(|((_, id), b)| (id, b))
+            .collect::<Vec<_>>();
+        if !keys.is_empty() {
+            out.push_str("[key pool (all time)]\n");
+            for (key_id, b) in keys {
+                out.push_str(&format!(
+                    "  - {}: {} req (err {}) / {}\n",
+                    key_id,
+                    b.requests_total,
+                    b.requests_error,
+                    tokens_short(b.usage.total_tokens)
+                ));
+            }
+            out.push('\n');
+        }
+    }
+
     out.push_str("[recent breakdown (<=200)]\n");
     out.push_str(&format!(
         "requests: {}  errors: {}  2xx/3xx/4xx/5xx: {}/{}/{}/{}\n",
@@ -1,6 +1,8 @@
 use ratatui::widgets::{ListState, TableState};
 
-use crate::config::RetryConfig;
+use tracing::warn;
+
+use crate::config::{RetryConfig, ServerConfig};
 
 use super::Language;
 use super::model::{Snapshot, filtered_requests_len};
@@ -9,7 +11,9 @@ use super::types::{Focus, Overlay, Page, StatsFocus};
 #[derive(Debug)]
 pub(in crate::tui) struct UiState {
     pub(in crate::tui) service_name: &'static str,
-    pub(in crate::tui) port: u16,
+    pub(in crate::tui) admin_base_url: String,
+    pub(in crate::tui) admin_token: Option<String>,
+    pub(in crate::tui) admin_client: reqwest::Client,
     pub(in crate::tui) language: Language,
     pub(in crate::tui) refresh_ms: u64,
     pub(in crate::tui) page: Page,
@@ -39,8 +43,13 @@ pub(in crate::tui) struct UiState {
     pub(in crate::tui) last_runtime_config_loaded_at_ms: Option<u64>,
     pub(in crate::tui) last_runtime_config_source_mtime_ms: Option<u64>,
     pub(in crate::tui) last_runtime_retry: Option<RetryConfig>,
+    pub(in crate::tui) last_filter_problems: Vec<String>,
     pub(in crate::tui) last_runtime_config_refresh_at: Option<std::time::Instant>,
     pub(in crate::tui) should_exit: bool,
+    /// Shutdown was requested; the dashboard stays up until in-flight requests drain.
+    pub(in crate::tui) draining: bool,
+    /// Ctrl+C while draining: abandon in-flight requests instead of just closing the dashboard.
+    pub(in crate::tui) force_quit: bool,
     pub(in crate::tui) configs_table: TableState,
     pub(in crate::tui) sessions_table: TableState,
     pub(in crate::tui) requests_table: TableState,
@@ -56,7 +65,9 @@ impl Default for UiState {
     fn default() -> Self {
         Self {
             service_name: "codex",
-            port: 3211,
+            admin_base_url: "http://127.0.0.1:3211".to_string(),
+            admin_token: None,
+            admin_client: reqwest::Client::new(),
             language: Language::En,
             refresh_ms: 500,
             page: Page::Dashboard,
@@ -86,8 +97,11 @@ impl Default for UiState {
             last_runtime_config_loaded_at_ms: None,
             last_runtime_config_source_mtime_ms: None,
             last_runtime_retry: None,
+            last_filter_problems: Vec::new(),
             last_runtime_config_refresh_at: None,
             should_exit: false,
+            draining: false,
+            force_quit: false,
             configs_table: TableState::default(),
             sessions_table: TableState::default(),
             requests_table: TableState::default(),
@@ -101,7 +115,44 @@ impl Default for UiState {
     }
 }
 
+/// HTTP client for the admin endpoints; with TLS it trusts the configured `tls_cert`
+/// (usually self-signed) in addition to the system roots, and nothing else.
+pub(in crate::tui) fn admin_client(server: &ServerConfig) -> reqwest::Client {
+    let mut builder = reqwest::Client::builder();
+    if let Ok(Some((cert, _))) = server.tls_files() {
+        match std::fs::read(&cert)
+            .map_err(anyhow::Error::from)
+            .and_then(|pem| Ok(reqwest::Certificate::from_pem_bundle(&pem)?))
+        {
+            Ok(certs) => {
+                for c in certs {
+                    builder = builder.add_root_certificate(c);
+                }
+            }
+            Err(e) => warn!(
+                "failed to load server.tls_cert {:?} for the TUI: {}",
+                cert, e
+            ),
+        }
+    }
+    builder.build().unwrap_or_default()
+}
+
 impl UiState {
+    /// Builds a request to the proxy's own `/__codex_helper/*` endpoints, with the admin token if set.
+    pub(in crate::tui) fn admin_request(
+        &self,
+        method: reqwest::Method,
+        path: &str,
+    ) -> reqwest::RequestBuilder {
+        let url = format!("{}{}", self.admin_base_url, path);
+        let req = self.admin_client.request(method, url);
+        match self.admin_token.as_deref() {
+            Some(token) => req.header(crate::proxy::ADMIN_TOKEN_HEADER, token),
+            None => req,
+        }
+    }
+
     pub(in crate::tui) fn clamp_selection(&mut self, snapshot: &Snapshot, providers_len: usize) {
         if providers_len == 0 {
             self.selected_config_idx = 0;
@@ -25,7 +25,12 @@ impl Drop for TerminalGuard {
         if self.disarmed {
             return;
         }
-        let _ = disable_raw_mode();
-        let _ = crossterm::execute!(io::stdout(), crossterm::cursor::Show, LeaveAlternateScreen);
+        restore_terminal();
     }
 }
+
+/// Best-effort terminal reset, also used before `process::exit` where no destructor runs.
+pub(crate) fn restore_terminal() {
+    let _ = disable_raw_mode();
+    let _ = crossterm::execute!(io::stdout(), crossterm::cursor::Show, LeaveAlternateScreen);
+}
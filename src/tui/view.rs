@@ -33,7 +33,7 @@ pub(in crate::tui) fn render_app(
 
     chrome::render_header(f, p, ui, snapshot, service_name, port, outer[0]);
     pages::render_body(f, p, ui, snapshot, providers, outer[1]);
-    chrome::render_footer(f, p, ui, outer[2]);
+    chrome::render_footer(f, p, ui, snapshot, outer[2]);
 
     match ui.overlay {
         Overlay::None => {}
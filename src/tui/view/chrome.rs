@@ -5,7 +5,9 @@ use ratatui::layout::{Constraint, Direction, Layout, Margin, Rect};
 use ratatui::prelude::{Line, Modifier, Span, Style, Text};
 use ratatui::widgets::{Block, Borders, Paragraph, Wrap};
 
-use crate::tui::model::{Palette, Snapshot};
+use crate::state::DrainStatus;
+use crate::tui::Language;
+use crate::tui::model::{Palette, Snapshot, now_ms};
 use crate::tui::state::UiState;
 use crate::tui::types::{Focus, Overlay, Page, page_index, page_titles};
 
@@ -245,7 +247,13 @@ pub(super) fn render_header(
     f.render_widget(tabs, chunks[2]);
 }
 
-pub(super) fn render_footer(f: &mut Frame<'_>, p: Palette, ui: &mut UiState, area: Rect) {
+pub(super) fn render_footer(
+    f: &mut Frame<'_>,
+    p: Palette,
+    ui: &mut UiState,
+    snapshot: &Snapshot,
+    area: Rect,
+) {
     let now = std::time::Instant::now();
     if let Some((_, ts)) = ui.toast.as_ref()
         && now.duration_since(*ts) > Duration::from_secs(3)
@@ -317,8 +325,26 @@ pub(super) fn render_footer(f: &mut Frame<'_>, p: Palette, ui: &mut UiState, are
     };
     let right = ui.toast.as_ref().map(|(s, _)| s.as_str()).unwrap_or("");
 
+    // While draining, the countdown replaces the key hints.
+    let (left, left_color) = match snapshot.drain {
+        DrainStatus::Draining { deadline_ms } => {
+            let active: usize = snapshot.rows.iter().map(|r| r.active_count).sum();
+            let secs = deadline_ms.saturating_sub(now_ms()).div_ceil(1000);
+            let text = match ui.language {
+                Language::Zh => format!(
+                    "正在等待 {active} 个请求完成（剩余 {secs}s，再按 Ctrl+C 立即退出，q 关闭面板）"
+                ),
+                Language::En => format!(
+                    "Draining: waiting for {active} active request(s) ({secs}s left, Ctrl+C again to quit now, q to close dashboard)"
+                ),
+            };
+            (text, p.warn)
+        }
+        _ => (left.to_string(), p.muted),
+    };
+
     let line = Line::from(vec![
-        Span::styled(left, Style::default().fg(p.muted)),
+        Span::styled(left, Style::default().fg(left_color)),
         Span::raw(" "),
         Span::styled(right, Style::default().fg(p.accent)),
     ]);
@@ -65,7 +65,15 @@ pub(super) fn render_configs_page(
                 .as_deref()
                 .filter(|s| !s.trim().is_empty())
                 .unwrap_or("-");
-            let on = if enabled { "on" } else { "off" };
+            let over_budget = snapshot
+                .budgets
+                .get(cfg.name.as_str())
+                .is_some_and(|b| b.exceeded.is_some());
+            let on = match (enabled, over_budget) {
+                (false, _) => "off",
+                (true, true) => "cap",
+                (true, false) => "on",
+            };
             let up = cfg.upstreams.len().to_string();
             let health = if let Some(st) = snapshot.health_checks.get(cfg.name.as_str())
                 && !st.done
@@ -111,7 +119,11 @@ pub(super) fn render_configs_page(
                     .unwrap_or_else(|| "-".to_string())
             };
 
-            let mut style = Style::default().fg(if enabled { p.text } else { p.muted });
+            let mut style = Style::default().fg(if enabled && !over_budget {
+                p.text
+            } else {
+                p.muted
+            });
             if global_override == Some(cfg.name.as_str()) {
                 style = style.fg(p.accent).add_modifier(Modifier::BOLD);
             }
@@ -231,6 +243,24 @@ pub(super) fn render_configs_page(
             Span::styled(routing, Style::default().fg(p.muted)),
         ]));
 
+        if let Some(b) = snapshot.budgets.get(cfg.name.as_str()) {
+            let mut spans = vec![
+                Span::styled("budget: ", Style::default().fg(p.muted)),
+                Span::styled(
+                    b.summary(),
+                    Style::default().fg(if b.exceeded.is_some() { p.warn } else { p.text }),
+                ),
+            ];
+            if let Some(limit) = b.exceeded.as_deref() {
+                spans.push(Span::raw("  "));
+                spans.push(Span::styled(
+                    format!("over {limit}, skipped by auto routing"),
+                    Style::default().fg(p.bad),
+                ));
+            }
+            lines.push(Line::from(spans));
+        }
+
         if let Some(st) = snapshot.health_checks.get(cfg.name.as_str()) {
             let status = if !st.done {
                 if st.cancel_requested {
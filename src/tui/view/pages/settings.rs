@@ -25,6 +25,25 @@ pub(super) fn render_settings_page(
 
     let mut lines = Vec::new();
 
+    if !ui.last_filter_problems.is_empty() {
+        let warn = Style::default().fg(p.warn).add_modifier(Modifier::BOLD);
+        lines.push(Line::from(vec![Span::styled(
+            crate::tui::i18n::pick(
+                ui.language,
+                "⚠ filter.json 有问题（用 `ch filter test` 检查）：",
+                "⚠ filter.json has problems (check with `ch filter test`):",
+            ),
+            warn,
+        )]));
+        for problem in &ui.last_filter_problems {
+            lines.push(Line::from(vec![Span::styled(
+                format!("  - {problem}"),
+                Style::default().fg(p.warn),
+            )]));
+        }
+        lines.push(Line::from(""));
+    }
+
     let lang_name = match ui.language {
         crate::tui::Language::Zh => "中文",
         crate::tui::Language::En => "English",
@@ -237,14 +256,15 @@ pub(super) fn render_settings_page(
             Span::styled("retry: ", Style::default().fg(p.muted)),
             Span::styled(
                 format!(
-                    "attempts={} backoff={}..{} jitter={} cooldown(cf_chal={}s cf_to={}s transport={}s)",
+                    "attempts={} backoff={}..{} jitter={} cooldown(cf_chal={}s cf_to={}s transport={}s key={}s)",
                     retry.max_attempts,
                     retry.backoff_ms,
                     retry.backoff_max_ms,
                     retry.jitter_ms,
                     retry.cloudflare_challenge_cooldown_secs,
                     retry.cloudflare_timeout_cooldown_secs,
-                    retry.transport_cooldown_secs
+                    retry.transport_cooldown_secs,
+                    retry.key_cooldown_secs
                 ),
                 Style::default().fg(p.muted),
             ),
@@ -5,31 +5,10 @@ use ratatui::widgets::{Block, Borders, Cell, Paragraph, Row, Sparkline, Table, W
 
 use crate::state::UsageBucket;
 use crate::tui::ProviderOption;
-use crate::tui::model::{Palette, Snapshot, shorten, tokens_short};
+use crate::tui::model::{Palette, Snapshot, cache_hit_pct, shorten, tokens_short};
 use crate::tui::state::UiState;
 use crate::tui::types::StatsFocus;
 
-fn pricing_per_1k_usd() -> Option<(f64, f64)> {
-    let input = std::env::var("CODEX_HELPER_PRICE_INPUT_PER_1K_USD")
-        .ok()
-        .and_then(|s| s.trim().parse::<f64>().ok())?;
-    let output = std::env::var("CODEX_HELPER_PRICE_OUTPUT_PER_1K_USD")
-        .ok()
-        .and_then(|s| s.trim().parse::<f64>().ok())?;
-    if input.is_finite() && output.is_finite() && input >= 0.0 && output >= 0.0 {
-        Some((input, output))
-    } else {
-        None
-    }
-}
-
-fn estimate_cost_usd(bucket: &UsageBucket) -> Option<f64> {
-    let (input_price, output_price) = pricing_per_1k_usd()?;
-    let input = (bucket.usage.input_tokens.max(0) as f64) / 1000.0;
-    let output = (bucket.usage.output_tokens.max(0) as f64) / 1000.0;
-    Some(input * input_price + output * output_price)
-}
-
 fn fmt_pct(num: u64, den: u64) -> String {
     if den == 0 {
         return "-".to_string();
@@ -80,15 +59,13 @@ fn render_kpis(f: &mut Frame<'_>, p: Palette, snapshot: &Snapshot, area: Rect) {
     let err_pct = fmt_pct(s.requests_error, s.requests_total);
     let avg_ms = fmt_avg_ms(s.duration_ms_total, s.requests_total);
     let tokens = &s.usage;
-    let cost = estimate_cost_usd(s).map(|v| format!("${v:.2}"));
-    let cost_hint = if pricing_per_1k_usd().is_some() {
-        cost.unwrap_or_else(|| "-".to_string())
-    } else {
-        "(set CODEX_HELPER_PRICE_* env)".to_string()
+    let (cost, pricing_note) = match s.cost.display() {
+        Some(cost) => (cost, "per model ([pricing])"),
+        None => ("-".to_string(), "no priced requests; see [pricing]"),
     };
 
     let b1 = Block::default()
-        .title("Requests (since start)")
+        .title("Requests (all time)")
         .borders(Borders::ALL)
         .border_style(Style::default().fg(p.border));
     let t1 = Text::from(vec![
@@ -113,7 +90,7 @@ fn render_kpis(f: &mut Frame<'_>, p: Palette, snapshot: &Snapshot, area: Rect) {
     );
 
     let b2 = Block::default()
-        .title("Tokens (since start)")
+        .title("Tokens (all time)")
         .borders(Borders::ALL)
         .border_style(Style::default().fg(p.border));
     let t2 = Text::from(vec![
@@ -143,6 +120,20 @@ fn render_kpis(f: &mut Frame<'_>, p: Palette, snapshot: &Snapshot, area: Rect) {
                 Style::default().fg(p.text),
             ),
         ]),
+        Line::from(vec![
+            Span::styled("cch  ", Style::default().fg(p.muted)),
+            Span::styled(
+                format!(
+                    "{}/{}",
+                    tokens_short(tokens.cached_input_tokens),
+                    tokens_short(tokens.cache_write_tokens)
+                ),
+                Style::default().fg(p.text),
+            ),
+            Span::raw("   "),
+            Span::styled("hit  ", Style::default().fg(p.muted)),
+            Span::styled(cache_hit_pct(tokens), Style::default().fg(p.good)),
+        ]),
     ]);
     f.render_widget(
         Paragraph::new(t2).block(b2).wrap(Wrap { trim: true }),
@@ -155,8 +146,8 @@ fn render_kpis(f: &mut Frame<'_>, p: Palette, snapshot: &Snapshot, area: Rect) {
         .border_style(Style::default().fg(p.border));
     let t3 = Text::from(vec![
         Line::from(vec![
-            Span::styled("usd  ", Style::default().fg(p.muted)),
-            Span::styled(cost_hint, Style::default().fg(p.accent)),
+            Span::styled("total  ", Style::default().fg(p.muted)),
+            Span::styled(cost, Style::default().fg(p.accent)),
         ]),
         Line::from(vec![
             Span::styled(
@@ -164,7 +155,7 @@ fn render_kpis(f: &mut Frame<'_>, p: Palette, snapshot: &Snapshot, area: Rect) {
                 Style::default().fg(p.muted).add_modifier(Modifier::DIM),
             ),
             Span::styled(
-                "global per-1k (env)",
+                pricing_note,
                 Style::default().fg(p.muted).add_modifier(Modifier::DIM),
             ),
         ]),
@@ -242,22 +233,22 @@ fn render_bucket_table_stateful(
         Cell::from(Span::styled("err%", Style::default().fg(p.muted))),
         Cell::from(Span::styled("tok", Style::default().fg(p.muted))),
         Cell::from(Span::styled("avg", Style::default().fg(p.muted))),
-        Cell::from(Span::styled("usd", Style::default().fg(p.muted))),
+        Cell::from(Span::styled("hit%", Style::default().fg(p.muted))),
+        Cell::from(Span::styled("cost", Style::default().fg(p.muted))),
     ])
     .style(Style::default().add_modifier(Modifier::BOLD));
 
     let rows = items
         .iter()
         .map(|(name, b)| {
-            let cost = estimate_cost_usd(b)
-                .map(|v| format!("{v:.2}"))
-                .unwrap_or_else(|| "-".to_string());
+            let cost = b.cost.display().unwrap_or_else(|| "-".to_string());
             Row::new(vec![
                 Cell::from(name.clone()),
                 Cell::from(b.requests_total.to_string()),
                 Cell::from(fmt_pct(b.requests_error, b.requests_total)),
                 Cell::from(tokens_short(b.usage.total_tokens)),
                 Cell::from(fmt_avg_ms(b.duration_ms_total, b.requests_total)),
+                Cell::from(cache_hit_pct(&b.usage)),
                 Cell::from(cost),
             ])
         })
@@ -266,12 +257,13 @@ fn render_bucket_table_stateful(
     let table = Table::new(
         rows,
         [
-            Constraint::Percentage(40),
+            Constraint::Percentage(35),
             Constraint::Length(6),
             Constraint::Length(6),
             Constraint::Length(7),
             Constraint::Length(6),
-            Constraint::Length(8),
+            Constraint::Length(6),
+            Constraint::Length(10),
         ],
     )
     .header(header)
@@ -354,7 +346,7 @@ fn render_detail_panel(
     let inner = Layout::default()
         .direction(Direction::Vertical)
         .constraints([
-            Constraint::Length(7),
+            Constraint::Length(8),
             Constraint::Length(5),
             Constraint::Min(0),
         ])
@@ -362,9 +354,7 @@ fn render_detail_panel(
 
     let err_pct = fmt_pct(bucket.requests_error, bucket.requests_total);
     let avg_ms = fmt_avg_ms(bucket.duration_ms_total, bucket.requests_total);
-    let cost = estimate_cost_usd(bucket)
-        .map(|v| format!("${v:.2}"))
-        .unwrap_or_else(|| "-".to_string());
+    let cost = bucket.cost.display().unwrap_or_else(|| "-".to_string());
 
     let lines = vec![
         Line::from(vec![
@@ -397,7 +387,7 @@ fn render_detail_panel(
             Span::styled("avg  ", Style::default().fg(p.muted)),
             Span::styled(avg_ms, Style::default().fg(p.text)),
             Span::raw("   "),
-            Span::styled("usd  ", Style::default().fg(p.muted)),
+            Span::styled("cost  ", Style::default().fg(p.muted)),
             Span::styled(cost, Style::default().fg(p.muted)),
         ]),
         Line::from(vec![
@@ -412,8 +402,22 @@ fn render_detail_panel(
                 Style::default().fg(p.muted),
             ),
         ]),
+        Line::from(vec![
+            Span::styled("cache(rd/wr)  ", Style::default().fg(p.muted)),
+            Span::styled(
+                format!(
+                    "{}/{}",
+                    tokens_short(bucket.usage.cached_input_tokens),
+                    tokens_short(bucket.usage.cache_write_tokens),
+                ),
+                Style::default().fg(p.muted),
+            ),
+            Span::raw("   "),
+            Span::styled("hit  ", Style::default().fg(p.muted)),
+            Span::styled(cache_hit_pct(&bucket.usage), Style::default().fg(p.good)),
+        ]),
         Line::from(vec![Span::styled(
-            "pricing: set CODEX_HELPER_PRICE_* env for usd",
+            "cost: estimated from [[pricing.models]]",
             Style::default().fg(p.muted).add_modifier(Modifier::DIM),
         )]),
     ];
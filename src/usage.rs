@@ -11,6 +11,12 @@ pub struct UsageMetrics {
     pub reasoning_tokens: i64,
     #[serde(default)]
     pub total_tokens: i64,
+    /// Input tokens read from the prompt cache; part of `input_tokens`.
+    #[serde(default)]
+    pub cached_input_tokens: i64,
+    /// Input tokens written to the prompt cache (Anthropic); part of `input_tokens`.
+    #[serde(default)]
+    pub cache_write_tokens: i64,
 }
 
 impl UsageMetrics {
@@ -19,6 +25,18 @@ impl UsageMetrics {
         self.output_tokens = self.output_tokens.saturating_add(other.output_tokens);
         self.reasoning_tokens = self.reasoning_tokens.saturating_add(other.reasoning_tokens);
         self.total_tokens = self.total_tokens.saturating_add(other.total_tokens);
+        self.cached_input_tokens = self
+            .cached_input_tokens
+            .saturating_add(other.cached_input_tokens);
+        self.cache_write_tokens = self
+            .cache_write_tokens
+            .saturating_add(other.cache_write_tokens);
+    }
+
+    /// Share of input tokens served from the prompt cache, or `None` without input tokens.
+    pub fn cache_hit_ratio(&self) -> Option<f64> {
+        (self.input_tokens > 0)
+            .then(|| self.cached_input_tokens.max(0) as f64 / self.input_tokens as f64)
     }
 }
 
@@ -31,34 +49,66 @@ fn to_i64(v: &Value) -> i64 {
 }
 
 fn extract_usage_obj(payload: &Value) -> Option<&Value> {
-    if let Some(u) = payload.get("usage") {
+    // Chat Completions 流式分片在最后一片之前会带 `"usage": null`，需要跳过。
+    if let Some(u) = payload.get("usage").filter(|u| u.is_object()) {
         return Some(u);
     }
     if let Some(resp) = payload.get("response")
-        && let Some(u) = resp.get("usage")
+        && let Some(u) = resp.get("usage").filter(|u| u.is_object())
     {
         return Some(u);
     }
     None
 }
 
+/// First present key among `keys` (Responses vs Chat Completions naming).
+fn get_any<'a>(obj: &'a Value, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter()
+        .find_map(|k| obj.get(*k).filter(|v| !v.is_null()))
+}
+
 fn usage_from_value(usage_obj: &Value) -> UsageMetrics {
     let mut m = UsageMetrics::default();
 
-    if let Some(v) = usage_obj.get("input_tokens") {
+    if let Some(v) = get_any(usage_obj, &["input_tokens", "prompt_tokens"]) {
         m.input_tokens = to_i64(v);
     }
-    if let Some(v) = usage_obj.get("output_tokens") {
+    if let Some(v) = get_any(usage_obj, &["output_tokens", "completion_tokens"]) {
         m.output_tokens = to_i64(v);
     }
-    if let Some(v) = usage_obj.get("total_tokens") {
+    if let Some(v) = get_any(
+        usage_obj,
+        &["input_tokens_details", "prompt_tokens_details"],
+    )
+    .and_then(|d| d.get("cached_tokens"))
+    {
+        m.cached_input_tokens = to_i64(v);
+    }
+    // Anthropic reports cache reads/writes next to `input_tokens` instead of inside it; fold them
+    // in so `input_tokens` always means the whole prompt.
+    let cache_read = usage_obj
+        .get("cache_read_input_tokens")
+        .map(to_i64)
+        .unwrap_or(0);
+    let cache_write = usage_obj
+        .get("cache_creation_input_tokens")
+        .map(to_i64)
+        .unwrap_or(0);
+    if cache_read > 0 || cache_write > 0 {
+        m.cached_input_tokens = cache_read;
+        m.cache_write_tokens = cache_write;
+        m.input_tokens = m.input_tokens + cache_read + cache_write;
+    }
+    if let Some(v) = usage_obj.get("total_tokens").filter(|v| !v.is_null()) {
         m.total_tokens = to_i64(v);
     } else {
         m.total_tokens = m.input_tokens + m.output_tokens;
     }
-    if let Some(details) = usage_obj
-        .get("output_tokens_details")
-        .and_then(|v| v.as_object())
+    if let Some(details) = get_any(
+        usage_obj,
+        &["output_tokens_details", "completion_tokens_details"],
+    )
+    .and_then(|v| v.as_object())
         && let Some(v) = details.get("reasoning_tokens")
     {
         m.reasoning_tokens = to_i64(v);
@@ -66,6 +116,67 @@ fn usage_from_value(usage_obj: &Value) -> UsageMetrics {
     m
 }
 
+/// Anthropic `message_delta` usage carries the running output count and, on newer API
+/// versions, the final input/cache figures; merge it over what `message_start` reported.
+fn merge_anthropic_delta(prev: Option<UsageMetrics>, delta_obj: &Value) -> UsageMetrics {
+    let delta = usage_from_value(delta_obj);
+    let mut m = prev.unwrap_or_default();
+    if delta_obj.get("output_tokens").is_some() {
+        m.output_tokens = delta.output_tokens;
+    }
+    let has_input = [
+        "input_tokens",
+        "cache_read_input_tokens",
+        "cache_creation_input_tokens",
+    ]
+    .iter()
+    .any(|k| delta_obj.get(*k).map(to_i64).unwrap_or(0) > 0);
+    if has_input {
+        m.input_tokens = delta.input_tokens;
+        m.cached_input_tokens = delta.cached_input_tokens;
+        m.cache_write_tokens = delta.cache_write_tokens;
+    }
+    m.total_tokens = m.input_tokens + m.output_tokens;
+    m
+}
+
+/// Fold one SSE `data:` payload into `last`.
+///
+/// - Responses API: `response.completed` carries the full `response.usage`.
+/// - Chat Completions: the final chunk carries `usage` (with `stream_options.include_usage`).
+/// - Anthropic: `message_start` reports input/cache tokens, `message_delta` the output tokens.
+///
+/// Returns true when `last` now holds the final usage of the response; Anthropic's
+/// `message_start` only yields a partial figure.
+fn apply_sse_payload(json: &Value, last: &mut Option<UsageMetrics>) -> bool {
+    match json.get("type").and_then(|t| t.as_str()) {
+        Some("message_start") => {
+            if let Some(u) = json
+                .get("message")
+                .and_then(|m| m.get("usage"))
+                .filter(|u| u.is_object())
+            {
+                *last = Some(usage_from_value(u));
+            }
+            false
+        }
+        Some("message_delta") => match json.get("usage").filter(|u| u.is_object()) {
+            Some(u) => {
+                *last = Some(merge_anthropic_delta(last.take(), u));
+                true
+            }
+            None => false,
+        },
+        _ => match extract_usage_obj(json) {
+            Some(usage_obj) => {
+                *last = Some(usage_from_value(usage_obj));
+                true
+            }
+            None => false,
+        },
+    }
+}
+
 pub fn extract_usage_from_bytes(data: &[u8]) -> Option<UsageMetrics> {
     let text = std::str::from_utf8(data).ok()?.trim();
     if text.is_empty() {
@@ -93,10 +204,8 @@ pub fn extract_usage_from_sse_bytes(data: &[u8]) -> Option<UsageMetrics> {
                 if payload_str.is_empty() {
                     continue;
                 }
-                if let Ok(json) = serde_json::from_str::<Value>(payload_str)
-                    && let Some(usage_obj) = extract_usage_obj(&json)
-                {
-                    last = Some(usage_from_value(usage_obj));
+                if let Ok(json) = serde_json::from_str::<Value>(payload_str) {
+                    apply_sse_payload(&json, &mut last);
                 }
             }
         }
@@ -105,18 +214,23 @@ pub fn extract_usage_from_sse_bytes(data: &[u8]) -> Option<UsageMetrics> {
     last
 }
 
-/// Incrementally scan SSE bytes for `data: {json}` lines that contain usage information.
+/// Incrementally scan SSE bytes for `data: {json}` lines that contain usage information
+/// (Responses, Chat Completions or Anthropic Messages streams).
 ///
 /// This is designed for streaming scenarios where the response arrives in many chunks:
 /// it avoids repeatedly re-parsing the entire buffer (which can become O(n^2)).
 ///
 /// - `scan_pos` is an in/out cursor into `data` (byte index).
 /// - `last` stores the latest usage parsed so far (updated in-place).
+///
+/// Returns true if this call saw the response's final usage (as opposed to e.g. the partial
+/// figures of an Anthropic `message_start`).
 pub fn scan_usage_from_sse_bytes_incremental(
     data: &[u8],
     scan_pos: &mut usize,
     last: &mut Option<UsageMetrics>,
-) {
+) -> bool {
+    let mut complete = false;
     let mut i = (*scan_pos).min(data.len());
 
     while i < data.len() {
@@ -146,14 +260,13 @@ pub fn scan_usage_from_sse_bytes_incremental(
             continue;
         }
 
-        if let Ok(json) = serde_json::from_slice::<Value>(payload)
-            && let Some(usage_obj) = extract_usage_obj(&json)
-        {
-            *last = Some(usage_from_value(usage_obj));
+        if let Ok(json) = serde_json::from_slice::<Value>(payload) {
+            complete |= apply_sse_payload(&json, last);
         }
     }
 
     *scan_pos = i;
+    complete
 }
 
 #[cfg(test)]
@@ -201,7 +314,108 @@ mod tests {
                 output_tokens: 2,
                 reasoning_tokens: 0,
                 total_tokens: 3,
+                cached_input_tokens: 0,
+                cache_write_tokens: 0,
             })
         );
     }
+
+    #[test]
+    fn cached_tokens_from_openai_and_anthropic_usage() {
+        let openai = extract_usage_from_bytes(
+            br#"{"usage":{"input_tokens":1000,"input_tokens_details":{"cached_tokens":800},"output_tokens":50,"total_tokens":1050}}"#,
+        )
+        .unwrap();
+        assert_eq!(openai.input_tokens, 1000);
+        assert_eq!(openai.cached_input_tokens, 800);
+        assert_eq!(openai.cache_hit_ratio(), Some(0.8));
+
+        let anthropic = extract_usage_from_bytes(
+            br#"{"type":"message","usage":{"input_tokens":20,"cache_creation_input_tokens":30,"cache_read_input_tokens":150,"output_tokens":10}}"#,
+        )
+        .unwrap();
+        assert_eq!(anthropic.input_tokens, 200);
+        assert_eq!(anthropic.cached_input_tokens, 150);
+        assert_eq!(anthropic.cache_write_tokens, 30);
+        assert_eq!(anthropic.total_tokens, 210);
+        assert_eq!(anthropic.cache_hit_ratio(), Some(0.75));
+    }
+
+    #[test]
+    fn chat_completions_stream_usage_from_final_chunk() {
+        let sse = concat!(
+            "data: {\"id\":\"c1\",\"choices\":[{\"delta\":{\"content\":\"hi\"}}],\"usage\":null}\n",
+            "\n",
+            "data: {\"id\":\"c1\",\"choices\":[],\"usage\":{\"prompt_tokens\":120,\"completion_tokens\":30,\"total_tokens\":150,",
+            "\"prompt_tokens_details\":{\"cached_tokens\":100},\"completion_tokens_details\":{\"reasoning_tokens\":12}}}\n",
+            "\n",
+            "data: [DONE]\n",
+            "\n"
+        );
+        let expected = Some(UsageMetrics {
+            input_tokens: 120,
+            output_tokens: 30,
+            reasoning_tokens: 12,
+            total_tokens: 150,
+            cached_input_tokens: 100,
+            cache_write_tokens: 0,
+        });
+
+        assert_eq!(extract_usage_from_sse_bytes(sse.as_bytes()), expected);
+        let mut pos = 0usize;
+        let mut last = None;
+        scan_usage_from_sse_bytes_incremental(sse.as_bytes(), &mut pos, &mut last);
+        assert_eq!(last, expected);
+
+        // Chunks without the final usage leave nothing behind.
+        let no_usage = "data: {\"choices\":[{\"delta\":{}}],\"usage\":null}\n\n";
+        assert_eq!(extract_usage_from_sse_bytes(no_usage.as_bytes()), None);
+    }
+
+    #[test]
+    fn anthropic_stream_merges_message_start_and_delta() {
+        let sse = concat!(
+            "event: message_start\n",
+            "data: {\"type\":\"message_start\",\"message\":{\"id\":\"m1\",\"usage\":{\"input_tokens\":25,",
+            "\"cache_creation_input_tokens\":0,\"cache_read_input_tokens\":75,\"output_tokens\":1}}}\n",
+            "\n",
+            "event: content_block_delta\n",
+            "data: {\"type\":\"content_block_delta\",\"index\":0,\"delta\":{\"type\":\"text_delta\",\"text\":\"hi\"}}\n",
+            "\n",
+            "event: message_delta\n",
+            "data: {\"type\":\"message_delta\",\"delta\":{\"stop_reason\":\"end_turn\"},\"usage\":{\"output_tokens\":40}}\n",
+            "\n",
+            "event: message_stop\n",
+            "data: {\"type\":\"message_stop\"}\n",
+            "\n"
+        );
+        let expected = Some(UsageMetrics {
+            input_tokens: 100,
+            output_tokens: 40,
+            reasoning_tokens: 0,
+            total_tokens: 140,
+            cached_input_tokens: 75,
+            cache_write_tokens: 0,
+        });
+
+        assert_eq!(extract_usage_from_sse_bytes(sse.as_bytes()), expected);
+
+        // Feed it byte-chunked, the way the proxy sees it.
+        let mut buf = Vec::new();
+        let mut pos = 0usize;
+        let mut last = None;
+        let mut complete_at = None;
+        for (n, chunk) in sse.as_bytes().chunks(17).enumerate() {
+            buf.extend_from_slice(chunk);
+            if scan_usage_from_sse_bytes_incremental(&buf, &mut pos, &mut last) {
+                complete_at.get_or_insert(n);
+            }
+            if complete_at.is_none() && last.is_some() {
+                // Only message_start seen so far: partial usage, not reported as final.
+                assert_eq!(last.as_ref().map(|u| u.output_tokens), Some(1));
+            }
+        }
+        assert!(complete_at.is_some());
+        assert_eq!(last, expected);
+    }
 }
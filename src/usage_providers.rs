@@ -113,7 +113,7 @@ fn domain_matches(base_url: &str, domains: &[String]) -> bool {
     false
 }
 
-fn resolve_token(
+async fn resolve_token(
     provider: &UsageProviderConfig,
     upstreams: &[UpstreamRef],
     cfg: &ProxyConfig,
@@ -131,10 +131,10 @@ fn resolve_token(
         if let Some(service) = cfg.codex.configs.get(&uref.config_name)
             && let Some(up) = service.upstreams.get(uref.index)
         {
-            if let Some(token) = up.auth.resolve_auth_token() {
+            if let Some(token) = up.auth.resolve_auth_token().await {
                 return Some(token);
             }
-            if let Some(token) = up.auth.resolve_api_key() {
+            if let Some(token) = up.auth.resolve_api_key().await {
                 return Some(token);
             }
         }
@@ -329,7 +329,7 @@ pub async fn poll_for_codex_upstream(
 
         let c = client.get_or_insert_with(Client::new);
 
-        if let Some(token) = resolve_token(&provider, &upstreams, &cfg) {
+        if let Some(token) = resolve_token(&provider, &upstreams, &cfg).await {
             match provider.kind {
                 ProviderKind::BudgetHttpJson => {
                     match poll_budget_http_json(c, &provider.endpoint, &token).await {